@@ -45,6 +45,42 @@ fn octree_insertion<I: IntoIterator<Item = (Vector3<f64>, i32)>>(
     octree
 }
 
+fn linear_insertion<I: IntoIterator<Item = (Vector3<f64>, i32)>>(
+    vecs: I,
+) -> LinearOctree<i32, u64> {
+    let mut octree = LinearOctree::<_, u64>::new();
+    let space = LeveledRegion(0);
+    for (v, i) in vecs {
+        octree.insert(space.discretize(v).unwrap(), i);
+    }
+    octree
+}
+
+fn linear_from_sorted<I: IntoIterator<Item = (Vector3<f64>, i32)>>(
+    vecs: I,
+) -> LinearOctree<i32, u64> {
+    let space = LeveledRegion(0);
+    let mut items: Vec<(u64, i32)> = vecs
+        .into_iter()
+        .map(|(v, i)| (space.discretize(v).unwrap(), i))
+        .collect();
+    items.sort_by_key(|&(m, _)| m);
+    LinearOctree::from_sorted(items)
+}
+
+fn linear_from_points(points: Vec<Vector3<f64>>) -> LinearOctree<i32, u64> {
+    let space = LeveledRegion(0);
+    LinearOctree::from_sorted(space.discretize_sorted(points.into_iter().map(|v| (v, 0)).collect()))
+}
+
+#[cfg(feature = "rayon")]
+fn linear_from_points_parallel(points: Vec<Vector3<f64>>) -> LinearOctree<i32, u64> {
+    LinearOctree::from_points_parallel(
+        LeveledRegion(0),
+        points.into_iter().map(|v| (v, 0)).collect(),
+    )
+}
+
 fn random_points(num: usize) -> Vec<Vector3<f64>> {
     let mut xrng = SmallRng::from_seed([1; 16]);
     let mut yrng = SmallRng::from_seed([4; 16]);
@@ -61,37 +97,63 @@ fn random_points(num: usize) -> Vec<Vector3<f64>> {
 }
 
 fn points(c: &mut Criterion) {
-    c.bench(
-        "octree",
-        ParameterizedBenchmark::new(
-            "insertion",
-            |b, &n| {
-                let points = random_points(n);
-                b.iter(move || octree_insertion(points.iter().cloned().map(|v| (v, 0))))
-            },
-            (10..39).map(|n| 1.5f64.powi(n) as usize),
-        )
-        .with_function("iteration", |b, &n| {
+    let bench = ParameterizedBenchmark::new(
+        "insertion",
+        |b, &n| {
             let points = random_points(n);
-            let octree = octree_insertion(points.iter().cloned().map(|v| (v, 0)));
-            b.iter(move || octree.iter().count())
+            b.iter(move || octree_insertion(points.iter().cloned().map(|v| (v, 0))))
+        },
+        (10..39).map(|n| 1.5f64.powi(n) as usize),
+    )
+    .with_function("linear_insertion", |b, &n| {
+        let points = random_points(n);
+        b.iter(move || linear_insertion(points.iter().cloned().map(|v| (v, 0))))
+    })
+    .with_function("linear_from_sorted", |b, &n| {
+        let points = random_points(n);
+        b.iter(move || linear_from_sorted(points.iter().cloned().map(|v| (v, 0))))
+    })
+    .with_function("iteration", |b, &n| {
+        let points = random_points(n);
+        let octree = octree_insertion(points.iter().cloned().map(|v| (v, 0)));
+        b.iter(move || octree.iter().count())
+    })
+    .with_function("full_fold", |b, &n| {
+        let points = random_points(n);
+        let octree = octree_insertion(points.iter().cloned().map(|v| (v, 0)));
+        b.iter(move || {
+            octree
+                .iter_fold(
+                    PositiveX,
+                    MortonRegionCache::with_hasher(1, MortonBuildHasher::default()),
+                )
+                .count()
         })
-        .with_function("full_fold", |b, &n| {
-            let points = random_points(n);
-            let octree = octree_insertion(points.iter().cloned().map(|v| (v, 0)));
-            b.iter(move || {
-                octree
-                    .iter_fold(
-                        PositiveX,
-                        MortonRegionCache::with_hasher(1, MortonBuildHasher::default()),
-                    )
-                    .count()
-            })
+    })
+    .with_function("cached_fold_after_insert", |b, &n| {
+        let points = random_points(n);
+        let mut octree = octree_insertion(points.iter().cloned().map(|v| (v, 0)));
+        let mut cache = FoldCache::new();
+        octree.fold_cached(&mut cache, &PositiveX);
+        let changed = LeveledRegion(0).discretize(points[0]).unwrap();
+        b.iter(move || {
+            octree.insert(changed, 1);
+            *octree.fold_cached(&mut cache, &PositiveX).unwrap()
         })
-        .sample_size(5)
-        .warm_up_time(std::time::Duration::from_millis(1000))
-        .measurement_time(std::time::Duration::from_millis(5000)),
-    );
+    })
+    .with_function("linear_from_points", |b, &n| {
+        let points = random_points(n);
+        b.iter(move || linear_from_points(points.clone()))
+    })
+    .sample_size(5)
+    .warm_up_time(std::time::Duration::from_millis(1000))
+    .measurement_time(std::time::Duration::from_millis(5000));
+    #[cfg(feature = "rayon")]
+    let bench = bench.with_function("linear_from_points_parallel", |b, &n| {
+        let points = random_points(n);
+        b.iter(move || linear_from_points_parallel(points.clone()))
+    });
+    c.bench("octree", bench);
 }
 
 criterion_group!(benches, points);
@@ -0,0 +1,224 @@
+//! This module contains helpers to work with 3 dimensional Hilbert indices, which order space like morton codes
+//! do, but without the jumps that a z-order curve makes at the edges of its cells.
+//!
+//! It is kept out of the crate root because its functions share names with the methods of `Morton`.
+//!
+//! A Hilbert index is stored in the same primitive as a morton code and has the same hierarchy: the first `level`
+//! octants of an index pick a cell at `level`, and every index in that cell shares them. This means `MortonRegion`
+//! and a `LinearOctree` can hold Hilbert indices to get Hilbert ordering. Only the order of the octants within a
+//! cell differs, so anything that decodes a code into space, like `LeveledRegion::undiscretize` or the spatial
+//! queries of `LinearOctree`, must be given morton codes, which `to_morton` converts back to.
+
+use crate::{LeveledRegion, Morton};
+use nalgebra::Vector3;
+use num_traits::{Float, FromPrimitive, ToPrimitive};
+
+/// Encode the three dimensions (x, y, z) into a Hilbert index. Only the low `dim_bits()` bits of each dimension are
+/// used.
+///
+/// ```
+/// use nalgebra::Vector3;
+///
+/// // The curve starts at the origin and every step moves to a face-adjacent cell.
+/// assert_eq!(space::hilbert::encode(Vector3::<u64>::new(0, 0, 0)), 0);
+/// let step = space::hilbert::decode(1u64);
+/// assert_eq!(step.x + step.y + step.z, 1);
+/// ```
+pub fn encode<M>(dims: Vector3<M>) -> M
+where
+    M: Morton,
+{
+    let bits = M::dim_bits();
+    let mask = (M::one() << bits) - M::one();
+    let mut x = [dims.x & mask, dims.y & mask, dims.z & mask];
+    let top = M::one() << (bits - 1);
+
+    // Undo the rotations and reflections of every level, from the top down.
+    let mut q = top;
+    while q > M::one() {
+        let p = q - M::one();
+        for i in 0..3 {
+            if x[i] & q != M::zero() {
+                x[0] = x[0] ^ p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] = x[0] ^ t;
+                x[i] = x[i] ^ t;
+            }
+        }
+        q = q >> 1;
+    }
+
+    // Gray encode.
+    for i in 1..3 {
+        x[i] = x[i] ^ x[i - 1];
+    }
+    let mut t = M::zero();
+    let mut q = top;
+    while q > M::one() {
+        if x[2] & q != M::zero() {
+            t = t ^ (q - M::one());
+        }
+        q = q >> 1;
+    }
+    for d in &mut x {
+        *d = *d ^ t;
+    }
+
+    // The first dimension holds the most significant bit of every octant.
+    M::encode(Vector3::new(x[2], x[1], x[0]))
+}
+
+/// Decode the Hilbert index into the three individual dimensions (x, y, z). This is the inverse of `encode`.
+///
+/// ```
+/// use nalgebra::Vector3;
+///
+/// let dims = Vector3::<u64>::new(5, 7, 9);
+/// assert_eq!(space::hilbert::decode(space::hilbert::encode(dims)), dims);
+/// ```
+pub fn decode<M>(index: M) -> Vector3<M>
+where
+    M: Morton,
+{
+    let bits = M::dim_bits();
+    let dims = (index & M::used_bits()).decode();
+    let mut x = [dims.z, dims.y, dims.x];
+    let end = M::one() << bits;
+
+    // Gray decode.
+    let t = x[2] >> 1;
+    for i in (1..3).rev() {
+        x[i] = x[i] ^ x[i - 1];
+    }
+    x[0] = x[0] ^ t;
+
+    // Redo the rotations and reflections of every level, from the bottom up.
+    let mut q = M::one() + M::one();
+    while q != end {
+        let p = q - M::one();
+        for i in (0..3).rev() {
+            if x[i] & q != M::zero() {
+                x[0] = x[0] ^ p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] = x[0] ^ t;
+                x[i] = x[i] ^ t;
+            }
+        }
+        q = q << 1;
+    }
+    Vector3::new(x[0], x[1], x[2])
+}
+
+/// Converts a morton code into the Hilbert index of the same leaf cell.
+///
+/// ```
+/// use space::Morton;
+/// use nalgebra::Vector3;
+///
+/// let morton = u64::encode(Vector3::new(3, 1, 4));
+/// assert_eq!(space::hilbert::to_morton(space::hilbert::from_morton(morton)), morton);
+/// ```
+pub fn from_morton<M>(morton: M) -> M
+where
+    M: Morton,
+{
+    encode(morton.decode())
+}
+
+/// Converts a Hilbert index into the morton code of the same leaf cell.
+pub fn to_morton<M>(index: M) -> M
+where
+    M: Morton,
+{
+    M::encode(decode(index))
+}
+
+/// Same as `LeveledRegion::discretize`, but gives back the Hilbert index of the leaf cell the point is in.
+///
+/// ```
+/// use space::LeveledRegion;
+/// use nalgebra::Vector3;
+///
+/// let region = LeveledRegion(0);
+/// let point = Vector3::new(0.5, -0.25, 0.75);
+/// let index: u64 = space::hilbert::discretize(region, point).unwrap();
+/// assert_eq!(space::hilbert::to_morton(index), region.discretize::<f64, u64>(point).unwrap());
+/// ```
+pub fn discretize<S, M>(region: LeveledRegion, point: Vector3<S>) -> Option<M>
+where
+    S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    M: Morton + std::fmt::Debug + 'static,
+{
+    region.discretize::<S, M>(point).map(from_morton)
+}
+
+/// Same as `LeveledRegion::undiscretize`, but takes the Hilbert index of the leaf cell.
+pub fn undiscretize<S, M>(region: LeveledRegion, index: M) -> Vector3<S>
+where
+    S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    M: Morton + std::fmt::Debug + 'static,
+{
+    region.undiscretize(to_morton(index))
+}
+
+#[test]
+fn test_round_trip() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([9; 16]);
+    for _ in 0..1000 {
+        let index = rng.gen::<u64>() & u64::used_bits();
+        assert_eq!(encode(decode(index)), index);
+        let dims =
+            Vector3::new(rng.gen::<u64>(), rng.gen::<u64>(), rng.gen::<u64>()).map(|d| d >> 43);
+        assert_eq!(decode(encode(dims)), dims);
+    }
+}
+
+#[test]
+fn test_steps_are_adjacent() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let step = |index: u64| {
+        let (a, b) = (decode(index), decode(index + 1));
+        (0..3)
+            .map(|i| {
+                if a[i] > b[i] {
+                    a[i] - b[i]
+                } else {
+                    b[i] - a[i]
+                }
+            })
+            .sum::<u64>()
+    };
+    for index in 0..4096 {
+        assert_eq!(step(index), 1);
+    }
+    let mut rng = SmallRng::from_seed([10; 16]);
+    for _ in 0..1000 {
+        let index = rng.gen::<u64>() & (u64::used_bits() >> 1);
+        assert_eq!(step(index), 1);
+    }
+}
+
+#[test]
+fn test_cells_share_prefix() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([11; 16]);
+    for level in 0..4 {
+        let low_bits = 3 * level;
+        let prefix = (rng.gen::<u64>() & u64::used_bits()) >> low_bits << low_bits;
+        // Every index under a prefix is in the same cell, which is also where its morton code is.
+        let cell = decode(prefix).map(|d| d >> level);
+        for index in prefix..prefix + (1 << low_bits) {
+            assert_eq!(decode(index).map(|d| d >> level), cell);
+            assert_eq!(to_morton(index) >> low_bits, to_morton(prefix) >> low_bits);
+        }
+    }
+}
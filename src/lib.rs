@@ -11,7 +11,9 @@
 #![feature(box_syntax, box_patterns)]
 #![deny(missing_docs)]
 
+pub mod hilbert;
 mod morton;
+pub mod morton2;
 mod octree;
 
 pub use self::morton::*;
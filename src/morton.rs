@@ -111,6 +111,410 @@ where
     })
 }
 
+/// Iterates over every morton code from `start` to `end` inclusive in z-order, which gives back nothing if
+/// `start > end`.
+///
+/// The z-order curve jumps around space, so this is mostly useful for visiting every slot between two codes. Use
+/// `morton_box_ranges` to find the codes in a box.
+///
+/// ```
+/// let codes: Vec<u64> = space::morton_range(6, 9).collect();
+/// assert_eq!(codes, vec![6, 7, 8, 9]);
+/// ```
+pub fn morton_range<M>(start: M, end: M) -> impl Iterator<Item = M>
+where
+    M: Morton,
+{
+    let first = if start <= end { Some(start) } else { None };
+    std::iter::successors(
+        first,
+        move |&m| if m < end { Some(m + M::one()) } else { None },
+    )
+}
+
+/// Splits the box of leaf cells from `min` to `max` inclusive on every axis into the fewest intervals of morton codes
+/// that cover exactly the cells in the box. The intervals are inclusive and are given back in z-order, so a sorted
+/// array of mortons can be searched for the box with one binary search per interval.
+///
+/// This gives back nothing if `min > max` on any axis.
+///
+/// ```
+/// use space::morton_box_ranges;
+/// use nalgebra::Vector3;
+///
+/// // The first 2x2x2 cells are one contiguous interval.
+/// let ranges: Vec<(u64, u64)> = morton_box_ranges(Vector3::new(0, 0, 0), Vector3::new(1, 1, 1));
+/// assert_eq!(ranges, vec![(0, 7)]);
+///
+/// // A row of 3 cells along x isn't, because the third cell is in the next 2x2x2 block.
+/// let ranges: Vec<(u64, u64)> = morton_box_ranges(Vector3::new(0, 0, 0), Vector3::new(2, 0, 0));
+/// assert_eq!(ranges, vec![(0, 1), (8, 8)]);
+/// ```
+pub fn morton_box_ranges<M>(min: Vector3<M>, max: Vector3<M>) -> Vec<(M, M)>
+where
+    M: Morton,
+{
+    let mut ranges: Vec<(M, M)> = Vec::new();
+    if (0..3).any(|i| min[i] > max[i]) {
+        return ranges;
+    }
+    let mut nodes = vec![MortonRegion::<M>::base()];
+    while let Some(region) = nodes.pop() {
+        let cut = M::dim_bits() - region.level;
+        let low = (region.morton >> (3 * cut)).decode().map(|n| n << cut);
+        let extent = (M::one() << cut) - M::one();
+        if (0..3).any(|i| low[i] > max[i] || low[i] + extent < min[i]) {
+            continue;
+        }
+        if (0..3).all(|i| min[i] <= low[i] && low[i] + extent <= max[i]) {
+            // Every region has its mortons in one interval, which is merged with the last one if they touch.
+            let start = (region.morton >> (3 * cut)) << (3 * cut);
+            let end = start | ((M::one() << (3 * cut)) - M::one());
+            match ranges.last_mut() {
+                Some(last) if last.1 + M::one() == start => last.1 = end,
+                _ => ranges.push((start, end)),
+            }
+        } else {
+            // The children are pushed in reverse so that they are popped in z-order.
+            for i in (0..8).rev() {
+                nodes.push(region.enter(i));
+            }
+        }
+    }
+    ranges
+}
+
+/// Renders the top `level` octants of `morton` as a path of octant digits from the root, separated by slashes, like
+/// `"3/1/7"`. A `level` of `0` gives back an empty path.
+///
+/// Each digit is the octant at that level, whose bits are `zyx`, so bit `0` is set for the positive half of x, bit
+/// `1` for y, and bit `2` for z.
+///
+/// Panics if `level` is larger than `dim_bits()`.
+///
+/// ```
+/// use space::{octant_path, Morton};
+/// use nalgebra::Vector3;
+///
+/// // The top bit of x and y is set and the top bit of z isn't, so the first octant is `0b011`.
+/// let morton = u64::encode(Vector3::new(1 << 20, 1 << 20, 0));
+/// assert_eq!(octant_path(morton, 3), "3/0/0");
+/// assert_eq!(octant_path(morton, 0), "");
+/// ```
+pub fn octant_path<M>(morton: M, level: u32) -> String
+where
+    M: Morton,
+{
+    assert!(
+        level as usize <= M::dim_bits(),
+        "space::octant_path(): level exceeds the number of levels in the morton"
+    );
+    (0..level as usize)
+        .map(|i| morton.get_level(i).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Parses a path made by `octant_path` back into a morton code, with every level below the end of the path set to
+/// `0`. The level of the path is the number of octants in it.
+///
+/// Gives back `None` if any octant isn't a digit from `0` to `7` or if the path has more than `dim_bits()` octants.
+///
+/// ```
+/// use space::{from_octant_path, octant_path};
+///
+/// let morton: u64 = from_octant_path("3/1/7").unwrap();
+/// assert_eq!(octant_path(morton, 3), "3/1/7");
+/// assert_eq!(from_octant_path::<u64>(""), Some(0));
+/// assert_eq!(from_octant_path::<u64>("3/8"), None);
+/// ```
+pub fn from_octant_path<M>(path: &str) -> Option<M>
+where
+    M: Morton,
+{
+    let mut morton = M::zero();
+    if path.is_empty() {
+        return Some(morton);
+    }
+    for (level, octant) in path.split('/').enumerate() {
+        if level >= M::dim_bits() || octant.len() != 1 {
+            return None;
+        }
+        match octant.parse::<usize>() {
+            Ok(octant) if octant < 8 => morton.set_level(level, octant),
+            _ => return None,
+        }
+    }
+    Some(morton)
+}
+
+/// The mask of every third bit, which the bits of one dimension occupy in a 3 dimensional morton code.
+pub(crate) const INTERLEAVE3: u64 = 0x1_249_249_249_249_249;
+/// The mask of every second bit, which the bits of one dimension occupy in a 2 dimensional morton code.
+pub(crate) const INTERLEAVE2: u64 = 0x5555_5555_5555_5555;
+
+/// Compares two morton codes in z-order, which is the order that `LinearOctree::from_sorted` expects and that its
+/// methods give items back in. Use this and `LeveledRegion::morton_key` to sort items yourself instead of relying on
+/// the ordering of the primitive.
+///
+/// A morton code stores the octant of the top level in its most significant bits, so z-order is the same as the
+/// numeric order for every `Morton` type. Hilbert indices from the `hilbert` module are ordered along their curve
+/// the same way.
+///
+/// ```
+/// use space::{morton_cmp, Morton};
+/// use nalgebra::Vector3;
+/// use std::cmp::Ordering;
+///
+/// // The top level octant of the second code is further along the z-order curve.
+/// let a = u64::encode(Vector3::new(0, 0, 5));
+/// let b = u64::encode(Vector3::new(1 << 20, 0, 0));
+/// assert_eq!(morton_cmp(a, b), Ordering::Less);
+/// ```
+pub fn morton_cmp<M>(a: M, b: M) -> std::cmp::Ordering
+where
+    M: Morton,
+{
+    a.cmp(&b)
+}
+
+/// Gets the deepest cell that contains both `a` and `b`, as its morton prefix and level, which is their lowest common
+/// ancestor in the implicit octree. Only the first `level` octants of the prefix are set. Two codes in different top
+/// level octants give back the root at level `0` and a code with itself gives back the leaf cell at `dim_bits()`.
+///
+/// ```
+/// use space::{common_ancestor, Morton};
+/// use nalgebra::Vector3;
+///
+/// // These are siblings, so they only differ in the last octant.
+/// let a = u64::encode(Vector3::new(2, 0, 0));
+/// let b = u64::encode(Vector3::new(3, 1, 0));
+/// assert_eq!(common_ancestor(a, b), (a, 20));
+/// assert_eq!(common_ancestor(a, a), (a, 21));
+/// ```
+pub fn common_ancestor<M>(a: M, b: M) -> (M, u32)
+where
+    M: Morton,
+{
+    let unused = M::BITS - 3 * M::dim_bits();
+    let differ = (a ^ b) & M::used_bits();
+    let level = (differ.leading_zeros() as usize - unused) / 3;
+    let cut = 3 * (M::dim_bits() - level);
+    (((a >> cut) << cut) & M::used_bits(), level as u32)
+}
+
+/// Encodes every `(x, y, z)` in `coords` into the morton at the same index of `out`, like `Morton::from_coords`.
+/// Only the low `dim_bits()` bits of each coordinate are used.
+///
+/// Panics if `coords` and `out` have different lengths.
+///
+/// ```
+/// use space::encode_batch;
+///
+/// let mut out = [0u64; 2];
+/// encode_batch(&[(1, 2, 3), (0, 0, 1)], &mut out);
+/// assert_eq!(out, [53, 4]);
+/// ```
+pub fn encode_batch<M>(coords: &[(u32, u32, u32)], out: &mut [M])
+where
+    M: Morton,
+{
+    assert_eq!(
+        coords.len(),
+        out.len(),
+        "space::encode_batch(): coords and out must have the same length"
+    );
+    for (&(x, y, z), morton) in coords.iter().zip(out.iter_mut()) {
+        *morton = M::from_coords(
+            M::from_u32(x).unwrap(),
+            M::from_u32(y).unwrap(),
+            M::from_u32(z).unwrap(),
+        );
+    }
+}
+
+/// The order of the bits of each octant in a morton code, from the most significant bit to the least.
+///
+/// Every morton code and `Octant` in this crate is `Zyx`, which is `OCTANT_ORDER`. This is only used to convert to
+/// and from codes made for other conventions, like the `Xyz` order that many GPU voxel engines use.
+///
+/// ```
+/// use space::{Morton, OctantOrder};
+/// use nalgebra::Vector3;
+///
+/// // Only x is in the upper half, so its bit is the highest bit of the top octant in `Xyz`.
+/// let dims = Vector3::<u64>::new(1 << 20, 0, 0);
+/// assert_eq!(OctantOrder::Zyx.encode(dims).get_level(0), 0b001);
+/// assert_eq!(OctantOrder::Xyz.encode(dims).get_level(0), 0b100);
+/// assert_eq!(OctantOrder::Xyz.decode(OctantOrder::Xyz.encode(dims)), dims);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OctantOrder {
+    /// Bit `0` is set for the positive half of x, bit `1` for y, and bit `2` for z.
+    Zyx,
+    /// Bit `0` is set for the positive half of z, bit `1` for y, and bit `2` for x.
+    Xyz,
+}
+
+/// The octant order used by every morton code and `Octant` in this crate.
+pub const OCTANT_ORDER: OctantOrder = OctantOrder::Zyx;
+
+impl Default for OctantOrder {
+    fn default() -> Self {
+        OCTANT_ORDER
+    }
+}
+
+impl OctantOrder {
+    /// Gets the octant in this order that has the positive half of each axis that is `true`.
+    pub fn octant(self, x: bool, y: bool, z: bool) -> u8 {
+        let (low, high) = match self {
+            OctantOrder::Zyx => (x, z),
+            OctantOrder::Xyz => (z, x),
+        };
+        low as u8 | (y as u8) << 1 | (high as u8) << 2
+    }
+
+    /// Converts an octant in `OCTANT_ORDER`, like the ones in this crate, into this order.
+    ///
+    /// Panics if `octant` is not in `[0, 8)`.
+    pub fn from_zyx(self, octant: u8) -> u8 {
+        assert!(
+            octant < 8,
+            "space::OctantOrder::from_zyx(): octant must be from 0 to 7"
+        );
+        self.octant(octant & 1 != 0, octant & 2 != 0, octant & 4 != 0)
+    }
+
+    /// Converts an octant in this order into `OCTANT_ORDER`. This is the inverse of `from_zyx`.
+    ///
+    /// Panics if `octant` is not in `[0, 8)`.
+    pub fn to_zyx(self, octant: u8) -> u8 {
+        // Both orders only swap x and z, so converting is its own inverse.
+        self.from_zyx(octant)
+    }
+
+    /// Encode the three dimensions (x, y, z) into a morton code with this octant order.
+    pub fn encode<M>(self, dims: Vector3<M>) -> M
+    where
+        M: Morton,
+    {
+        match self {
+            OctantOrder::Zyx => M::encode(dims),
+            OctantOrder::Xyz => M::encode(Vector3::new(dims.z, dims.y, dims.x)),
+        }
+    }
+
+    /// Decode a morton code with this octant order into the three dimensions (x, y, z).
+    pub fn decode<M>(self, morton: M) -> Vector3<M>
+    where
+        M: Morton,
+    {
+        let dims = morton.decode();
+        match self {
+            OctantOrder::Zyx => dims,
+            OctantOrder::Xyz => Vector3::new(dims.z, dims.y, dims.x),
+        }
+    }
+
+    /// Converts a morton code of this crate into one with this octant order for the same leaf cell.
+    pub fn from_morton<M>(self, morton: M) -> M
+    where
+        M: Morton,
+    {
+        self.encode(morton.decode())
+    }
+
+    /// Converts a morton code with this octant order into one of this crate for the same leaf cell.
+    pub fn to_morton<M>(self, morton: M) -> M
+    where
+        M: Morton,
+    {
+        M::encode(self.decode(morton))
+    }
+}
+
+/// Spreads the low bits of `value` out to the bits of `mask` shifted by `offset`.
+///
+/// This interleaves one dimension into a morton code, where `mask` is `INTERLEAVE3` or `INTERLEAVE2`
+/// and `offset` is the index of the dimension.
+///
+/// With the `simd` feature on x86-64, this checks at runtime if the CPU has BMI2 and uses its `pdep` instruction if
+/// it does. The check is only done on the first call. Otherwise `interleave_portable` is used.
+#[inline]
+pub(crate) fn interleave(value: u64, mask: u64, offset: u32) -> u64 {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        if bmi2::detected() {
+            // This is safe because the CPU was checked for BMI2.
+            return unsafe { bmi2::interleave(value, mask, offset) };
+        }
+    }
+    interleave_portable(value, mask, offset)
+}
+
+/// Packs the bits of `morton` under `mask` shifted by `offset` into the low bits, which reverses `interleave`.
+///
+/// This uses BMI2 with the `simd` feature the same way as `interleave`.
+#[inline]
+pub(crate) fn deinterleave(morton: u64, mask: u64, offset: u32) -> u64 {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        if bmi2::detected() {
+            // This is safe because the CPU was checked for BMI2.
+            return unsafe { bmi2::deinterleave(morton, mask, offset) };
+        }
+    }
+    deinterleave_portable(morton, mask, offset)
+}
+
+/// The portable version of `interleave`, which only uses `pdep` if the crate was compiled for a target with BMI2.
+#[inline]
+pub(crate) fn interleave_portable(value: u64, mask: u64, offset: u32) -> u64 {
+    value.pdep(mask << offset)
+}
+
+/// The portable version of `deinterleave`, which only uses `pext` if the crate was compiled for a target with BMI2.
+#[inline]
+pub(crate) fn deinterleave_portable(morton: u64, mask: u64, offset: u32) -> u64 {
+    morton.pext(mask << offset)
+}
+
+/// Versions of `interleave` and `deinterleave` that are compiled to use BMI2 for the `simd` feature.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod bmi2 {
+    use std::arch::x86_64::{_pdep_u64, _pext_u64};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Whether the CPU has BMI2: `0` if it wasn't checked yet, `1` if it doesn't and `2` if it does.
+    static HAS_BMI2: AtomicUsize = AtomicUsize::new(0);
+
+    /// Checks if the CPU supports BMI2. The CPU is only asked the first time and the answer is kept after that.
+    #[inline]
+    pub(super) fn detected() -> bool {
+        match HAS_BMI2.load(Ordering::Relaxed) {
+            0 => {
+                let has = is_x86_feature_detected!("bmi2");
+                HAS_BMI2.store(if has { 2 } else { 1 }, Ordering::Relaxed);
+                has
+            }
+            state => state == 2,
+        }
+    }
+
+    /// Only call this if the CPU supports BMI2.
+    #[target_feature(enable = "bmi2")]
+    pub(super) unsafe fn interleave(value: u64, mask: u64, offset: u32) -> u64 {
+        _pdep_u64(value, mask << offset)
+    }
+
+    /// Only call this if the CPU supports BMI2.
+    #[target_feature(enable = "bmi2")]
+    pub(super) unsafe fn deinterleave(morton: u64, mask: u64, offset: u32) -> u64 {
+        _pext_u64(morton, mask << offset)
+    }
+}
+
 /// Also known as a Z-order encoding, this partitions a bounded space into finite, but localized,
 /// linear boxes. This morton code is always encoding 3 dimensional data.
 pub trait Morton: PrimInt + FromPrimitive + ToPrimitive + Hash + std::fmt::Debug + 'static {
@@ -122,6 +526,33 @@ pub trait Morton: PrimInt + FromPrimitive + ToPrimitive + Hash + std::fmt::Debug
     /// Decode the morton code into the three individual dimensions (x, y, z).
     fn decode(self) -> Vector3<Self>;
 
+    /// Gets the integer grid coordinates `(x, y, z)` of the leaf cell this morton code addresses, which are each in
+    /// `[0, 2**dim_bits())`. This is the same as `decode`, but as a tuple.
+    ///
+    /// ```
+    /// use space::Morton;
+    ///
+    /// assert_eq!(53u64.coords(), (1, 2, 3));
+    /// ```
+    #[inline]
+    fn coords(self) -> (Self, Self, Self) {
+        let dims = self.decode();
+        (dims[0], dims[1], dims[2])
+    }
+
+    /// Builds the morton code of the leaf cell at the integer grid coordinates `(x, y, z)`, which is the inverse of
+    /// `coords`. Only the low `dim_bits()` bits of each coordinate are used.
+    ///
+    /// ```
+    /// use space::Morton;
+    ///
+    /// assert_eq!(u64::from_coords(1, 2, 3), 53);
+    /// ```
+    #[inline]
+    fn from_coords(x: Self, y: Self, z: Self) -> Self {
+        Self::encode(Vector3::new(x, y, z))
+    }
+
     /// The number of bits used to represent each dimension.
     #[inline]
     fn dim_bits() -> usize {
@@ -196,6 +627,121 @@ pub trait Morton: PrimInt + FromPrimitive + ToPrimitive + Hash + std::fmt::Debug
         *self = *self & !Self::level_mask(level)
     }
 
+    /// Gets the face-adjacent neighbor of this morton code by stepping one cell along `axis`, where `0` is x,
+    /// `1` is y, and `2` is z. The step is towards positive infinity if `positive` is set.
+    ///
+    /// Returns `None` if the neighbor would leave the representable grid.
+    ///
+    /// ```
+    /// use space::Morton;
+    /// use nalgebra::Vector3;
+    ///
+    /// let morton = u64::encode(Vector3::new(1, 2, 3));
+    /// assert_eq!(morton.neighbor(0, true), Some(u64::encode(Vector3::new(2, 2, 3))));
+    /// assert_eq!(morton.neighbor(2, false), Some(u64::encode(Vector3::new(1, 2, 2))));
+    /// assert_eq!(0u64.neighbor(1, false), None);
+    /// ```
+    #[inline]
+    fn neighbor(self, axis: u8, positive: bool) -> Option<Self> {
+        assert!(
+            axis < 3,
+            "Morton::neighbor: got invalid axis {} (max is 2)",
+            axis
+        );
+        let axis = axis as usize;
+        let mut dims = self.decode();
+        let max = (Self::one() << Self::dim_bits()) - Self::one();
+        if positive {
+            if dims[axis] == max {
+                return None;
+            }
+            dims[axis] = dims[axis] + Self::one();
+        } else {
+            if dims[axis] == Self::zero() {
+                return None;
+            }
+            dims[axis] = dims[axis] - Self::one();
+        }
+        Some(Self::encode(dims))
+    }
+
+    /// Iterates over the up to 26 cells around this morton code that share a face, edge, or corner with it.
+    /// Neighbors that would leave the representable grid are skipped.
+    ///
+    /// ```
+    /// use space::Morton;
+    /// use nalgebra::Vector3;
+    ///
+    /// assert_eq!(u64::encode(Vector3::new(1, 2, 3)).neighbors_26().count(), 26);
+    /// // A corner of the grid only has the 7 neighbors inside the grid.
+    /// assert_eq!(0u64.neighbors_26().count(), 7);
+    /// ```
+    #[inline]
+    fn neighbors_26(self) -> Neighbors26<Self> {
+        Neighbors26 {
+            dims: self.decode(),
+            offset: 0,
+        }
+    }
+
+    /// Treats this morton as a prefix code and gets the code of its parent by stripping the lowest octant.
+    ///
+    /// Prefix codes use the same layout as `MortonRegion::canonicalize`, so the region `MortonRegion { morton, level }`
+    /// of a `LinearOctree` has the prefix code `region.canonicalize()`. The root is `0`, and the unused bits sit above
+    /// the octants of every other code so that the depth is not lost. This returns `None` at the root.
+    ///
+    /// ```
+    /// use space::{Morton, MortonRegion};
+    ///
+    /// let code = 0u64.child(5).child(2);
+    /// assert_eq!(code, 0b1_101_010);
+    /// assert_eq!(code, MortonRegion::<u64>::base().enter(5).enter(2).canonicalize());
+    /// assert_eq!(code.parent(), Some(0b1_101));
+    /// assert_eq!(0b1_101u64.parent(), Some(0));
+    /// assert_eq!(0u64.parent(), None);
+    /// ```
+    #[inline]
+    fn parent(self) -> Option<Self> {
+        match self.prefix_level() {
+            0 => None,
+            1 => Some(Self::zero()),
+            _ => Some(self >> 3),
+        }
+    }
+
+    /// Treats this morton as a prefix code (see `parent`) and gets the code of the child in `octant`.
+    /// The passed octant must be in the range `[0, 8)` and the code must not already be at the deepest level.
+    #[inline]
+    fn child(self, octant: u8) -> Self {
+        if octant > 0b111 {
+            panic!("Morton::child: got invalid octant {} (max is 7)", octant);
+        }
+        let level = self.prefix_level();
+        if level >= Self::dim_bits() {
+            panic!(
+                "Morton::child: code is already at the deepest level {}",
+                Self::dim_bits()
+            );
+        }
+        let octant = Self::from_u8(octant).unwrap();
+        if level == 0 {
+            Self::unused_bits() >> (3 * (Self::dim_bits() - 1)) | octant
+        } else {
+            self << 3 | octant
+        }
+    }
+
+    /// Gets the level of this morton when it is treated as a prefix code (see `parent`), where the root `0` is
+    /// level `0`.
+    #[inline]
+    fn prefix_level(self) -> usize {
+        if self == Self::zero() {
+            0
+        } else {
+            (3 * Self::dim_bits()).saturating_sub(self.leading_zeros() as usize) / 3
+        }
+    }
+
     /// Because the upper bits are never set in the morton code, it is possible to create a unique morton code
     /// that doesn't represent an actual place in an octree which can be used as a null morton code.
     #[inline]
@@ -210,6 +756,53 @@ pub trait Morton: PrimInt + FromPrimitive + ToPrimitive + Hash + std::fmt::Debug
     }
 }
 
+/// An `Iterator` over the cells that share a face, edge, or corner with a morton code, in z-order of their offsets.
+///
+/// Produced by `Morton::neighbors_26`.
+pub struct Neighbors26<M> {
+    dims: Vector3<M>,
+    /// The index of the next offset in `[0, 27)`, where each axis has an offset of `-1`, `0`, or `1`.
+    offset: u8,
+}
+
+impl<M> Iterator for Neighbors26<M>
+where
+    M: Morton,
+{
+    type Item = M;
+
+    fn next(&mut self) -> Option<M> {
+        let max = (M::one() << M::dim_bits()) - M::one();
+        while self.offset < 27 {
+            let offset = self.offset;
+            self.offset += 1;
+            // The middle offset is the cell itself.
+            if offset == 13 {
+                continue;
+            }
+            let mut dims = self.dims;
+            let mut inside = true;
+            for (axis, &step) in [offset % 3, offset / 3 % 3, offset / 9].iter().enumerate() {
+                match step {
+                    0 if dims[axis] == M::zero() => inside = false,
+                    0 => dims[axis] = dims[axis] - M::one(),
+                    2 if dims[axis] == max => inside = false,
+                    2 => dims[axis] = dims[axis] + M::one(),
+                    _ => {}
+                }
+            }
+            if inside {
+                return Some(M::encode(dims));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(27 - self.offset as usize))
+    }
+}
+
 impl Morton for u64 {
     const BITS: usize = 64;
 
@@ -225,8 +818,9 @@ impl Morton for u64 {
     #[inline]
     fn encode(dims: Vector3<Self>) -> Self {
         let [x, y, z]: [Self; 3] = dims.into();
-        let bits = 0x1_249_249_249_249_249u64;
-        z.pdep(bits << 2) | y.pdep(bits << 1) | x.pdep(bits)
+        interleave(z, INTERLEAVE3, 2)
+            | interleave(y, INTERLEAVE3, 1)
+            | interleave(x, INTERLEAVE3, 0)
     }
 
     /// Decode a u64 morton to its associated Vector3<u64>
@@ -240,8 +834,11 @@ impl Morton for u64 {
     /// ```
     #[inline]
     fn decode(self) -> Vector3<Self> {
-        let bits = 0x1_249_249_249_249_249u64;
-        let (x, y, z) = (self.pext(bits), self.pext(bits << 1), self.pext(bits << 2));
+        let (x, y, z) = (
+            deinterleave(self, INTERLEAVE3, 0),
+            deinterleave(self, INTERLEAVE3, 1),
+            deinterleave(self, INTERLEAVE3, 2),
+        );
         Vector3::new(x & Self::used_bits(), y, z)
     }
 }
@@ -491,3 +1088,346 @@ fn test_write() {
     hash.write_u64(123);
     println!("hash={}", hash.finish());
 }
+
+#[test]
+fn test_coords_round_trip() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([9; 16]);
+    for _ in 0..1000 {
+        let m = rng.gen::<u64>() & u64::used_bits();
+        let (x, y, z) = m.coords();
+        assert_eq!(u64::from_coords(x, y, z), m);
+        assert!(x.max(y).max(z) < 1 << u64::dim_bits());
+
+        let m = rng.gen::<u128>() & u128::used_bits();
+        let (x, y, z) = m.coords();
+        assert_eq!(u128::from_coords(x, y, z), m);
+        assert!(x.max(y).max(z) < 1 << u128::dim_bits());
+    }
+    let last = (1 << u64::dim_bits()) - 1;
+    assert_eq!(u64::from_coords(last, last, last), u64::used_bits());
+}
+
+#[test]
+fn test_neighbor_round_trip() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([5; 16]);
+    for _ in 0..1000 {
+        let morton = rng.gen::<u64>() & u64::used_bits();
+        for axis in 0..3 {
+            if let Some(next) = morton.neighbor(axis, true) {
+                assert_eq!(next.neighbor(axis, false), Some(morton));
+            }
+            if let Some(prev) = morton.neighbor(axis, false) {
+                assert_eq!(prev.neighbor(axis, true), Some(morton));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_neighbors_26() {
+    let last = (1u64 << u64::dim_bits()) - 1;
+    let check = |dims: Vector3<u64>, count: usize| {
+        let center = u64::encode(dims);
+        let neighbors: Vec<u64> = center.neighbors_26().collect();
+        assert_eq!(neighbors.len(), count, "{:?}", dims);
+        for &n in &neighbors {
+            let other = n.decode();
+            let chebyshev = (0..3)
+                .map(|i| (other[i] as i64 - dims[i] as i64).abs())
+                .max()
+                .unwrap();
+            assert_eq!(chebyshev, 1);
+        }
+        let mut unique = neighbors.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), count);
+    };
+    // Interior, face, edge, and corner cells.
+    check(Vector3::new(5, 6, 7), 26);
+    check(Vector3::new(0, 6, 7), 17);
+    check(Vector3::new(last, 0, 7), 11);
+    check(Vector3::new(0, last, 0), 7);
+    check(Vector3::new(last, last, last), 7);
+}
+
+#[test]
+fn test_neighbor_boundaries() {
+    let max = (1 << u64::dim_bits()) - 1;
+    let corner = u64::encode(Vector3::new(max, max, max));
+    for axis in 0..3 {
+        assert_eq!(corner.neighbor(axis, true), None);
+        assert_eq!(0u64.neighbor(axis, false), None);
+        assert!(corner.neighbor(axis, false).is_some());
+        assert!(0u64.neighbor(axis, true).is_some());
+    }
+
+    let max = (1 << u128::dim_bits()) - 1;
+    let corner = u128::encode(Vector3::new(max, max, max));
+    assert_eq!(corner, u128::used_bits());
+    assert_eq!(corner.neighbor(0, true), None);
+    assert_eq!(
+        corner.neighbor(0, false).unwrap().neighbor(0, true),
+        Some(corner)
+    );
+}
+
+#[test]
+fn test_child_parent_round_trip() {
+    let mut codes = vec![0u64];
+    for _ in 0..3 {
+        codes = codes
+            .into_iter()
+            .flat_map(|code| (0..8).map(move |octant| code.child(octant)))
+            .collect();
+    }
+    for code in codes {
+        for octant in 0..8 {
+            assert_eq!(code.child(octant).parent(), Some(code));
+        }
+    }
+    assert_eq!(0u128.child(3).parent(), Some(0));
+    assert_eq!(0u128.parent(), None);
+}
+
+#[test]
+fn test_morton_range() {
+    assert_eq!(morton_range(3u64, 3).collect::<Vec<_>>(), vec![3]);
+    assert_eq!(morton_range(4u64, 3).count(), 0);
+    let last = u64::used_bits();
+    assert_eq!(
+        morton_range(last - 2, last).collect::<Vec<_>>(),
+        vec![last - 2, last - 1, last]
+    );
+    assert_eq!(morton_range(0u128, 7).count(), 8);
+}
+
+#[test]
+fn test_morton_box_ranges_brute_force() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([8; 16]);
+    for _ in 0..200 {
+        let mut min = Vector3::new(0u64, 0, 0);
+        let mut max = Vector3::new(0u64, 0, 0);
+        for i in 0..3 {
+            let a = rng.gen_range(0, 20);
+            let b = rng.gen_range(0, 20);
+            min[i] = a.min(b);
+            max[i] = a.max(b);
+        }
+        let mut cells: Vec<u64> = Vec::new();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    cells.push(u64::encode(Vector3::new(x, y, z)));
+                }
+            }
+        }
+        cells.sort();
+        let mut brute: Vec<(u64, u64)> = Vec::new();
+        for &m in &cells {
+            match brute.last_mut() {
+                Some(last) if last.1 + 1 == m => last.1 = m,
+                _ => brute.push((m, m)),
+            }
+        }
+        assert_eq!(morton_box_ranges(min, max), brute);
+    }
+
+    assert_eq!(
+        morton_box_ranges(Vector3::new(0u64, 0, 0), u64::used_bits().decode()),
+        vec![(0, u64::used_bits())]
+    );
+    assert!(morton_box_ranges(Vector3::new(1u64, 0, 0), Vector3::new(0, 5, 5)).is_empty());
+}
+
+#[test]
+fn test_child_matches_region_enter() {
+    fn check<M: Morton>() {
+        let mut region = MortonRegion::<M>::base();
+        let mut code = M::zero();
+        for (i, octant) in [3, 0, 7, 7, 1]
+            .iter()
+            .cycle()
+            .take(M::dim_bits())
+            .enumerate()
+        {
+            region = region.enter(*octant as usize);
+            code = code.child(*octant);
+            assert!(region.canonicalize() == code, "level {}", i + 1);
+        }
+        while let Some(parent) = code.parent() {
+            region.exit();
+            code = parent;
+            assert!(region.canonicalize() == code);
+        }
+        assert_eq!(region.level, 0);
+    }
+
+    check::<u64>();
+    check::<u128>();
+}
+
+#[test]
+fn test_morton_cmp_matches_octants() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([8; 16]);
+    let octants = |m: u64| {
+        (0..u64::dim_bits())
+            .map(|l| m.get_level(l))
+            .collect::<Vec<_>>()
+    };
+    for _ in 0..1000 {
+        let a = rng.gen::<u64>() & u64::used_bits();
+        // Share a random prefix so that the codes often only differ in their lower levels.
+        let cut = 3 * rng.gen_range(0, u64::dim_bits());
+        let b = (a >> cut << cut) | (rng.gen::<u64>() & u64::used_bits()) >> (63 - cut);
+        assert_eq!(morton_cmp(a, b), octants(a).cmp(&octants(b)));
+    }
+}
+
+#[test]
+fn test_encode_batch() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([9; 16]);
+    let coords: Vec<(u32, u32, u32)> = (0..1000)
+        .map(|_| (rng.gen(), rng.gen(), rng.gen()))
+        .collect();
+    let mut out = vec![0u64; coords.len()];
+    encode_batch(&coords, &mut out);
+    let mut wide = vec![0u128; coords.len()];
+    encode_batch(&coords, &mut wide);
+    for (&(x, y, z), (&m, &w)) in coords.iter().zip(out.iter().zip(wide.iter())) {
+        assert_eq!(m, u64::from_coords(x as u64, y as u64, z as u64));
+        assert_eq!(w, u128::from_coords(x as u128, y as u128, z as u128));
+    }
+}
+
+#[test]
+#[cfg(feature = "simd")]
+fn test_interleave_matches_portable() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([10; 16]);
+    for _ in 0..1000 {
+        let value = rng.gen::<u64>();
+        let morton = rng.gen::<u64>();
+        for &(mask, offsets) in &[(INTERLEAVE3, 3), (INTERLEAVE2, 2)] {
+            for offset in 0..offsets {
+                assert_eq!(
+                    interleave(value, mask, offset),
+                    interleave_portable(value, mask, offset)
+                );
+                assert_eq!(
+                    deinterleave(morton, mask, offset),
+                    deinterleave_portable(morton, mask, offset)
+                );
+            }
+        }
+        let dims = Vector3::new(value, value >> 21, value >> 42);
+        assert_eq!(
+            u64::encode(dims).decode(),
+            dims.map(|d| d & ((1 << 21) - 1))
+        );
+    }
+}
+
+#[test]
+fn test_octant_path_round_trip() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([7; 16]);
+    for _ in 0..1000 {
+        let morton: u64 = rng.gen::<u64>() & u64::used_bits();
+        let level = rng.gen_range(0, u64::dim_bits() as u32 + 1);
+        let path = octant_path(morton, level);
+        assert_eq!(
+            path.split('/').filter(|s| !s.is_empty()).count(),
+            level as usize
+        );
+        let parsed: u64 = from_octant_path(&path).unwrap();
+        let cut = 3 * (u64::dim_bits() - level as usize);
+        assert_eq!(parsed, (morton >> cut) << cut);
+    }
+    assert_eq!(from_octant_path::<u64>("1//2"), None);
+    assert_eq!(from_octant_path::<u64>("12"), None);
+    assert_eq!(from_octant_path::<u64>(&vec!["1"; 22].join("/")), None);
+    assert_eq!(
+        from_octant_path::<u64>(&vec!["1"; 21].join("/")),
+        Some(u64::used_bits() / 7)
+    );
+}
+
+#[test]
+fn test_common_ancestor() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([14; 16]);
+    for _ in 0..1000 {
+        let a = rng.gen::<u64>() & u64::used_bits();
+        assert_eq!(common_ancestor(a, a), (a, u64::dim_bits() as u32));
+
+        // Every child of the parent of `a` shares it.
+        let parent = a & !7;
+        for octant in 0..8 {
+            assert_eq!(
+                common_ancestor(a, parent | octant),
+                if parent | octant == a {
+                    (a, u64::dim_bits() as u32)
+                } else {
+                    (parent, u64::dim_bits() as u32 - 1)
+                }
+            );
+        }
+
+        let b = rng.gen::<u64>() & u64::used_bits();
+        let (prefix, level) = common_ancestor(a, b);
+        assert_eq!(common_ancestor(b, a), (prefix, level));
+        let region = MortonRegion {
+            morton: prefix,
+            level: level as usize,
+        };
+        assert!(region.contains(a) && region.contains(b));
+        if (level as usize) < u64::dim_bits() {
+            assert_ne!(a.get_level(level as usize), b.get_level(level as usize));
+        }
+    }
+    let a = u128::encode(Vector3::new(1 << 41, 5, 0));
+    let b = u128::encode(Vector3::new(1 << 41, 4, 0));
+    assert_eq!(common_ancestor(a, b), (a & !7, 41));
+    assert_eq!(common_ancestor(0u128, a), (0, 0));
+}
+
+#[test]
+fn test_octant_orders() {
+    use crate::LeveledRegion;
+
+    // The point is only in the positive half of x and y.
+    let region = LeveledRegion(0);
+    let morton: u64 = region.discretize(Vector3::new(0.5, 0.5, -0.5)).unwrap();
+    for &(order, expected) in &[(OctantOrder::Zyx, 0b011), (OctantOrder::Xyz, 0b110)] {
+        assert_eq!(order.octant(true, true, false), expected);
+        assert_eq!(order.from_zyx(morton.get_level(0) as u8), expected);
+        assert_eq!(order.to_zyx(expected), morton.get_level(0) as u8);
+        let converted = order.from_morton(morton);
+        assert_eq!(converted.get_level(0), expected as usize);
+        assert_eq!(order.to_morton(converted), morton);
+        assert_eq!(order.decode(converted), morton.decode());
+    }
+    assert_eq!(OctantOrder::default(), OCTANT_ORDER);
+}
@@ -0,0 +1,193 @@
+//! This module contains helpers to work with 2 dimensional morton codes, which are useful for quadtrees and tiles.
+//!
+//! It is kept out of the crate root because `Morton2` shares method names with `Morton` and both are
+//! implemented for `u64`, so importing them into the same scope would make those calls ambiguous.
+
+use crate::morton::{deinterleave, interleave, INTERLEAVE2};
+use nalgebra::Vector2;
+use num_traits::{Float, FromPrimitive, PrimInt, ToPrimitive};
+use std::hash::{Hash, Hasher};
+
+/// Also known as a Z-order encoding, this partitions a bounded plane into finite, but localized,
+/// linear boxes. This morton code is always encoding 2 dimensional data.
+pub trait Morton2:
+    PrimInt + FromPrimitive + ToPrimitive + Hash + std::fmt::Debug + 'static
+{
+    /// This is the total number of bits in the primitive.
+    const BITS: usize;
+
+    /// Encode the two dimensions (x, y) into a morton code.
+    fn encode(dims: Vector2<Self>) -> Self;
+    /// Decode the morton code into the two individual dimensions (x, y).
+    fn decode(self) -> Vector2<Self>;
+
+    /// The number of bits used to represent each dimension.
+    #[inline]
+    fn dim_bits() -> usize {
+        Self::BITS / 2
+    }
+}
+
+impl Morton2 for u32 {
+    const BITS: usize = 32;
+
+    /// Encode a Vector2<u32> into a morton code.
+    ///
+    /// ```
+    /// use space::morton2::Morton2;
+    /// use nalgebra::Vector2;
+    ///
+    /// let morton_code = Morton2::encode(Vector2::<u32>::new(1, 2));
+    /// assert_eq!(morton_code, 9);
+    /// ```
+    #[inline]
+    #[allow(clippy::cast_lossless)]
+    fn encode(dims: Vector2<Self>) -> Self {
+        u64::encode(dims.map(|d| d as u64)) as u32
+    }
+
+    /// Decode a u32 morton to its associated Vector2<u32>
+    ///
+    /// ```
+    /// use space::morton2::Morton2;
+    /// use nalgebra::Vector2;
+    ///
+    /// let coordinates = Morton2::decode(9u32);
+    /// assert_eq!(coordinates, Vector2::<u32>::new(1, 2));
+    /// ```
+    #[inline]
+    #[allow(clippy::cast_lossless)]
+    fn decode(self) -> Vector2<Self> {
+        (self as u64).decode().map(|d| d as u32)
+    }
+}
+
+impl Morton2 for u64 {
+    const BITS: usize = 64;
+
+    /// Encode a Vector2<u64> into a morton code.
+    ///
+    /// ```
+    /// use space::morton2::Morton2;
+    /// use nalgebra::Vector2;
+    ///
+    /// let morton_code = Morton2::encode(Vector2::<u64>::new(1, 2));
+    /// assert_eq!(morton_code, 9);
+    /// ```
+    #[inline]
+    fn encode(dims: Vector2<Self>) -> Self {
+        interleave(dims.y, INTERLEAVE2, 1) | interleave(dims.x, INTERLEAVE2, 0)
+    }
+
+    /// Decode a u64 morton to its associated Vector2<u64>
+    ///
+    /// ```
+    /// use space::morton2::Morton2;
+    /// use nalgebra::Vector2;
+    ///
+    /// let coordinates = Morton2::decode(9u64);
+    /// assert_eq!(coordinates, Vector2::<u64>::new(1, 2));
+    /// ```
+    #[inline]
+    fn decode(self) -> Vector2<Self> {
+        Vector2::new(
+            deinterleave(self, INTERLEAVE2, 0),
+            deinterleave(self, INTERLEAVE2, 1),
+        )
+    }
+}
+
+/// This wraps a 2 dimensional morton to convey special external trait implementations to it.
+///
+/// This includes:
+/// - `Hash`
+/// - `From<Vector2<S>>`
+/// - `Into<Vector2<S>>`
+///
+/// ```
+/// use space::morton2::Morton2Wrapper;
+/// use nalgebra::Vector2;
+///
+/// let Morton2Wrapper(tile): Morton2Wrapper<u32> = Vector2::new(0.25f32, 0.75).into();
+/// assert_eq!(tile, 0b1011 << 28);
+/// let center: Vector2<f32> = Morton2Wrapper(tile).into();
+/// assert!((center - Vector2::new(0.25, 0.75)).norm() < 1e-4);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Morton2Wrapper<M>(pub M);
+
+impl<M> Default for Morton2Wrapper<M>
+where
+    M: Morton2,
+{
+    #[inline]
+    fn default() -> Self {
+        Morton2Wrapper(M::zero())
+    }
+}
+
+#[allow(clippy::derive_hash_xor_eq)]
+impl<M> Hash for Morton2Wrapper<M>
+where
+    M: Morton2,
+{
+    #[inline]
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: Hasher,
+    {
+        state.write_u64(self.0.to_u64().unwrap())
+    }
+}
+
+impl<S, M> From<Vector2<S>> for Morton2Wrapper<M>
+where
+    M: Morton2,
+    S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+{
+    #[inline]
+    fn from(point: Vector2<S>) -> Self {
+        let point = point.map(|d| {
+            M::from_u64(
+                (d * (S::one() + S::one()).powi(M::dim_bits() as i32))
+                    .to_u64()
+                    .unwrap(),
+            )
+            .unwrap()
+        });
+        Morton2Wrapper(M::encode(point))
+    }
+}
+
+impl<S, M> Into<Vector2<S>> for Morton2Wrapper<M>
+where
+    M: Morton2,
+    S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+{
+    #[inline]
+    fn into(self) -> Vector2<S> {
+        let point = self.0.decode();
+        let scale = (S::one() + S::one()).powi(-(M::dim_bits() as i32));
+
+        point.map(|d| {
+            (S::from_u64(d.to_u64().unwrap()).unwrap() + S::from_f32(0.5).unwrap()) * scale
+        })
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = SmallRng::from_seed([7; 16]);
+    for _ in 0..1000 {
+        let dims = Vector2::new(rng.gen::<u32>(), rng.gen::<u32>());
+        assert_eq!(
+            u64::encode(dims.map(u64::from)).decode(),
+            dims.map(u64::from)
+        );
+        let dims = dims.map(|d| d >> 16);
+        assert_eq!(u32::encode(dims).decode(), dims);
+    }
+}
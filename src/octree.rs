@@ -1,14 +1,21 @@
 //! Octree types and algorithms.
 
+mod builder;
+mod folders;
 mod linear;
 mod pointer;
 
-pub use self::linear::LinearOctree;
+pub use self::builder::OctreeBuilder;
+pub use self::folders::*;
+pub use self::linear::{FromBytesError, LinearExploreIter, LinearOctree, WeightedSampler};
+pub use self::pointer::Entry;
+pub use self::pointer::FoldCache;
 pub use self::pointer::PointerOctree;
 pub use self::pointer::ResizingPointerOctree;
+pub use self::pointer::InsertOutcome;
 
 use crate::morton::*;
-use nalgebra::Vector3;
+use nalgebra::{Point3, Vector3};
 use num_traits::{Float, FromPrimitive, ToPrimitive};
 
 use serde::{Deserialize, Serialize};
@@ -48,6 +55,109 @@ where
     }
 }
 
+/// Tells a `TryFolder` walk whether to go on with a sum or to stop with it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ControlFlow<B, C> {
+    /// Keep walking the tree with this sum.
+    Continue(C),
+    /// Stop walking the tree and give back this sum.
+    Break(B),
+}
+
+/// Same as `Folder`, but `gather` and `fold` can stop the tree walk early by giving back `ControlFlow::Break`,
+/// such as to stop searching once a region with a particular item is found.
+///
+/// Every `Folder` is also a `TryFolder` that never stops early.
+pub trait TryFolder<Item, M> {
+    /// This is the type that `gather` and `fold` will produce and acts as the accumulator.
+    type Sum;
+
+    /// `gather` converts a leaf node into the internal `Sum` type, or stops the walk.
+    fn gather<'a>(&self, morton: M, item: &'a Item) -> ControlFlow<Self::Sum, Self::Sum>;
+
+    /// `fold` is allowed to assume the `it` gives at least one item and no more than 8 items. It is only called once
+    /// none of the children stopped the walk.
+    fn fold<I>(&self, it: I) -> ControlFlow<Self::Sum, Self::Sum>
+    where
+        I: Iterator<Item = Self::Sum>;
+}
+
+impl<Item, M, F> TryFolder<Item, M> for F
+where
+    F: Folder<Item, M>,
+{
+    type Sum = F::Sum;
+
+    fn gather<'a>(&self, morton: M, item: &'a Item) -> ControlFlow<Self::Sum, Self::Sum> {
+        ControlFlow::Continue(Folder::gather(self, morton, item))
+    }
+
+    fn fold<I>(&self, it: I) -> ControlFlow<Self::Sum, Self::Sum>
+    where
+        I: Iterator<Item = Self::Sum>,
+    {
+        ControlFlow::Continue(Folder::fold(self, it))
+    }
+}
+
+/// Same as `Folder`, but `fold` is also told which node it is folding: the morton prefix and level of the node and
+/// its `(min, max)` corners in world space.
+///
+/// Every `Folder` is also a `SpatialFolder` that ignores the node.
+pub trait SpatialFolder<Item, M, S> {
+    /// This is the type that `gather` and `fold` will produce and acts as the accumulator.
+    type Sum;
+
+    /// `gather` converts a leaf node into the internal `Sum` type.
+    fn gather<'a>(&self, morton: M, item: &'a Item) -> Self::Sum;
+
+    /// `fold` is allowed to assume the `it` gives at least one item and no more than 8 items. Only the first `level`
+    /// octants of `prefix` are set.
+    fn fold<I>(&self, prefix: M, level: u32, bounds: (Vector3<S>, Vector3<S>), it: I) -> Self::Sum
+    where
+        I: Iterator<Item = Self::Sum>;
+}
+
+impl<Item, M, S, F> SpatialFolder<Item, M, S> for F
+where
+    F: Folder<Item, M>,
+{
+    type Sum = F::Sum;
+
+    fn gather<'a>(&self, morton: M, item: &'a Item) -> Self::Sum {
+        Folder::gather(self, morton, item)
+    }
+
+    fn fold<I>(&self, _: M, _: u32, _: (Vector3<S>, Vector3<S>), it: I) -> Self::Sum
+    where
+        I: Iterator<Item = Self::Sum>,
+    {
+        Folder::fold(self, it)
+    }
+}
+
+/// Tells a `Visitor` walk whether to look inside of a node or to skip everything in it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Descent {
+    /// Visit the children of the node, or its leaf if it only has one.
+    Descend,
+    /// Skip the node and everything in it.
+    Skip,
+}
+
+/// Implement this trait to walk an octree from the top down, deciding at every node whether to look inside of it.
+/// This is enough to implement culling, range queries, and custom aggregation.
+pub trait Visitor<Item, M, S> {
+    /// Called on every non-empty node before anything in it, with the first `level` octants of the node in
+    /// `prefix` and the rest of its bits set to `0`. `bounds` are the `(min, max)` corners of the node in world
+    /// space.
+    fn enter_node(&mut self, prefix: M, level: u32, bounds: (Vector3<S>, Vector3<S>)) -> Descent;
+
+    /// Called on every leaf in a node that was descended into. A node with only one leaf might be bigger than the
+    /// leaf cell, so the leaf isn't necessarily inside of every node that was entered on the way to it.
+    fn leaf(&mut self, morton: M, item: &Item);
+}
+
 macro_rules! tuple_folder {
     ({$($id: ident),* $(,)?}, {$($sm: ident),* $(,)?}, {$($acc: ident),* $(,)?}, {$($item: ident),* $(,)?}) => {
         #[allow(non_snake_case)]
@@ -138,13 +248,141 @@ impl<Item, M> Folder<Item, M> for NullFolder {
     }
 }
 
+/// Implement this trait for items that know their exact position so that queries like
+/// `LinearOctree::k_nearest_positioned` can measure distances to it instead of to the center of its leaf cell.
+///
+/// The position must be inside the leaf cell of the morton that the item is stored at, which is always true if the
+/// morton came from discretizing the position.
+pub trait Positioned<S> {
+    /// Gets the exact position of the item in world space.
+    fn position(&self) -> Vector3<S>;
+}
+
+impl<S> Positioned<S> for Vector3<S>
+where
+    S: nalgebra::base::Scalar,
+{
+    fn position(&self) -> Vector3<S> {
+        *self
+    }
+}
+
+/// The error given back by `LeveledRegion::discretize_checked` when a point is not in the region.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OutOfBounds<S> {
+    /// The first axis that the point was outside of the region on, where `0` is x, `1` is y, and `2` is z.
+    pub axis: usize,
+    /// The coordinate of the point on that axis.
+    pub value: S,
+    /// The bound of the region that the coordinate exceeded, which is either `-2**n` or `2**n`.
+    pub bound: S,
+}
+
+impl<S> std::fmt::Display for OutOfBounds<S>
+where
+    S: std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let axis = ["x", "y", "z"].get(self.axis).unwrap_or(&"?");
+        write!(
+            f,
+            "point is out of bounds on the {} axis: {} exceeds {}",
+            axis, self.value, self.bound
+        )
+    }
+}
+
+impl<S> std::error::Error for OutOfBounds<S> where S: std::fmt::Debug + std::fmt::Display {}
+
+/// The error given back by the `try_` methods, like `LeveledRegion::try_discretize`, which say why the methods they
+/// are named after give back `None`.
+///
+/// ```
+/// use space::{LeveledRegion, OutOfBounds, SpaceError};
+/// use nalgebra::Vector3;
+///
+/// let region = LeveledRegion(0);
+/// assert_eq!(
+///     region.try_discretize::<f64, u64>(Vector3::new(0.0, 2.0, 0.0)),
+///     Err(SpaceError::OutOfBounds(OutOfBounds { axis: 1, value: 2.0, bound: 1.0 }))
+/// );
+/// assert_eq!(
+///     region.try_discretize::<f64, u64>(Vector3::new(0.0, 0.0, std::f64::NAN)),
+///     Err(SpaceError::NonFinite { axis: 2 })
+/// );
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpaceError {
+    /// The point is finite, but outside of the region.
+    OutOfBounds(OutOfBounds<f64>),
+    /// The coordinate of the point on `axis` is NaN or infinite, where `0` is x, `1` is y, and `2` is z.
+    NonFinite {
+        /// The first axis with a coordinate that isn't finite.
+        axis: usize,
+    },
+    /// The region at `level` is too large or too small for the scalar or morton type.
+    LevelOverflow {
+        /// The level of the region.
+        level: i32,
+    },
+    /// A scalar couldn't be converted to or from `f64`.
+    ConversionFailed,
+    /// There were no points to work with.
+    Empty,
+}
+
+impl<S> From<OutOfBounds<S>> for SpaceError
+where
+    S: ToPrimitive,
+{
+    fn from(error: OutOfBounds<S>) -> Self {
+        SpaceError::OutOfBounds(OutOfBounds {
+            axis: error.axis,
+            value: error.value.to_f64().unwrap_or(std::f64::NAN),
+            bound: error.bound.to_f64().unwrap_or(std::f64::NAN),
+        })
+    }
+}
+
+impl std::fmt::Display for SpaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpaceError::OutOfBounds(error) => write!(f, "{}", error),
+            SpaceError::NonFinite { axis } => {
+                let axis = ["x", "y", "z"].get(*axis).unwrap_or(&"?");
+                write!(f, "point is not finite on the {} axis", axis)
+            }
+            SpaceError::LevelOverflow { level } => write!(
+                f,
+                "region at level {} is out of the range of the scalar or morton",
+                level
+            ),
+            SpaceError::ConversionFailed => write!(f, "scalar could not be converted"),
+            SpaceError::Empty => write!(f, "there are no points"),
+        }
+    }
+}
+
+impl std::error::Error for SpaceError {}
+
 /// This defines a region from [-2**n, 2**n).
+///
+/// `n` may be negative for a region smaller than the unit cube, which is useful for normalized data. Every method
+/// works the same way there, as long as the region `is_representable` with the scalar and morton types used.
+///
+/// ```
+/// let region = space::LeveledRegion(-1);
+/// assert_eq!(region.bounds::<f64>().1, nalgebra::Vector3::new(0.5, 0.5, 0.5));
+/// assert!(region.discretize::<f64, u64>(nalgebra::Vector3::new(0.25, -0.5, 0.0)).is_some());
+/// assert!(region.discretize::<f64, u64>(nalgebra::Vector3::new(0.5, 0.0, 0.0)).is_none());
+/// ```
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct LeveledRegion(pub i32);
 
 impl LeveledRegion {
     /// This allows the discretization of a `Vector3` `point` to a morton code using the region.
-    /// If the point is not in the region it gives back `None`.
+    /// If the point is not in the region `[-2**n, 2**n)` it gives back `None`, which includes any point with a NaN
+    /// or infinite coordinate.
     ///
     /// ```
     /// let region = space::LeveledRegion(0);
@@ -154,22 +392,619 @@ impl LeveledRegion {
     /// // This is outside the bounds, so it gives back `None`.
     /// let outside_bounds = nalgebra::Vector3::new(1.5, 1.5, 1.5);
     /// assert!(region.discretize::<f32, u64>(outside_bounds).is_none());
+    /// // The lower bound is in the region, but the upper bound is not.
+    /// assert!(region.discretize::<f32, u64>(nalgebra::Vector3::new(-1.0, -1.0, -1.0)).is_some());
+    /// assert!(region.discretize::<f32, u64>(nalgebra::Vector3::new(1.0, 0.0, 0.0)).is_none());
     /// ```
+    ///
+    /// If the region is not `is_representable` with `S` and `M`, this gives back `None` for every point. Use
+    /// `try_discretize` to find out why a point gave back `None`.
     pub fn discretize<S, M>(self, point: Vector3<S>) -> Option<M>
     where
         S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
         M: Morton + std::fmt::Debug + 'static,
     {
+        self.try_discretize(point).ok()
+    }
+
+    /// Same as `discretize`, but gives back a `SpaceError` instead of `None`. A coordinate that isn't finite is
+    /// `NonFinite`, a region that isn't `is_representable` is `LevelOverflow`, and a point outside of the region is
+    /// `OutOfBounds`.
+    pub fn try_discretize<S, M>(self, point: Vector3<S>) -> Result<M, SpaceError>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton + std::fmt::Debug + 'static,
+    {
+        if let Some(axis) = (0..3).find(|&i| !point[i].is_finite()) {
+            return Err(SpaceError::NonFinite { axis });
+        }
+        if !self.is_representable::<S, M>() {
+            return Err(SpaceError::LevelOverflow { level: self.0 });
+        }
+        self.discretize_checked(point).map_err(SpaceError::from)
+    }
+
+    /// Same as `discretize`, but takes a `Point3`.
+    ///
+    /// ```
+    /// use space::LeveledRegion;
+    /// use nalgebra::Point3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let point = Point3::new(0.5, -0.25, 0.75);
+    /// let morton: u64 = region.discretize_point(point).unwrap();
+    /// assert_eq!(Some(morton), region.discretize(point.coords));
+    /// assert!((region.undiscretize_point::<f64, _>(morton) - point).norm() < 1e-5);
+    /// ```
+    pub fn discretize_point<S, M>(self, point: Point3<S>) -> Option<M>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton + std::fmt::Debug + 'static,
+    {
+        self.discretize(point.coords)
+    }
+
+    /// Checks if both the width of the region and the width of its leaf cells for `M` are normal numbers in `S`.
+    /// If the width overflows to infinity or the leaf cells underflow, the region can't be discretized into, since
+    /// the mortons would come out wrong.
+    ///
+    /// ```
+    /// use space::LeveledRegion;
+    ///
+    /// assert!(LeveledRegion(40).is_representable::<f32, u64>());
+    /// assert!(!LeveledRegion(127).is_representable::<f32, u64>());
+    /// assert!(LeveledRegion(127).is_representable::<f64, u64>());
+    /// assert!(!LeveledRegion(-107).is_representable::<f32, u64>());
+    /// ```
+    pub fn is_representable<S, M>(self) -> bool
+    where
+        S: Float + std::fmt::Debug + 'static,
+        M: Morton,
+    {
+        let two = S::one() + S::one();
+        match (
+            self.0.checked_add(1),
+            self.0.checked_add(1 - M::dim_bits() as i32),
+        ) {
+            (Some(width), Some(leaf)) => two.powi(width).is_finite() && two.powi(leaf).is_normal(),
+            _ => false,
+        }
+    }
+
+    /// Same as `discretize`, but only the first `level` octants of the morton are kept and the rest are set to `0`.
+    /// This snaps the point to the corner of its cell at `level`, so every point in that cell gets the same morton.
+    ///
+    /// Panics if `level` is greater than `M::dim_bits()`.
+    ///
+    /// ```
+    /// use space::LeveledRegion;
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let a = region.discretize_to_level::<f32, u64>(Vector3::new(0.1, 0.1, 0.1), 2);
+    /// let b = region.discretize_to_level::<f32, u64>(Vector3::new(0.2, 0.4, 0.3), 2);
+    /// assert_eq!(a, b);
+    /// assert_eq!(region.discretize_to_level::<f32, u64>(Vector3::new(1.5, 0.0, 0.0), 2), None);
+    /// ```
+    pub fn discretize_to_level<S, M>(self, point: Vector3<S>, level: u32) -> Option<M>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton + std::fmt::Debug + 'static,
+    {
+        assert!(
+            level as usize <= M::dim_bits(),
+            "space::LeveledRegion::discretize_to_level(): level exceeds the number of levels in the morton"
+        );
+        let cut = 3 * (M::dim_bits() - level as usize);
+        self.discretize::<S, M>(point).map(|m| (m >> cut) << cut)
+    }
+
+    /// Same as `discretize`, but if the point is not in the region it gives back which axis it was outside of.
+    /// A coordinate that is NaN is treated as being outside of the region.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, OutOfBounds};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let error = region.discretize_checked::<f32, u64>(Vector3::new(0.5, -1.5, 0.5));
+    /// assert_eq!(error, Err(OutOfBounds { axis: 1, value: -1.5, bound: -1.0 }));
+    /// ```
+    ///
+    /// The region must be `is_representable` with `S` and `M`, or this panics. Use `try_discretize` to get back
+    /// `SpaceError::LevelOverflow` instead.
+    pub fn discretize_checked<S, M>(self, point: Vector3<S>) -> Result<M, OutOfBounds<S>>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton + std::fmt::Debug + 'static,
+    {
+        assert!(
+            self.is_representable::<S, M>(),
+            "space::LeveledRegion::discretize_checked(): the region is out of the range of the scalar"
+        );
         let bound = (S::one() + S::one()).powi(self.0);
-        if point.iter().any(|n| n.abs() > bound) {
-            None
+        if let Some(axis) = self.outside_axis(point) {
+            let value = point[axis];
+            Err(OutOfBounds {
+                axis,
+                value,
+                bound: if value < S::zero() { -bound } else { bound },
+            })
         } else {
+            // Rounding can carry a point just below the upper bound to `1` in normalized space, which is outside of
+            // the grid, so it is clamped to the largest value below `1`.
+            let below_one = S::one() - S::epsilon() / (S::one() + S::one());
             // Convert the point into normalized space.
-            let MortonWrapper(m) =
-                (point.map(|n| (n + bound) / (S::one() + S::one()).powi(self.0 + 1))).into();
-            Some(m)
+            let MortonWrapper(m) = (point
+                .map(|n| ((n + bound) / (S::one() + S::one()).powi(self.0 + 1)).min(below_one)))
+            .into();
+            Ok(m)
         }
     }
+
+    /// Checks if `point` is in the region `[-2**n, 2**n)` on every axis, which is exactly when `discretize` gives
+    /// back `Some`, without computing the morton code.
+    ///
+    /// ```
+    /// let region = space::LeveledRegion(0);
+    /// assert!(region.contains(nalgebra::Vector3::new(-1.0, 0.5, 0.5)));
+    /// assert!(!region.contains(nalgebra::Vector3::new(1.0, 0.5, 0.5)));
+    /// assert!(!region.contains(nalgebra::Vector3::new(std::f32::NAN, 0.5, 0.5)));
+    /// ```
+    pub fn contains<S>(self, point: Vector3<S>) -> bool
+    where
+        S: Float + std::fmt::Debug + 'static,
+    {
+        self.outside_axis(point).is_none()
+    }
+
+    /// Gets the `(min, max)` corners of the region in world space. The region includes `min`, but not `max`.
+    ///
+    /// ```
+    /// let (min, max) = space::LeveledRegion(1).bounds::<f32>();
+    /// assert_eq!(min, nalgebra::Vector3::new(-2.0, -2.0, -2.0));
+    /// assert_eq!(max, nalgebra::Vector3::new(2.0, 2.0, 2.0));
+    /// ```
+    pub fn bounds<S>(self) -> (Vector3<S>, Vector3<S>)
+    where
+        S: Float + std::fmt::Debug + 'static,
+    {
+        let bound = (S::one() + S::one()).powi(self.0);
+        (
+            Vector3::new(-bound, -bound, -bound),
+            Vector3::new(bound, bound, bound),
+        )
+    }
+
+    /// Gets the euclidean distance from `point` to the closest point of the region, which is `0` if `point` is
+    /// inside of it.
+    ///
+    /// ```
+    /// let region = space::LeveledRegion(0);
+    /// assert_eq!(region.distance_to(nalgebra::Vector3::new(0.5, 0.5, 0.5)), 0.0);
+    /// assert_eq!(region.distance_to(nalgebra::Vector3::new(4.0, 0.5, 0.5)), 3.0);
+    /// ```
+    pub fn distance_to<S>(self, point: Vector3<S>) -> S
+    where
+        S: Float + std::fmt::Debug + 'static,
+    {
+        let (min, max) = self.bounds();
+        box_distance2(point, min, max).sqrt()
+    }
+
+    /// Gives back the first axis that `point` is outside of the region on, if any.
+    fn outside_axis<S>(self, point: Vector3<S>) -> Option<usize>
+    where
+        S: Float + std::fmt::Debug + 'static,
+    {
+        let bound = (S::one() + S::one()).powi(self.0);
+        // The region is half-open, so a point exactly on the upper bound is outside of it.
+        (0..3).find(|&i| point[i].is_nan() || point[i] < -bound || point[i] >= bound)
+    }
+
+    /// Discretizes every point from `points` with `discretize`.
+    ///
+    /// ```
+    /// use space::LeveledRegion;
+    /// use nalgebra::Vector3;
+    ///
+    /// let points = vec![Vector3::new(0.5, 0.5, 0.5), Vector3::new(1.5, 0.5, 0.5)];
+    /// let mortons: Vec<Option<u64>> = LeveledRegion(0).discretize_iter(points).collect();
+    /// assert!(mortons[0].is_some());
+    /// assert!(mortons[1].is_none());
+    /// ```
+    pub fn discretize_iter<S, M, I>(self, points: I) -> impl Iterator<Item = Option<M>>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton + std::fmt::Debug + 'static,
+        I: IntoIterator<Item = Vector3<S>>,
+    {
+        points.into_iter().map(move |point| self.discretize(point))
+    }
+
+    /// Same as `discretize_iter`, but points that are not in the region are dropped.
+    ///
+    /// ```
+    /// use space::LeveledRegion;
+    /// use nalgebra::Vector3;
+    ///
+    /// let points = vec![Vector3::new(0.5, 0.5, 0.5), Vector3::new(1.5, 0.5, 0.5)];
+    /// assert_eq!(LeveledRegion(0).discretize_filter_map::<_, u64, _>(points).count(), 1);
+    /// ```
+    pub fn discretize_filter_map<S, M, I>(self, points: I) -> impl Iterator<Item = M>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton + std::fmt::Debug + 'static,
+        I: IntoIterator<Item = Vector3<S>>,
+    {
+        points
+            .into_iter()
+            .filter_map(move |point| self.discretize(point))
+    }
+
+    /// Gives back a key for sorting `(point, item)` pairs in z-order with `sort_by_key`, which is the morton of the
+    /// point or `None` if it is not in the region. Items that are not in the region sort first.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut items = vec![(Vector3::new(0.5, 0.5, 0.5), "a"), (Vector3::new(-0.5, -0.5, -0.5), "b")];
+    /// items.sort_by_key(region.morton_key::<_, u64, _>());
+    /// assert_eq!(items[0].1, "b");
+    ///
+    /// let sorted: Vec<(u64, _)> = items
+    ///     .into_iter()
+    ///     .filter_map(|(point, item)| region.discretize(point).map(|m| (m, item)))
+    ///     .collect();
+    /// let tree = LinearOctree::from_sorted(sorted);
+    /// assert_eq!(tree.len(), 2);
+    /// ```
+    pub fn morton_key<S, M, Item>(self) -> impl Fn(&(Vector3<S>, Item)) -> Option<M>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton + std::fmt::Debug + 'static,
+    {
+        move |&(point, _): &(Vector3<S>, Item)| self.discretize(point)
+    }
+
+    /// Discretizes the point of every item and sorts them by morton so that they can be given straight to
+    /// `LinearOctree::from_sorted`. Items whose point is not in the region are dropped.
+    ///
+    /// The sort is stable, so items that land in the same leaf cell stay in the order they were given in.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let items = vec![
+    ///     (Vector3::new(0.5, 0.5, 0.5), "a"),
+    ///     (Vector3::new(-0.5, -0.5, -0.5), "b"),
+    ///     (Vector3::new(1.5, 0.5, 0.5), "outside"),
+    /// ];
+    /// let sorted = region.discretize_sorted::<_, u64, _>(items);
+    /// assert_eq!(sorted.iter().map(|&(_, item)| item).collect::<Vec<_>>(), vec!["b", "a"]);
+    ///
+    /// let tree = LinearOctree::from_sorted(sorted);
+    /// assert!(tree.get(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap()).is_some());
+    /// ```
+    pub fn discretize_sorted<S, M, Item>(self, items: Vec<(Vector3<S>, Item)>) -> Vec<(M, Item)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton + std::fmt::Debug + 'static,
+    {
+        let mut sorted: Vec<(M, Item)> = items
+            .into_iter()
+            .filter_map(|(point, item)| self.discretize(point).map(|m| (m, item)))
+            .collect();
+        sorted.sort_by_key(|&(m, _)| m);
+        sorted
+    }
+
+    /// This is the inverse of `discretize`. It gives back the center of the leaf cell that `morton`
+    /// addresses in the region.
+    ///
+    /// ```
+    /// let region = space::LeveledRegion(0);
+    /// let point = nalgebra::Vector3::new(0.5, -0.25, 0.75);
+    /// let morton: u64 = region.discretize(point).unwrap();
+    /// let center: nalgebra::Vector3<f64> = region.undiscretize(morton);
+    /// assert!((center - point).norm() < 1e-5);
+    /// ```
+    pub fn undiscretize<S, M>(self, morton: M) -> Vector3<S>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton + std::fmt::Debug + 'static,
+    {
+        let bound = (S::one() + S::one()).powi(self.0);
+        // Convert the point out of normalized space.
+        let normalized: Vector3<S> = MortonWrapper(morton).into();
+        normalized.map(|n| n * (S::one() + S::one()).powi(self.0 + 1) - bound)
+    }
+
+    /// Same as `undiscretize`, but gives back a `Point3`.
+    pub fn undiscretize_point<S, M>(self, morton: M) -> Point3<S>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton + std::fmt::Debug + 'static,
+    {
+        Point3::from_coordinates(self.undiscretize(morton))
+    }
+
+    /// Gets the `(min, max)` corners in world space of the cell addressed by the first `level` octants of
+    /// `morton`. A `level` of `0` is the whole region and a `level` of `M::dim_bits()` is a single leaf cell.
+    ///
+    /// Panics if `level` is greater than `M::dim_bits()`.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let morton: u64 = region.discretize(Vector3::new(0.25, -0.75, 0.5)).unwrap();
+    /// let (min, max) = region.cell_bounds::<f32, _>(morton, 2);
+    /// assert_eq!(min, Vector3::new(0.0, -1.0, 0.5));
+    /// assert_eq!(max, Vector3::new(0.5, -0.5, 1.0));
+    /// ```
+    pub fn cell_bounds<S, M>(self, morton: M, level: u32) -> (Vector3<S>, Vector3<S>)
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton,
+    {
+        assert!(
+            level as usize <= M::dim_bits(),
+            "space::LeveledRegion::cell_bounds(): level exceeds the number of levels in the morton"
+        );
+        self.region_bounds(MortonRegion {
+            morton,
+            level: level as usize,
+        })
+    }
+
+    /// Gets the edge length in world space of a cell at `level`, which is `2^(region + 1 - level)`. A `level` of `0`
+    /// is the whole region. This is exact for every scalar type as long as the result is representable.
+    ///
+    /// ```
+    /// let region = space::LeveledRegion(2);
+    /// assert_eq!(region.cell_size::<f64>(0), 8.0);
+    /// assert_eq!(region.cell_size::<f32>(3), 1.0);
+    /// assert_eq!(region.cell_size::<f64>(21), 2.0f64.powi(-18));
+    /// ```
+    pub fn cell_size<S>(self, level: u32) -> S
+    where
+        S: Float + std::fmt::Debug + 'static,
+    {
+        (S::one() + S::one()).powi(self.0 + 1 - level as i32)
+    }
+
+    /// Gets the smallest level whose `cell_size` is at most `radius`. This is clamped to `M::dim_bits()`, the level of
+    /// a leaf cell, so a `radius` smaller than a leaf cell, or one that isn't positive, gives back that level.
+    ///
+    /// ```
+    /// use space::LeveledRegion;
+    ///
+    /// let region = LeveledRegion(0);
+    /// assert_eq!(region.level_for_radius::<f64, u64>(2.0), 0);
+    /// assert_eq!(region.level_for_radius::<f64, u64>(0.3), 3);
+    /// assert_eq!(region.level_for_radius::<f64, u64>(1e-30), 21);
+    /// ```
+    pub fn level_for_radius<S, M>(self, radius: S) -> u32
+    where
+        S: Float + std::fmt::Debug + 'static,
+        M: Morton,
+    {
+        let max_level = M::dim_bits() as u32;
+        (0..max_level)
+            .find(|&level| self.cell_size::<S>(level) <= radius)
+            .unwrap_or(max_level)
+    }
+
+    /// Gets the `(min, max)` corners of a `MortonRegion` in the world space of this region.
+    fn region_bounds<S, M>(self, region: MortonRegion<M>) -> (Vector3<S>, Vector3<S>)
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton,
+    {
+        let bound = (S::one() + S::one()).powi(self.0);
+        let width = self.cell_size::<S>(region.level as u32);
+        let cut = M::dim_bits() - region.level;
+        let cell = (region.morton >> (3 * cut)).decode();
+        let min = cell.map(|d| S::from_u64(d.to_u64().unwrap()).unwrap() * width - bound);
+        (min, min.map(|n| n + width))
+    }
+
+    /// Converts the closed box `[min, max]` into the inclusive range of leaf cell coordinates it touches on
+    /// each axis. Gives back `None` if the box is inverted or doesn't touch the region.
+    fn grid_range<S, M>(self, min: Vector3<S>, max: Vector3<S>) -> Option<[(M, M); 3]>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        M: Morton,
+    {
+        let bound = (S::one() + S::one()).powi(self.0);
+        // The number of leaf cells per unit of world space.
+        let scale = (S::one() + S::one()).powi(M::dim_bits() as i32 - self.0 - 1);
+        let last = (S::one() + S::one()).powi(M::dim_bits() as i32) - S::one();
+        let cell = |n: S| {
+            let n = ((n + bound) * scale).floor().max(S::zero()).min(last);
+            M::from_u64(n.to_u64().unwrap()).unwrap()
+        };
+        let mut range = [(M::zero(), M::zero()); 3];
+        for i in 0..3 {
+            // This is written so that NaN also gives back `None`.
+            if !(min[i] <= max[i] && max[i] >= -bound && min[i] < bound) {
+                return None;
+            }
+            range[i] = (cell(min[i]), cell(max[i]));
+        }
+        Some(range)
+    }
+}
+
+/// Checks if the cell of a `MortonRegion` overlaps the inclusive range of leaf cell coordinates on every axis.
+fn grid_overlaps<M>(region: MortonRegion<M>, range: &[(M, M); 3]) -> bool
+where
+    M: Morton,
+{
+    let cut = M::dim_bits() - region.level;
+    let low = (region.morton >> (3 * cut)).decode().map(|n| n << cut);
+    let extent = (M::one() << cut) - M::one();
+    (0..3).all(|i| low[i] <= range[i].1 && low[i] + extent >= range[i].0)
+}
+
+/// Checks if the cell of a `MortonRegion` is inside of the inclusive range of leaf cell coordinates on every axis.
+fn grid_contains<M>(region: MortonRegion<M>, range: &[(M, M); 3]) -> bool
+where
+    M: Morton,
+{
+    let cut = M::dim_bits() - region.level;
+    let low = (region.morton >> (3 * cut)).decode().map(|n| n << cut);
+    let extent = (M::one() << cut) - M::one();
+    (0..3).all(|i| low[i] >= range[i].0 && low[i] + extent <= range[i].1)
+}
+
+/// Squared euclidean distance between two points.
+fn distance2<S>(a: Vector3<S>, b: Vector3<S>) -> S
+where
+    S: Float + std::fmt::Debug + 'static,
+{
+    (0..3).fold(S::zero(), |acc, i| {
+        let delta = a[i] - b[i];
+        acc + delta * delta
+    })
+}
+
+/// Squared euclidean distance from a point to the box `[min, max]` (`0` if the point is inside).
+fn box_distance2<S>(point: Vector3<S>, min: Vector3<S>, max: Vector3<S>) -> S
+where
+    S: Float + std::fmt::Debug + 'static,
+{
+    (0..3).fold(S::zero(), |acc, i| {
+        let delta = if point[i] < min[i] {
+            min[i] - point[i]
+        } else if point[i] > max[i] {
+            point[i] - max[i]
+        } else {
+            S::zero()
+        };
+        acc + delta * delta
+    })
+}
+
+/// Squared euclidean distance from a point to the farthest corner of the box `[min, max]`.
+fn box_max_distance2<S>(point: Vector3<S>, min: Vector3<S>, max: Vector3<S>) -> S
+where
+    S: Float + std::fmt::Debug + 'static,
+{
+    (0..3).fold(S::zero(), |acc, i| {
+        let delta = (point[i] - min[i]).abs().max((max[i] - point[i]).abs());
+        acc + delta * delta
+    })
+}
+
+/// Same as `box_distance2`, but on an axis the point may also be shifted by `period` in either direction, whichever
+/// is closest. The point must be within `period` of the box on every axis, which is true if both are in the region.
+fn periodic_box_distance2<S>(point: Vector3<S>, min: Vector3<S>, max: Vector3<S>, period: S) -> S
+where
+    S: Float + std::fmt::Debug + 'static,
+{
+    (0..3).fold(S::zero(), |acc, i| {
+        let delta = [-period, S::zero(), period]
+            .iter()
+            .map(|&shift| {
+                let p = point[i] + shift;
+                if p < min[i] {
+                    min[i] - p
+                } else if p > max[i] {
+                    p - max[i]
+                } else {
+                    S::zero()
+                }
+            })
+            .fold(S::infinity(), S::min);
+        acc + delta * delta
+    })
+}
+
+/// Gives back the `(entry, exit)` range of `t >= 0` where the ray `origin + t * dir` is inside the closed box
+/// `[min, max]`, or `None` if the ray misses the box.
+fn ray_interval<S>(
+    origin: Vector3<S>,
+    dir: Vector3<S>,
+    min: Vector3<S>,
+    max: Vector3<S>,
+) -> Option<(S, S)>
+where
+    S: Float + std::fmt::Debug + 'static,
+{
+    let mut entry = S::zero();
+    let mut exit = S::infinity();
+    for i in 0..3 {
+        if dir[i] == S::zero() {
+            // The ray is parallel to this slab, so it is either always or never inside of it.
+            if origin[i] < min[i] || origin[i] > max[i] {
+                return None;
+            }
+        } else {
+            let a = (min[i] - origin[i]) / dir[i];
+            let b = (max[i] - origin[i]) / dir[i];
+            entry = entry.max(a.min(b));
+            exit = exit.min(a.max(b));
+        }
+    }
+    if entry <= exit {
+        Some((entry, exit))
+    } else {
+        None
+    }
+}
+
+/// One of the 8 octants of a region in zyx bit format, so bit `0` is set for the positive half of `x`, bit `1` for
+/// `y`, and bit `2` for `z`.
+///
+/// ```
+/// use space::Octant;
+///
+/// let octant = Octant::from(0b101);
+/// assert!(octant.x() && !octant.y() && octant.z());
+/// assert_eq!(u8::from(octant), 0b101);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Octant(u8);
+
+impl Octant {
+    /// Checks if the octant is in the positive half of the `x` axis.
+    pub fn x(self) -> bool {
+        self.0 & 0b001 != 0
+    }
+
+    /// Checks if the octant is in the positive half of the `y` axis.
+    pub fn y(self) -> bool {
+        self.0 & 0b010 != 0
+    }
+
+    /// Checks if the octant is in the positive half of the `z` axis.
+    pub fn z(self) -> bool {
+        self.0 & 0b100 != 0
+    }
+}
+
+impl From<u8> for Octant {
+    /// Panics if `octant` is greater than `7`.
+    fn from(octant: u8) -> Self {
+        assert!(
+            octant < 8,
+            "space::Octant::from(): octant must be in zyx format from 0 to 7"
+        );
+        Octant(octant)
+    }
+}
+
+impl From<Octant> for u8 {
+    fn from(octant: Octant) -> Self {
+        octant.0
+    }
 }
 
 /// Defines a ```LeveledRegion``` from [-2^n, 2^n) shifted so that it is centered at ```center```.
@@ -193,11 +1028,93 @@ impl<S> CenteredLeveledRegion<S>
 where
     S: Float + ToPrimitive + FromPrimitive + PartialOrd + std::fmt::Debug + Copy + 'static,
 {
+    /// Finds the smallest region centered on the bounding box of `points` that contains all of them, so that
+    /// `discretize` gives back `Some` for every point. If all of the points are the same, the level is `0`.
+    ///
+    /// Gives back `None` if `points` is empty or any coordinate isn't finite. Use `try_fit` to find out which.
+    ///
+    /// ```
+    /// use space::CenteredLeveledRegion;
+    /// use nalgebra::Vector3;
+    ///
+    /// let points = [Vector3::new(1.0f32, 2.0, 3.0), Vector3::new(4.0, -2.0, 3.5)];
+    /// let region = CenteredLeveledRegion::fit(&points).unwrap();
+    /// assert_eq!(region.center, Vector3::new(2.5, 0.0, 3.25));
+    /// assert_eq!(region.leveled_region.0, 2);
+    /// assert!(points.iter().all(|&p| region.discretize::<u64>(p).is_some()));
+    /// ```
+    pub fn fit(points: &[Vector3<S>]) -> Option<Self> {
+        Self::try_fit(points).ok()
+    }
+
+    /// Same as `fit`, but gives back `Empty` if there are no points and `NonFinite` if a coordinate isn't finite.
+    pub fn try_fit(points: &[Vector3<S>]) -> Result<Self, SpaceError> {
+        let first = points.first().ok_or(SpaceError::Empty)?;
+        let two = S::one() + S::one();
+        let mut min = *first;
+        let mut max = *first;
+        for point in points {
+            for i in 0..3 {
+                if !point[i].is_finite() {
+                    return Err(SpaceError::NonFinite { axis: i });
+                }
+                min[i] = min[i].min(point[i]);
+                max[i] = max[i].max(point[i]);
+            }
+        }
+        let center: Vector3<S> = Vector3::from_iterator((0..3).map(|i| (min[i] + max[i]) / two));
+        // This is computed the same way as in `discretize` so that rounding can't push a point out.
+        let extent = points.iter().fold(S::zero(), |extent, point| {
+            (0..3).fold(extent, |extent, i| extent.max((point[i] - center[i]).abs()))
+        });
+
+        let mut level = 0;
+        if extent > S::zero() {
+            level = extent
+                .to_f64()
+                .ok_or(SpaceError::ConversionFailed)?
+                .log2()
+                .floor() as i32
+                + 1;
+            while two.powi(level) <= extent {
+                level += 1;
+            }
+            while two.powi(level - 1) > extent {
+                level -= 1;
+            }
+        }
+        Ok(CenteredLeveledRegion {
+            leveled_region: LeveledRegion(level),
+            center,
+        })
+    }
+
     /// Return octant where old points should be placed upon resizing
     /// based upon the the position of the new point
-    pub fn expand_loc(&self, point: Vector3<S>) -> Option<u8> {
-        let radius: S = S::from(2.0.powi(self.leveled_region.0) as f64)
-            .expect("space::CenteredLeveledRegion::expand_loc: Unable to convert f64 to S");
+    ///
+    /// This never panics. The radius of the region is computed in `S`, so a level too large for `S` gives an
+    /// infinite radius that contains every finite point, and a NaN coordinate is treated as being inside.
+    ///
+    /// ```
+    /// use space::{CenteredLeveledRegion, LeveledRegion};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = CenteredLeveledRegion {
+    ///     leveled_region: LeveledRegion(0),
+    ///     center: Vector3::new(0.0, 0.0, 0.0),
+    /// };
+    /// // The new point is past the positive `x` bound, so the old region becomes the negative `x` half.
+    /// let octant = region.expand_loc(Vector3::new(1.5, 0.5, -0.5)).unwrap();
+    /// assert!(!octant.x() && !octant.y() && octant.z());
+    /// assert_eq!(region.expand_loc(Vector3::new(0.5, 0.5, 0.5)), None);
+    /// ```
+    pub fn expand_loc(&self, point: Vector3<S>) -> Option<Octant> {
+        self.expand_loc_u8(point).map(Octant)
+    }
+
+    /// Same as `expand_loc`, but gives back the octant as a `u8` in zyx bit format.
+    pub fn expand_loc_u8(&self, point: Vector3<S>) -> Option<u8> {
+        let radius: S = (S::one() + S::one()).powi(self.leveled_region.0);
         let lower_bound: Vector3<S> = self.center.map(|p| p - radius);
         let upper_bound: Vector3<S> = self.center.map(|p| p + radius);
 
@@ -242,26 +1159,826 @@ where
         self.leveled_region.discretize(point - self.center)
     }
 
+    /// Same as `discretize`, but gives back a `SpaceError` instead of `None` like `LeveledRegion::try_discretize`.
+    pub fn try_discretize<M>(self, point: Vector3<S>) -> Result<M, SpaceError>
+    where
+        M: Morton + std::fmt::Debug + 'static,
+        S: nalgebra::base::Scalar + alga::general::ClosedSub,
+    {
+        if let Some(axis) = (0..3).find(|&i| !point[i].is_finite()) {
+            return Err(SpaceError::NonFinite { axis });
+        }
+        self.leveled_region.try_discretize(point - self.center)
+    }
+
+    /// Gets the `(min, max)` corners of the region in world space, taking into account the shifted center of the
+    /// ```CenteredLeveledRegion```.
+    pub fn bounds(self) -> (Vector3<S>, Vector3<S>)
+    where
+        S: nalgebra::base::Scalar + alga::general::ClosedAdd,
+    {
+        let (min, max) = self.leveled_region.bounds::<S>();
+        (min + self.center, max + self.center)
+    }
+
+    /// Gets the euclidean distance from `point` to the closest point of the region, which is `0` if `point` is
+    /// inside of it, taking into account the shifted center of the ```CenteredLeveledRegion```.
+    pub fn distance_to(self, point: Vector3<S>) -> S
+    where
+        S: nalgebra::base::Scalar + alga::general::ClosedSub,
+    {
+        self.leveled_region.distance_to(point - self.center)
+    }
+
+    /// Checks if `point` is in the region, taking into account the shifted center of the
+    /// ```CenteredLeveledRegion```. This is exactly when `discretize` gives back `Some`.
+    pub fn contains(self, point: Vector3<S>) -> bool
+    where
+        S: nalgebra::base::Scalar + alga::general::ClosedSub,
+    {
+        self.leveled_region.contains(point - self.center)
+    }
+
+    /// Inverse of `discretize`, taking into account the shifted center of the ```CenteredLeveledRegion```.
+    pub fn undiscretize<M>(self, morton: M) -> Vector3<S>
+    where
+        M: Morton + std::fmt::Debug + 'static,
+        S: nalgebra::base::Scalar + alga::general::ClosedAdd,
+    {
+        self.leveled_region.undiscretize(morton) + self.center
+    }
+
     /// Expand the ```CenteredLeveledRegion``` by one "notch" (1 level of the ```LeveledRegion```)
     /// The octant represents the octant where the old points should be moved
     /// (as in the ```expand_loc``` function)
+    ///
+    /// This never panics. The center is moved by a radius computed in `S`, so if the level is too large for `S` the
+    /// center becomes infinite instead.
     pub fn expand(&mut self, octant: u8)
     where
         S: std::ops::AddAssign,
     {
         // Adjust center
+        let radius: S = (S::one() + S::one()).powi(self.leveled_region.0);
         let center_adjust: Vector3<S> = Vector3::from_iterator((0..3).map(|i| {
             // New octant is in the positive half, so the center is shifted left (negative)
             if octant & (1 << i) != 0 {
-                S::from_f64(-(2.0.powi(self.leveled_region.0)) as f64)
-                    .expect("space::CenteredLeveledRegion::expand: Unable to convert f64 to S")
+                -radius
             } else {
-                S::from_f64(2.0.powi(self.leveled_region.0) as f64)
-                    .expect("space::CenteredLeveledRegion::expand: Unable to convert f64 to S")
+                radius
             }
         }));
 
         self.center += center_adjust;
         self.leveled_region.0 += 1;
     }
+
+    /// Same as `expand`, but leaves the region as it is and gives back `LevelOverflow` if the level can't go any
+    /// higher or the center would stop being finite, or `NonFinite` if the center already isn't.
+    ///
+    /// ```
+    /// use space::{CenteredLeveledRegion, LeveledRegion, SpaceError};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut region = CenteredLeveledRegion {
+    ///     leveled_region: LeveledRegion(127),
+    ///     center: Vector3::new(0.0f32, 0.0, 0.0),
+    /// };
+    /// // Expanding from level `128` would move the center by `2^128`, which is too large for `f32`.
+    /// assert_eq!(region.try_expand(0), Ok(()));
+    /// assert_eq!(region.try_expand(0), Err(SpaceError::LevelOverflow { level: 128 }));
+    /// assert_eq!(region.leveled_region.0, 128);
+    /// ```
+    pub fn try_expand(&mut self, octant: u8) -> Result<(), SpaceError>
+    where
+        S: std::ops::AddAssign,
+    {
+        if let Some(axis) = (0..3).find(|&i| !self.center[i].is_finite()) {
+            return Err(SpaceError::NonFinite { axis });
+        }
+        let level = self.leveled_region.0;
+        if level.checked_add(1).is_none() {
+            return Err(SpaceError::LevelOverflow { level });
+        }
+        let mut expanded = *self;
+        expanded.expand(octant);
+        if (0..3).any(|i| !expanded.center[i].is_finite()) {
+            return Err(SpaceError::LevelOverflow { level });
+        }
+        *self = expanded;
+        Ok(())
+    }
+
+    /// Shrink the ```CenteredLeveledRegion``` by one "notch" (1 level of the ```LeveledRegion```), keeping only
+    /// the given octant. This is the exact inverse of ```expand``` with the same octant.
+    ///
+    /// Gives back `false` without changing anything if the smaller region can't be represented, either because
+    /// the level can't go any lower or because the half-extent is too small to move the center in `S`.
+    ///
+    /// ```
+    /// use space::{CenteredLeveledRegion, LeveledRegion};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut region = CenteredLeveledRegion {
+    ///     leveled_region: LeveledRegion(2),
+    ///     center: Vector3::new(0.0, 0.0, 0.0),
+    /// };
+    /// assert!(region.shrink(0b101));
+    /// assert_eq!(region.leveled_region.0, 1);
+    /// assert_eq!(region.center, Vector3::new(2.0, -2.0, 2.0));
+    /// ```
+    pub fn shrink(&mut self, octant: u8) -> bool {
+        let level = match self.leveled_region.0.checked_sub(1) {
+            Some(level) => level,
+            None => return false,
+        };
+        let half = (S::one() + S::one()).powi(level);
+        if half <= S::zero() {
+            return false;
+        }
+        // This is the same adjustment that `expand` would make from the smaller region.
+        let center_adjust: Vector3<S> =
+            Vector3::from_iterator((0..3).map(
+                |i| {
+                    if octant & (1 << i) != 0 {
+                        -half
+                    } else {
+                        half
+                    }
+                },
+            ));
+        let center: Vector3<S> =
+            Vector3::from_iterator((0..3).map(|i| self.center[i] - center_adjust[i]));
+        if (0..3)
+            .any(|i| center[i] == self.center[i] || center[i] + center_adjust[i] != self.center[i])
+        {
+            return false;
+        }
+
+        self.center = center;
+        self.leveled_region.0 = level;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_undiscretize_round_trip() {
+        let mut rng = SmallRng::from_seed([1; 16]);
+        let region = LeveledRegion(3);
+        let width = 2.0f64.powi(region.0 + 1) / 2.0f64.powi(u64::dim_bits() as i32);
+        for _ in 0..1000 {
+            let point = Vector3::new(
+                rng.gen_range(-8.0, 8.0),
+                rng.gen_range(-8.0, 8.0),
+                rng.gen_range(-8.0, 8.0),
+            );
+            let morton: u64 = region.discretize(point).unwrap();
+            let center: Vector3<f64> = region.undiscretize(morton);
+            assert!((0..3).all(|i| (center[i] - point[i]).abs() <= width));
+        }
+    }
+
+    #[test]
+    fn test_expand_shrink_inverse() {
+        let original = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(3),
+            center: Vector3::new(10.0f64, -4.0, 3.5),
+        };
+        for octant in 0..8 {
+            let mut region = original;
+            region.expand(octant);
+            assert!(region.shrink(octant));
+            assert_eq!(region.leveled_region.0, original.leveled_region.0);
+            assert_eq!(region.center, original.center);
+        }
+    }
+
+    #[test]
+    fn test_expand_high_level_exact() {
+        let mut region = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(40),
+            center: Vector3::new(0.5f64, -0.25, 3.0),
+        };
+        let radius = (1u64 << 40) as f64;
+        let point = Vector3::new(0.5 + radius, 0.0, 3.0 - radius);
+        assert!(region.contains(Vector3::new(0.5 - radius, -0.25, 3.0)));
+        assert_eq!(region.expand_loc(point).map(u8::from), Some(0b100));
+        region.expand(0b100);
+        assert_eq!(region.leveled_region.0, 41);
+        assert_eq!(
+            region.center,
+            Vector3::new(0.5 + radius, -0.25 + radius, 3.0 - radius)
+        );
+        assert!(region.contains(point));
+
+        // Small levels are exact too.
+        let mut region = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(-40),
+            center: Vector3::new(0.5f64, -0.25, 3.0),
+        };
+        region.expand(0b111);
+        assert_eq!(
+            region.center,
+            Vector3::new(0.5, -0.25, 3.0).map(|n| n - 1.0 / radius)
+        );
+    }
+
+    #[test]
+    fn test_discretize_extreme_levels() {
+        let point = Vector3::new(3.0e11f32, -1.0e12, 5.0e10);
+        let region = LeveledRegion(40);
+        let morton: u64 = region.discretize(point).unwrap();
+        let center: Vector3<f32> = region.undiscretize(morton);
+        let leaf = region.cell_size::<f32>(21);
+        assert!((0..3).all(|i| (center[i] - point[i]).abs() <= leaf));
+
+        for &level in &[127, 200, -107, -1000, std::i32::MAX, std::i32::MIN] {
+            let region = LeveledRegion(level);
+            assert_eq!(
+                region.discretize::<f32, u64>(Vector3::new(0.0, 0.0, 0.0)),
+                None
+            );
+        }
+        assert!(LeveledRegion(126).is_representable::<f32, u64>());
+        assert!(LeveledRegion(-106).is_representable::<f32, u64>());
+        assert!(LeveledRegion(-85).is_representable::<f32, u128>());
+        assert!(!LeveledRegion(-86).is_representable::<f32, u128>());
+        assert!(LeveledRegion(127)
+            .discretize::<f64, u64>(Vector3::new(1.0e38, 0.0, 0.0))
+            .is_some());
+    }
+
+    #[test]
+    fn test_negative_levels() {
+        let mut rng = SmallRng::from_seed([13; 16]);
+        for &level in &[-1, -4, -20] {
+            let region = LeveledRegion(level);
+            let (min, max) = region.bounds::<f64>();
+            let bound = 2.0f64.powi(level);
+            assert_eq!(max, Vector3::new(bound, bound, bound));
+            assert_eq!(min, -max);
+            assert_eq!(region.cell_size::<f64>(0), 2.0 * bound);
+            assert_eq!(region.cell_bounds::<f64, u64>(0, 0), (min, max));
+
+            // Every point in the region lands in a leaf cell of `cell_size` that holds it.
+            let leaf = region.cell_size::<f64>(u64::dim_bits() as u32);
+            for _ in 0..100 {
+                let point = Vector3::new(
+                    rng.gen_range(-bound, bound),
+                    rng.gen_range(-bound, bound),
+                    rng.gen_range(-bound, bound),
+                );
+                assert!(region.contains(point));
+                let morton: u64 = region.discretize(point).unwrap();
+                let (low, high) = region.cell_bounds::<f64, u64>(morton, u64::dim_bits() as u32);
+                assert_eq!(high - low, Vector3::new(leaf, leaf, leaf));
+                assert!((0..3).all(|i| low[i] <= point[i] && point[i] < high[i]));
+                let center: Vector3<f64> = region.undiscretize(morton);
+                assert!((0..3).all(|i| (center[i] - point[i]).abs() <= leaf));
+            }
+
+            assert!(region.discretize::<f64, u64>(min).is_some());
+            assert!(region
+                .discretize::<f64, u64>(Vector3::new(bound, 0.0, 0.0))
+                .is_none());
+            assert!(region
+                .discretize::<f64, u64>(Vector3::new(0.0, -bound * 1.5, 0.0))
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn test_try_variants() {
+        let region = LeveledRegion(0);
+        let point = Vector3::new(0.5, -0.25, 0.0);
+        assert_eq!(
+            region.try_discretize::<f64, u64>(point).ok(),
+            region.discretize::<f64, u64>(point)
+        );
+        assert_eq!(
+            region.try_discretize::<f64, u64>(Vector3::new(0.0, -3.0, 0.0)),
+            Err(SpaceError::OutOfBounds(OutOfBounds {
+                axis: 1,
+                value: -3.0,
+                bound: -1.0,
+            }))
+        );
+        assert_eq!(
+            region.try_discretize::<f32, u64>(Vector3::new(std::f32::INFINITY, 0.0, 0.0)),
+            Err(SpaceError::NonFinite { axis: 0 })
+        );
+        assert_eq!(
+            LeveledRegion(200).try_discretize::<f32, u64>(Vector3::new(0.0, 0.0, 0.0)),
+            Err(SpaceError::LevelOverflow { level: 200 })
+        );
+
+        let centered = CenteredLeveledRegion {
+            leveled_region: region,
+            center: Vector3::new(10.0, 0.0, 0.0),
+        };
+        assert_eq!(
+            centered
+                .try_discretize::<u64>(Vector3::new(10.5, 0.0, 0.0))
+                .ok(),
+            centered.discretize::<u64>(Vector3::new(10.5, 0.0, 0.0))
+        );
+        assert_eq!(
+            centered.try_discretize::<u64>(Vector3::new(0.0, std::f64::NAN, 0.0)),
+            Err(SpaceError::NonFinite { axis: 1 })
+        );
+
+        assert_eq!(
+            CenteredLeveledRegion::<f64>::try_fit(&[]).err(),
+            Some(SpaceError::Empty)
+        );
+        assert_eq!(
+            CenteredLeveledRegion::try_fit(&[Vector3::new(0.0, 0.0, std::f64::NAN)]).err(),
+            Some(SpaceError::NonFinite { axis: 2 })
+        );
+        let points = [Vector3::new(1.0, 0.0, 0.0), Vector3::new(-3.0, 0.5, 2.0)];
+        let fit = CenteredLeveledRegion::try_fit(&points).unwrap();
+        assert_eq!(
+            fit.leveled_region.0,
+            CenteredLeveledRegion::fit(&points)
+                .unwrap()
+                .leveled_region
+                .0
+        );
+
+        let mut expanding = centered;
+        assert_eq!(expanding.try_expand(0b001), Ok(()));
+        assert_eq!(expanding.leveled_region.0, 1);
+        let mut maxed = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(std::i32::MAX),
+            center: Vector3::new(0.0, 0.0, 0.0),
+        };
+        assert_eq!(
+            maxed.try_expand(0),
+            Err(SpaceError::LevelOverflow {
+                level: std::i32::MAX
+            })
+        );
+        assert_eq!(
+            SpaceError::NonFinite { axis: 1 }.to_string(),
+            "point is not finite on the y axis"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_discretize_checked_unrepresentable() {
+        let _ = LeveledRegion(127).discretize_checked::<f32, u64>(Vector3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_expand_past_scalar_range() {
+        // `2**200` can't be converted to `f32`, but the region still works without panicking.
+        let mut region = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(200),
+            center: Vector3::new(0.0f32, 0.0, 0.0),
+        };
+        assert_eq!(region.expand_loc(Vector3::new(1.0e38, -1.0e38, 0.0)), None);
+        assert_eq!(
+            region.expand_loc_u8(Vector3::new(std::f32::NAN, 0.0, 0.0)),
+            None
+        );
+        let before = region;
+        assert!(!region.shrink(0));
+        assert_eq!(region.center, before.center);
+        region.expand(0);
+        assert_eq!(region.leveled_region.0, 201);
+        assert!((0..3).all(|i| region.center[i].is_infinite()));
+
+        let mut region = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(127),
+            center: Vector3::new(0.0f32, 0.0, 0.0),
+        };
+        assert_eq!(region.expand_loc(Vector3::new(1.0e38, 0.0, 0.0)), None);
+        assert_eq!(
+            region
+                .expand_loc(Vector3::new(std::f32::MAX, 0.0, 0.0))
+                .map(u8::from),
+            Some(0)
+        );
+        region.expand(0);
+        assert_eq!(region.center[0], 2.0f32.powi(127));
+    }
+
+    #[test]
+    fn test_expand_loc_octant() {
+        let region = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(1),
+            center: Vector3::new(1.0f64, 1.0, 1.0),
+        };
+        for x in &[-2.0, 0.5, 4.0] {
+            for y in &[-2.0, 1.5, 4.0] {
+                for z in &[-2.0, 0.5, 4.0] {
+                    let point = Vector3::new(*x, *y, *z);
+                    let octant = region.expand_loc(point);
+                    assert_eq!(octant.map(u8::from), region.expand_loc_u8(point));
+                    if let Some(octant) = octant {
+                        // The old region is placed on the opposite side of the new point, or the side nearest to
+                        // the center if the point is in bounds on that axis.
+                        assert_eq!(octant.x(), *x < 1.0);
+                        assert_eq!(octant.y(), *y < 1.0);
+                        assert_eq!(octant.z(), *z < 1.0);
+                    } else {
+                        assert!(region.contains(point));
+                    }
+                }
+            }
+        }
+        for octant in 0..8 {
+            assert_eq!(u8::from(Octant::from(octant)), octant);
+        }
+    }
+
+    #[test]
+    fn test_shrink_unrepresentable() {
+        let original = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(0),
+            center: Vector3::new(1.0e10f32, 0.0, 0.0),
+        };
+        let mut region = original;
+        // Half of a unit can't move a center this large in `f32`.
+        assert!(!region.shrink(0));
+        assert_eq!(region.leveled_region.0, original.leveled_region.0);
+        assert_eq!(region.center, original.center);
+
+        let mut region = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(std::i32::MIN),
+            center: Vector3::new(0.0f64, 0.0, 0.0),
+        };
+        assert!(!region.shrink(0));
+    }
+
+    #[test]
+    fn test_fit_contains_points() {
+        let mut rng = SmallRng::from_seed([3; 16]);
+        for &scale in &[1.0e-3f32, 1.0, 7.5, 1.0e4] {
+            let offset = Vector3::new(rng.gen_range(-100.0, 100.0), 0.0, 5.0);
+            let points: Vec<Vector3<f32>> = (0..200)
+                .map(|_| {
+                    Vector3::new(
+                        rng.gen_range(-scale, scale),
+                        rng.gen_range(-scale, scale),
+                        rng.gen_range(-scale, scale),
+                    ) + offset
+                })
+                .collect();
+            let region = CenteredLeveledRegion::fit(&points).unwrap();
+            assert!(points
+                .iter()
+                .all(|&p| region.discretize::<u64>(p).is_some()));
+            // One level lower must leave out at least one point.
+            let smaller = CenteredLeveledRegion {
+                leveled_region: LeveledRegion(region.leveled_region.0 - 1),
+                center: region.center,
+            };
+            assert!(points
+                .iter()
+                .any(|&p| smaller.discretize::<u64>(p).is_none()));
+        }
+    }
+
+    #[test]
+    fn test_fit_degenerate() {
+        assert!(CenteredLeveledRegion::<f32>::fit(&[]).is_none());
+        let point = Vector3::new(1.0f64, 2.0, 3.0);
+        let region = CenteredLeveledRegion::fit(&[point, point]).unwrap();
+        assert_eq!(region.center, point);
+        assert!(region.discretize::<u64>(point).is_some());
+        assert!(
+            CenteredLeveledRegion::fit(&[point, Vector3::new(std::f64::NAN, 0.0, 0.0)]).is_none()
+        );
+    }
+
+    #[test]
+    fn test_discretize_checked_axis() {
+        let region = LeveledRegion(1);
+        assert!(region
+            .discretize_checked::<f64, u64>(Vector3::new(1.5, -2.0, 0.0))
+            .is_ok());
+        let error = region
+            .discretize_checked::<f64, u64>(Vector3::new(1.0, 0.0, 2.5))
+            .unwrap_err();
+        assert_eq!(error.axis, 2);
+        assert_eq!(error.value, 2.5);
+        assert_eq!(error.bound, 2.0);
+        assert_eq!(
+            error.to_string(),
+            "point is out of bounds on the z axis: 2.5 exceeds 2"
+        );
+        let error = region
+            .discretize_checked::<f64, u64>(Vector3::new(std::f64::NAN, 3.0, 0.0))
+            .unwrap_err();
+        assert_eq!(error.axis, 0);
+        assert!(region
+            .discretize::<f64, u64>(Vector3::new(0.0, 0.0, std::f64::NAN))
+            .is_none());
+    }
+
+    #[test]
+    fn test_discretize_sorted_matches_insert() {
+        let mut rng = SmallRng::from_seed([4; 16]);
+        let region = LeveledRegion(0);
+        let items: Vec<(Vector3<f64>, usize)> = (0..500)
+            .map(|i| {
+                let point = Vector3::new(
+                    rng.gen_range(-1.2, 1.2),
+                    rng.gen_range(-1.2, 1.2),
+                    rng.gen_range(-1.2, 1.2),
+                );
+                (point, i)
+            })
+            .collect();
+        let mut inserted = LinearOctree::<usize, u64>::new();
+        for &(point, i) in &items {
+            if let Some(morton) = region.discretize(point) {
+                inserted.insert(morton, i);
+            }
+        }
+
+        let sorted = region.discretize_sorted(items);
+        assert!(sorted.windows(2).all(|w| w[0].0 <= w[1].0));
+        let built = LinearOctree::from_sorted(sorted);
+        let expected: Vec<(u64, usize)> = inserted
+            .iter_explore(|_| true)
+            .map(|(m, &i)| (m, i))
+            .collect();
+        let found: Vec<(u64, usize)> = built.iter_explore(|_| true).map(|(m, &i)| (m, i)).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn test_discretize_non_finite() {
+        let region = LeveledRegion(0);
+        for &bad in &[std::f32::NAN, std::f32::INFINITY, std::f32::NEG_INFINITY] {
+            for axis in 0..3 {
+                let mut point = Vector3::new(0.5f32, -0.25, 0.125);
+                point[axis] = bad;
+                assert_eq!(region.discretize::<f32, u64>(point), None);
+                assert_eq!(region.discretize::<f32, u128>(point), None);
+                assert_eq!(
+                    region
+                        .discretize_checked::<f32, u64>(point)
+                        .unwrap_err()
+                        .axis,
+                    axis
+                );
+            }
+        }
+
+        let centered = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(0),
+            center: Vector3::new(1.0f32, 1.0, 1.0),
+        };
+        let point = Vector3::new(std::f32::INFINITY, 1.0, 1.0);
+        assert_eq!(centered.discretize::<u64>(point), None);
+    }
+
+    #[test]
+    fn test_discretize_half_open_bounds() {
+        for &level in &[-3, 0, 5] {
+            let region = LeveledRegion(level);
+            let bound = 2.0f32.powi(level);
+            let last = (1 << u64::dim_bits()) - 1;
+
+            let low: u64 = region
+                .discretize(Vector3::new(-bound, -bound, -bound))
+                .unwrap();
+            assert_eq!(low, 0);
+            for axis in 0..3 {
+                let mut point = Vector3::new(0.0, 0.0, 0.0);
+                point[axis] = bound;
+                assert_eq!(region.discretize::<f32, u64>(point), None);
+                assert_eq!(
+                    region
+                        .discretize_checked::<f32, u64>(point)
+                        .unwrap_err()
+                        .bound,
+                    bound
+                );
+            }
+
+            // The largest `f32` below the upper bound must land in the last cell and not outside of the grid.
+            let below = bound * (1.0 - std::f32::EPSILON / 2.0);
+            let high: u64 = region
+                .discretize(Vector3::new(below, below, below))
+                .unwrap();
+            assert_eq!(high.decode(), Vector3::new(last, last, last));
+            assert_eq!(high & u64::unused_bits(), 0);
+        }
+    }
+
+    #[test]
+    fn test_contains_matches_discretize() {
+        let mut rng = SmallRng::from_seed([5; 16]);
+        let region = LeveledRegion(1);
+        let centered = CenteredLeveledRegion {
+            leveled_region: region,
+            center: Vector3::new(0.5f32, -1.0, 2.0),
+        };
+        let mut points: Vec<Vector3<f32>> = (0..1000)
+            .map(|_| {
+                Vector3::new(
+                    rng.gen_range(-3.0, 3.0),
+                    rng.gen_range(-3.0, 3.0),
+                    rng.gen_range(-3.0, 3.0),
+                )
+            })
+            .collect();
+        points.push(Vector3::new(-2.0, -2.0, -2.0));
+        points.push(Vector3::new(2.0, 0.0, 0.0));
+        points.push(Vector3::new(0.0, std::f32::NAN, 0.0));
+        points.push(Vector3::new(0.0, 0.0, std::f32::NEG_INFINITY));
+        for &point in &points {
+            assert_eq!(
+                region.contains(point),
+                region.discretize::<f32, u64>(point).is_some()
+            );
+            assert_eq!(
+                centered.contains(point),
+                centered.discretize::<u64>(point).is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn test_bounds_discretize() {
+        let region = LeveledRegion(2);
+        let (min, max) = region.bounds::<f64>();
+        assert!(region.discretize::<f64, u64>(min).is_some());
+        assert!(region.discretize::<f64, u64>(max).is_none());
+        assert!(region
+            .discretize::<f64, u64>(max.map(|n| n + 1e-9))
+            .is_none());
+
+        let centered = CenteredLeveledRegion {
+            leveled_region: region,
+            center: Vector3::new(1.0, -3.0, 0.5),
+        };
+        let (min, max) = centered.bounds();
+        assert_eq!(min, Vector3::new(-3.0, -7.0, -3.5));
+        assert_eq!(max, Vector3::new(5.0, 1.0, 4.5));
+        assert!(centered.discretize::<u64>(min).is_some());
+        assert!(centered.discretize::<u64>(max).is_none());
+        // Just outside the max corner on only one axis.
+        let outside = Vector3::new(max[0] + 1e-9, 0.0, 0.0);
+        assert!(centered.discretize::<u64>(outside).is_none());
+    }
+
+    #[test]
+    fn test_distance_to() {
+        let region = LeveledRegion(1);
+        assert_eq!(region.distance_to(Vector3::new(0.5, -1.0, 1.5)), 0.0);
+        // On a face and on a corner of the region.
+        assert_eq!(region.distance_to(Vector3::new(2.0, 0.0, -2.0)), 0.0);
+        assert_eq!(region.distance_to(Vector3::new(-2.0, 2.0, -2.0)), 0.0);
+        // Past a face, an edge, and a corner.
+        assert_eq!(region.distance_to(Vector3::new(0.0, 5.0, 1.0)), 3.0);
+        assert_eq!(region.distance_to(Vector3::new(5.0, -6.0, 1.0)), 5.0);
+        assert_eq!(region.distance_to(Vector3::new(-3.0, 4.0, 4.0)), 3.0);
+
+        let centered = CenteredLeveledRegion {
+            leveled_region: region,
+            center: Vector3::new(10.0, 0.0, 0.0),
+        };
+        assert_eq!(centered.distance_to(Vector3::new(10.0, 0.0, 0.0)), 0.0);
+        assert_eq!(centered.distance_to(Vector3::new(0.0, 0.0, 0.0)), 8.0);
+    }
+
+    #[test]
+    fn test_cell_size() {
+        for &r in &[-3, 0, 5] {
+            let region = LeveledRegion(r);
+            for level in 0..=u64::dim_bits() as u32 {
+                let size = region.cell_size::<f64>(level);
+                let (min, max) = region.cell_bounds::<f64, u64>(0, level);
+                assert_eq!(max - min, Vector3::new(size, size, size));
+                assert_eq!(region.cell_size::<f32>(level) as f64, size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_level_for_radius() {
+        let mut rng = SmallRng::from_seed([12; 16]);
+        let region = LeveledRegion(1);
+        for _ in 0..1000 {
+            let radius: f64 = rng.gen_range(-20.0f64, 3.0).exp2();
+            let level = region.level_for_radius::<f64, u64>(radius);
+            if level < u64::dim_bits() as u32 {
+                assert!(region.cell_size::<f64>(level) <= radius);
+            }
+            if level > 0 {
+                assert!(region.cell_size::<f64>(level - 1) > radius);
+            }
+        }
+        assert_eq!(region.level_for_radius::<f64, u64>(0.0), 21);
+        assert_eq!(region.level_for_radius::<f64, u64>(-1.0), 21);
+        assert_eq!(region.level_for_radius::<f64, u128>(1e-30), 42);
+        assert_eq!(region.level_for_radius::<f32, u64>(4.0), 0);
+        assert_eq!(region.level_for_radius::<f32, u64>(3.9), 1);
+    }
+
+    #[test]
+    fn test_discretize_to_level() {
+        let region = LeveledRegion(0);
+        let max_level = u64::dim_bits() as u32;
+        let a = Vector3::new(0.3, -0.2, 0.7);
+        let b = Vector3::new(0.3 + 1e-4, -0.2, 0.7 - 1e-4);
+        assert_ne!(
+            region.discretize_to_level::<f64, u64>(a, max_level),
+            region.discretize_to_level::<f64, u64>(b, max_level)
+        );
+        assert_eq!(
+            region.discretize_to_level::<f64, u64>(a, max_level),
+            region.discretize::<f64, u64>(a)
+        );
+        assert_eq!(
+            region.discretize_to_level::<f64, u64>(a, 5),
+            region.discretize_to_level::<f64, u64>(b, 5)
+        );
+        assert_eq!(region.discretize_to_level::<f64, u64>(a, 0), Some(0));
+
+        // The coarse morton is the corner of the cell that the point is in.
+        let coarse: u64 = region.discretize_to_level(a, 5).unwrap();
+        let (min, max) = region.cell_bounds::<f64, _>(coarse, 5);
+        assert!((0..3).all(|i| min[i] <= a[i] && a[i] < max[i]));
+        assert_eq!(region.cell_bounds::<f64, _>(coarse, max_level).0, min);
+    }
+
+    #[test]
+    fn test_cell_bounds_nesting() {
+        let mut rng = SmallRng::from_seed([6; 16]);
+        let region = LeveledRegion(3);
+        let max_level = u64::dim_bits() as u32;
+        let leaf_width = 2.0f64.powi(3 + 1) / 2.0f64.powi(max_level as i32);
+        for _ in 0..100 {
+            let point = Vector3::new(
+                rng.gen_range(-8.0, 8.0),
+                rng.gen_range(-8.0, 8.0),
+                rng.gen_range(-8.0, 8.0),
+            );
+            let morton: u64 = region.discretize(point).unwrap();
+
+            let (min, max) = region.cell_bounds::<f64, _>(morton, max_level);
+            assert!((0..3).all(|i| (max[i] - min[i] - leaf_width).abs() < 1e-12));
+            assert!((0..3).all(|i| min[i] <= point[i] && point[i] < max[i]));
+
+            for level in 0..max_level {
+                let (parent_min, parent_max) = region.cell_bounds::<f64, _>(morton, level);
+                let (child_min, child_max) = region.cell_bounds::<f64, _>(morton, level + 1);
+                assert!(
+                    (0..3).all(|i| parent_min[i] <= child_min[i] && child_max[i] <= parent_max[i])
+                );
+            }
+        }
+        assert_eq!(region.cell_bounds::<f64, u64>(0, 0), region.bounds());
+    }
+
+    #[test]
+    fn test_u128_discretize_precision() {
+        let mut rng = SmallRng::from_seed([2; 16]);
+        let region = LeveledRegion(0);
+        let deep_width = 2.0f64.powi(region.0 + 1) / 2.0f64.powi(u128::dim_bits() as i32);
+        let mut shallow_error = 0.0f64;
+        for _ in 0..100 {
+            let point = Vector3::new(
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+            );
+            let deep: u128 = region.discretize(point).unwrap();
+            let deep: Vector3<f64> = region.undiscretize(deep);
+            assert!((0..3).all(|i| (deep[i] - point[i]).abs() <= deep_width));
+
+            let shallow: u64 = region.discretize(point).unwrap();
+            let shallow: Vector3<f64> = region.undiscretize(shallow);
+            for i in 0..3 {
+                shallow_error = shallow_error.max((shallow[i] - point[i]).abs());
+            }
+        }
+        // The `u64` cells are `2^21` times wider, so their error must exceed the width of a `u128` cell.
+        assert!(shallow_error > deep_width);
+    }
+
+    #[test]
+    fn test_centered_undiscretize_round_trip() {
+        let region = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(1),
+            center: Vector3::new(10.0, -4.0, 3.0),
+        };
+        let point = Vector3::new(11.5, -5.25, 3.125);
+        let morton: u64 = region.discretize(point).unwrap();
+        let center = region.undiscretize(morton);
+        assert!((center - point).norm() < 1e-5);
+    }
 }
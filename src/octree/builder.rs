@@ -0,0 +1,248 @@
+use crate::*;
+use nalgebra::Vector3;
+use num_traits::{Float, FromPrimitive, ToPrimitive};
+
+/// Builds a `LinearOctree` from points, choosing the `LeveledRegion` so that each leaf cell holds roughly a target
+/// number of items on average.
+///
+/// The region is never smaller than the smallest one that contains every point. From there it is grown one level
+/// at a time, which doubles the size of the leaf cells, until the average number of items in each occupied leaf
+/// cell is as close to the target as it can get, or until the next level isn't `is_representable` with the scalar
+/// and morton types. The level isn't estimated up front: every level that is tried discretizes and sorts all `N`
+/// points again, so each one costs `O(N log N)`.
+///
+/// ```
+/// use space::OctreeBuilder;
+/// use nalgebra::Vector3;
+///
+/// let items: Vec<_> = (0..1024)
+///     .map(|i| (Vector3::new(i as f64 / 1024.0, 0.5, 0.5), i))
+///     .collect();
+/// let (tree, region) = OctreeBuilder::new(items).occupancy(16.0).build::<u64>().unwrap();
+///
+/// // The leaf cells of this region are `1 / 64` wide, so they hold 16 items each.
+/// assert_eq!(region.0, 14);
+/// assert_eq!(tree.len(), 64);
+/// let morton: u64 = region.discretize(Vector3::new(0.0, 0.5, 0.5)).unwrap();
+/// assert_eq!(tree.get(morton), Some(&(0..16).collect::<Vec<_>>()));
+/// ```
+pub struct OctreeBuilder<S, T> {
+    items: Vec<(Vector3<S>, T)>,
+    occupancy: f64,
+}
+
+impl<S, T> OctreeBuilder<S, T>
+where
+    S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+{
+    /// Starts building an octree from `items` with a target of `1` item per leaf cell. Items whose point isn't
+    /// finite are dropped when the octree is built.
+    pub fn new(items: Vec<(Vector3<S>, T)>) -> Self {
+        OctreeBuilder {
+            items,
+            occupancy: 1.0,
+        }
+    }
+
+    /// Sets the target average number of items in each occupied leaf cell.
+    ///
+    /// Panics if `items_per_leaf` is not positive.
+    pub fn occupancy(mut self, items_per_leaf: f64) -> Self {
+        assert!(
+            items_per_leaf > 0.0,
+            "space::OctreeBuilder::occupancy(): the occupancy must be positive"
+        );
+        self.occupancy = items_per_leaf;
+        self
+    }
+
+    /// Chooses the region that `build` would use. If there are no finite points, this is `LeveledRegion(0)`.
+    ///
+    /// Gives back `LevelOverflow` if the points are too large for any region that `is_representable` with `S` and
+    /// `M` to contain them.
+    ///
+    /// ```
+    /// use space::{OctreeBuilder, SpaceError};
+    /// use nalgebra::Vector3;
+    ///
+    /// let builder = OctreeBuilder::new(vec![(Vector3::new(std::f32::MAX, 0.0, 0.0), 0)]);
+    /// assert_eq!(builder.region::<u64>(), Err(SpaceError::LevelOverflow { level: 127 }));
+    /// ```
+    pub fn region<M>(&self) -> Result<LeveledRegion, SpaceError>
+    where
+        M: Morton + std::fmt::Debug + 'static,
+    {
+        let points: Vec<Vector3<S>> = self
+            .items
+            .iter()
+            .map(|&(point, _)| point)
+            .filter(|point| point.iter().all(|c| c.is_finite()))
+            .collect();
+        if points.is_empty() {
+            return Ok(LeveledRegion(0));
+        }
+
+        // Start just below the smallest region that contains every point and grow it until it does.
+        let max_abs = points
+            .iter()
+            .flat_map(|point| point.iter().map(|c| c.abs()))
+            .fold(S::zero(), S::max);
+        let mut level = if max_abs > S::zero() {
+            max_abs.log2().floor().to_i32().unwrap()
+        } else {
+            0
+        };
+        loop {
+            let region = LeveledRegion(level);
+            if region.is_representable::<S, M>() {
+                if points
+                    .iter()
+                    .all(|&point| region.discretize::<S, M>(point).is_some())
+                {
+                    break;
+                }
+            } else if level > 0 {
+                // The region is too wide for `S`, and every level above it is too.
+                return Err(SpaceError::LevelOverflow { level });
+            }
+            // Otherwise the leaf cells of the region are too small for `S`, which a higher level fixes.
+            level += 1;
+        }
+
+        let occupancy = |level: i32| {
+            // Every level that gets here is representable and contains the smallest region, so every point fits.
+            let mut mortons: Vec<M> = points
+                .iter()
+                .map(|&point| LeveledRegion(level).discretize(point).unwrap())
+                .collect();
+            mortons.sort_unstable();
+            mortons.dedup();
+            points.len() as f64 / mortons.len() as f64
+        };
+        // Once the leaf cells are as big as the smallest region, the octants around the origin can't merge.
+        let max_level = level + M::dim_bits() as i32;
+        let mut below = occupancy(level);
+        while below < self.occupancy
+            && level < max_level
+            && LeveledRegion(level + 1).is_representable::<S, M>()
+        {
+            let above = occupancy(level + 1);
+            if above >= self.occupancy {
+                // Pick whichever level is closer to the target by ratio.
+                if self.occupancy / below >= above / self.occupancy {
+                    level += 1;
+                }
+                return Ok(LeveledRegion(level));
+            }
+            level += 1;
+            below = above;
+        }
+        Ok(LeveledRegion(level))
+    }
+
+    /// Builds the octree, which holds every item that landed in each leaf cell in the order they were given, and
+    /// gives it back along with the region that was chosen.
+    ///
+    /// Gives back `LevelOverflow` like `region` if no region can contain the points.
+    pub fn build<M>(self) -> Result<(LinearOctree<Vec<T>, M>, LeveledRegion), SpaceError>
+    where
+        M: Morton + std::fmt::Debug + 'static,
+    {
+        let region = self.region::<M>()?;
+        let sorted = region.discretize_sorted(self.items);
+        let tree = LinearOctree::from_sorted_dedup_by(
+            sorted
+                .into_iter()
+                .map(|(m, item)| (m, vec![item]))
+                .collect(),
+            |mut a: Vec<T>, b: Vec<T>| {
+                a.extend(b);
+                a
+            },
+        );
+        Ok((tree, region))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_occupancy_near_target() {
+        let mut rng = SmallRng::from_seed([1; 16]);
+        let items: Vec<(Vector3<f64>, usize)> = (0..5000)
+            .map(|i| {
+                let point = Vector3::new(
+                    rng.gen_range(-3.0, 5.0),
+                    rng.gen_range(-3.0, 5.0),
+                    rng.gen_range(-3.0, 5.0),
+                );
+                (point, i)
+            })
+            .collect();
+        for &target in &[1.0, 4.0, 50.0] {
+            let (tree, region) = OctreeBuilder::new(items.clone())
+                .occupancy(target)
+                .build::<u64>()
+                .unwrap();
+            assert!(region.0 >= 3);
+            let total: usize = tree.iter_explore(|_| true).map(|(_, v)| v.len()).sum();
+            assert_eq!(total, items.len());
+            // Each level changes the occupancy by at most 8 times, so the closest one is within a factor of
+            // `sqrt(8)`.
+            let occupancy = total as f64 / tree.len() as f64;
+            assert!(occupancy >= target / 2.9 && occupancy <= target * 2.9);
+        }
+    }
+
+    #[test]
+    fn test_smallest_region() {
+        let items = vec![
+            (Vector3::new(0.75, -1.0, 0.0), 0),
+            (Vector3::new(1.0, 0.0, 0.0), 1),
+            (Vector3::new(std::f64::NAN, 0.0, 0.0), 2),
+        ];
+        let (tree, region) = OctreeBuilder::new(items).build::<u64>().unwrap();
+        // A point on the upper bound of `LeveledRegion(0)` is outside of it.
+        assert_eq!(region.0, 1);
+        assert_eq!(tree.len(), 2);
+
+        let empty = OctreeBuilder::<f64, usize>::new(vec![]);
+        assert_eq!(empty.region::<u64>().unwrap().0, 0);
+        assert!(empty.build::<u64>().unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_extreme_points() {
+        // The leaf cells can't grow past `LeveledRegion(126)`, since `LeveledRegion(127)` is too wide for `f32`.
+        let items: Vec<(Vector3<f32>, usize)> = (0..8)
+            .map(|i| (Vector3::new(1e37 * (i as f32 / 8.0), 0.0, 0.0), i))
+            .collect();
+        let (tree, region) = OctreeBuilder::new(items)
+            .occupancy(1000.0)
+            .build::<u64>()
+            .unwrap();
+        assert_eq!(region.0, 126);
+        assert_eq!(
+            tree.iter_explore(|_| true)
+                .map(|(_, v)| v.len())
+                .sum::<usize>(),
+            8
+        );
+
+        let items = vec![(Vector3::new(std::f32::MAX, 0.0, 0.0), 0)];
+        assert_eq!(
+            OctreeBuilder::new(items.clone()).region::<u64>(),
+            Err(SpaceError::LevelOverflow { level: 127 })
+        );
+        assert!(OctreeBuilder::new(items).build::<u64>().is_err());
+
+        // Points too small for the leaf cells of their smallest region still find a level.
+        let items = vec![(Vector3::new(1e-40f32, 0.0, 0.0), 0)];
+        let region = OctreeBuilder::new(items).region::<u64>().unwrap();
+        assert!(region.is_representable::<f32, u64>());
+    }
+}
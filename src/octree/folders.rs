@@ -0,0 +1,495 @@
+//! Prebuilt `Folder` implementations for common aggregates.
+
+use crate::*;
+use nalgebra::Vector3;
+use num_traits::{Float, FromPrimitive};
+
+/// Folds any items into the number of items in a region.
+///
+/// Because tuples of folders are also folders, this can be computed in the same pass as other folders.
+///
+/// ```
+/// use space::{BoundingBoxFolder, CountFolder, LinearOctree, Morton};
+/// use nalgebra::Vector3;
+///
+/// let mut tree = LinearOctree::<Vector3<f32>, u64>::new();
+/// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), Vector3::new(1.0, -2.0, 3.0));
+/// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), Vector3::new(-1.0, 2.0, 0.5));
+///
+/// let (count, (min, max)) = tree.fold(&(CountFolder, BoundingBoxFolder)).unwrap();
+/// assert_eq!(count, 2);
+/// assert_eq!(min, Vector3::new(-1.0, -2.0, 0.5));
+/// assert_eq!(max, Vector3::new(1.0, 2.0, 3.0));
+/// ```
+pub struct CountFolder;
+
+impl<Item, M> Folder<Item, M> for CountFolder {
+    type Sum = usize;
+
+    fn gather<'a>(&self, _: M, _: &'a Item) -> Self::Sum {
+        1
+    }
+
+    fn fold<I>(&self, it: I) -> Self::Sum
+    where
+        I: Iterator<Item = Self::Sum>,
+    {
+        it.sum()
+    }
+}
+
+/// Folds any items into a histogram of how deep they are in a region, where the count at index `i` is the number of
+/// leaves `i` levels below the region. This shows how balanced an octree is, so the region level can be tuned.
+///
+/// ```
+/// use space::{LevelHistogramFolder, LinearOctree, Morton};
+/// use nalgebra::Vector3;
+///
+/// let mut tree = LinearOctree::<(), u64>::new();
+/// tree.insert(Morton::encode(Vector3::new(0, 0, 0)), ());
+/// assert_eq!(tree.fold(&LevelHistogramFolder).unwrap(), vec![1]);
+///
+/// // These split on the first level, and the first two split again on the second.
+/// tree.insert(Morton::encode(Vector3::new(1 << 19, 0, 0)), ());
+/// tree.insert(Morton::encode(Vector3::new(1 << 20, 0, 0)), ());
+/// assert_eq!(tree.fold(&LevelHistogramFolder).unwrap(), vec![0, 1, 2]);
+/// ```
+pub struct LevelHistogramFolder;
+
+impl<Item, M> Folder<Item, M> for LevelHistogramFolder {
+    type Sum = Vec<usize>;
+
+    fn gather<'a>(&self, _: M, _: &'a Item) -> Self::Sum {
+        vec![1]
+    }
+
+    fn fold<I>(&self, it: I) -> Self::Sum
+    where
+        I: Iterator<Item = Self::Sum>,
+    {
+        // Every leaf of the children is one level deeper in this region.
+        it.fold(vec![0], |mut histogram, child| {
+            if histogram.len() < child.len() + 1 {
+                histogram.resize(child.len() + 1, 0);
+            }
+            for (level, count) in child.into_iter().enumerate() {
+                histogram[level + 1] += count;
+            }
+            histogram
+        })
+    }
+}
+
+/// Folds items into a `Vec` of every leaf in a region in z-order, which is useful for extracting the contents of a
+/// subtree.
+///
+/// Every item is cloned once when it is gathered and is then moved up the tree, so folding costs one clone per item.
+/// Keeping the sum of every region, such as with `collect_fold`, clones each item once for every region above it.
+///
+/// ```
+/// use space::{CollectFolder, LinearOctree, Morton};
+/// use nalgebra::Vector3;
+///
+/// let mut tree = LinearOctree::<&str, u64>::new();
+/// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), "b");
+/// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a");
+///
+/// let leaves = tree.fold(&CollectFolder).unwrap();
+/// assert_eq!(leaves, vec![
+///     (Morton::encode(Vector3::new(1, 2, 3)), "a"),
+///     (Morton::encode(Vector3::new(4, 5, 6)), "b"),
+/// ]);
+/// ```
+pub struct CollectFolder;
+
+impl<Item, M> Folder<Item, M> for CollectFolder
+where
+    Item: Clone,
+{
+    type Sum = Vec<(M, Item)>;
+
+    fn gather<'a>(&self, morton: M, item: &'a Item) -> Self::Sum {
+        vec![(morton, item.clone())]
+    }
+
+    fn fold<I>(&self, it: I) -> Self::Sum
+    where
+        I: Iterator<Item = Self::Sum>,
+    {
+        it.flatten().collect()
+    }
+}
+
+/// Folds items that are points into the axis-aligned bounding box `(min, max)` of every point in a region.
+///
+/// ```
+/// use space::{BoundingBoxFolder, LinearOctree, Morton};
+/// use nalgebra::Vector3;
+///
+/// let mut tree = LinearOctree::<Vector3<f32>, u64>::new();
+/// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), Vector3::new(1.0, -2.0, 3.0));
+/// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), Vector3::new(-1.0, 2.0, 0.5));
+///
+/// let (min, max) = tree.fold(&BoundingBoxFolder).unwrap();
+/// assert_eq!(min, Vector3::new(-1.0, -2.0, 0.5));
+/// assert_eq!(max, Vector3::new(1.0, 2.0, 3.0));
+/// ```
+pub struct BoundingBoxFolder;
+
+impl<S, M> Folder<Vector3<S>, M> for BoundingBoxFolder
+where
+    S: Float + std::fmt::Debug + 'static,
+{
+    type Sum = (Vector3<S>, Vector3<S>);
+
+    fn gather<'a>(&self, _: M, item: &'a Vector3<S>) -> Self::Sum {
+        (*item, *item)
+    }
+
+    fn fold<I>(&self, it: I) -> Self::Sum
+    where
+        I: Iterator<Item = Self::Sum>,
+    {
+        it.fold(None, |total: Option<Self::Sum>, (min, max)| {
+            Some(match total {
+                Some((total_min, total_max)) => (
+                    component_wise(total_min, min, S::min),
+                    component_wise(total_max, max, S::max),
+                ),
+                None => (min, max),
+            })
+        })
+        .expect("space::BoundingBoxFolder::fold(): must be given at least one sum")
+    }
+}
+
+/// Folds items that are points into the sum of the points and the number of points in a region.
+/// This is the aggregate that the Barnes-Hut algorithm uses, with each point having unit mass.
+///
+/// ```
+/// use space::{CentroidFolder, LinearOctree, Morton};
+/// use nalgebra::Vector3;
+///
+/// let mut tree = LinearOctree::<Vector3<f32>, u64>::new();
+/// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), Vector3::new(1.0, -2.0, 3.0));
+/// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), Vector3::new(-1.0, 2.0, 1.0));
+///
+/// let sum = tree.fold(&CentroidFolder).unwrap();
+/// assert_eq!(sum.1, 2);
+/// assert_eq!(sum.centroid(), Vector3::new(0.0, 0.0, 2.0));
+/// ```
+pub struct CentroidFolder;
+
+/// The `Sum` of a `CentroidFolder`, which holds the sum of the points and the number of points.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CentroidSum<S>(pub Vector3<S>, pub usize)
+where
+    S: Float + std::fmt::Debug + 'static;
+
+impl<S> CentroidSum<S>
+where
+    S: Float + FromPrimitive + std::fmt::Debug + 'static,
+{
+    /// Gets the average of the points.
+    pub fn centroid(&self) -> Vector3<S> {
+        let count = S::from_usize(self.1)
+            .expect("space::CentroidSum::centroid(): unable to convert usize to S");
+        self.0.map(|n| n / count)
+    }
+}
+
+impl<S, M> Folder<Vector3<S>, M> for CentroidFolder
+where
+    S: Float + std::fmt::Debug + 'static,
+{
+    type Sum = CentroidSum<S>;
+
+    fn gather<'a>(&self, _: M, item: &'a Vector3<S>) -> Self::Sum {
+        CentroidSum(*item, 1)
+    }
+
+    fn fold<I>(&self, it: I) -> Self::Sum
+    where
+        I: Iterator<Item = Self::Sum>,
+    {
+        it.fold(CentroidSum(Vector3::zeros(), 0), |total, part| {
+            CentroidSum(
+                component_wise(total.0, part.0, |a, b| a + b),
+                total.1 + part.1,
+            )
+        })
+    }
+}
+
+/// Folds items into the sum of their positions scaled by their weight and the total weight in a region, which the
+/// weighted centroid is found from. This is the aggregate that the Barnes-Hut algorithm uses with the mass of each
+/// body as its weight.
+///
+/// ```
+/// use space::{LinearOctree, Morton, Positioned, WeightedCentroidFolder};
+/// use nalgebra::Vector3;
+///
+/// struct Body {
+///     position: Vector3<f32>,
+///     mass: f32,
+/// }
+///
+/// impl Positioned<f32> for Body {
+///     fn position(&self) -> Vector3<f32> {
+///         self.position
+///     }
+/// }
+///
+/// let mut tree = LinearOctree::<Body, u64>::new();
+/// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), Body { position: Vector3::new(0.0, 0.0, 0.0), mass: 3.0 });
+/// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), Body { position: Vector3::new(4.0, 0.0, 0.0), mass: 1.0 });
+///
+/// let sum = tree.fold(&WeightedCentroidFolder::new(|body: &Body| body.mass)).unwrap();
+/// assert_eq!(sum.1, 4.0);
+/// assert_eq!(sum.centroid(), Some(Vector3::new(1.0, 0.0, 0.0)));
+/// ```
+pub struct WeightedCentroidFolder<F> {
+    weight: F,
+}
+
+impl<F> WeightedCentroidFolder<F> {
+    /// Creates a folder that uses `weight` to get the weight of each item.
+    pub fn new(weight: F) -> Self {
+        WeightedCentroidFolder { weight }
+    }
+}
+
+/// The `Sum` of a `WeightedCentroidFolder`, which holds the sum of the weighted positions and the total weight.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WeightedCentroidSum<S>(pub Vector3<S>, pub S)
+where
+    S: Float + std::fmt::Debug + 'static;
+
+impl<S> WeightedCentroidSum<S>
+where
+    S: Float + std::fmt::Debug + 'static,
+{
+    /// Gets the weighted average of the positions, or `None` if the total weight is `0`.
+    pub fn centroid(&self) -> Option<Vector3<S>> {
+        if self.1 == S::zero() {
+            None
+        } else {
+            Some(self.0.map(|n| n / self.1))
+        }
+    }
+}
+
+impl<Item, M, S, F> Folder<Item, M> for WeightedCentroidFolder<F>
+where
+    Item: Positioned<S>,
+    F: Fn(&Item) -> S,
+    S: Float + std::fmt::Debug + 'static,
+{
+    type Sum = WeightedCentroidSum<S>;
+
+    fn gather<'a>(&self, _: M, item: &'a Item) -> Self::Sum {
+        let weight = (self.weight)(item);
+        WeightedCentroidSum(item.position().map(|n| n * weight), weight)
+    }
+
+    fn fold<I>(&self, it: I) -> Self::Sum
+    where
+        I: Iterator<Item = Self::Sum>,
+    {
+        it.fold(
+            WeightedCentroidSum(Vector3::zeros(), S::zero()),
+            |total, part| {
+                WeightedCentroidSum(
+                    component_wise(total.0, part.0, |a, b| a + b),
+                    total.1 + part.1,
+                )
+            },
+        )
+    }
+}
+
+/// Folds items into the `(min, max)` of a scalar that the closure extracts from each item in a region.
+///
+/// ```
+/// use space::{LinearOctree, MinMaxFolder, Morton};
+/// use nalgebra::Vector3;
+///
+/// let mut tree = LinearOctree::<(Vector3<f32>, u8), u64>::new();
+/// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), (Vector3::new(1.0, -2.0, 3.0), 7));
+/// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), (Vector3::new(-1.0, 2.0, 0.5), 3));
+/// tree.insert(Morton::encode(Vector3::new(7, 8, 9)), (Vector3::new(0.0, 0.0, 0.0), 5));
+///
+/// let intensity = MinMaxFolder::new(|&(_, intensity): &(Vector3<f32>, u8)| intensity);
+/// assert_eq!(tree.fold(&intensity), Some((3, 7)));
+/// ```
+pub struct MinMaxFolder<F> {
+    extract: F,
+}
+
+impl<F> MinMaxFolder<F> {
+    /// Creates a folder that uses `extract` to get the scalar from each item.
+    pub fn new(extract: F) -> Self {
+        MinMaxFolder { extract }
+    }
+}
+
+impl<Item, M, S, F> Folder<Item, M> for MinMaxFolder<F>
+where
+    F: Fn(&Item) -> S,
+    S: PartialOrd + Copy,
+{
+    type Sum = (S, S);
+
+    fn gather<'a>(&self, _: M, item: &'a Item) -> Self::Sum {
+        let value = (self.extract)(item);
+        (value, value)
+    }
+
+    fn fold<I>(&self, mut it: I) -> Self::Sum
+    where
+        I: Iterator<Item = Self::Sum>,
+    {
+        let first = it
+            .next()
+            .expect("space::MinMaxFolder::fold(): must be given at least one sum");
+        it.fold(first, |(min, max), (part_min, part_max)| {
+            (
+                if part_min < min { part_min } else { min },
+                if part_max > max { part_max } else { max },
+            )
+        })
+    }
+}
+
+/// Combines two vectors component by component with `f`.
+fn component_wise<S, F>(a: Vector3<S>, b: Vector3<S>, f: F) -> Vector3<S>
+where
+    S: Float + std::fmt::Debug + 'static,
+    F: Fn(S, S) -> S,
+{
+    Vector3::new(f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_bounding_box_encloses_points() {
+        let mut rng = SmallRng::from_seed([1; 16]);
+        let region = LeveledRegion(2);
+        let mut tree = LinearOctree::<Vector3<f64>, u64>::new();
+        let points: Vec<Vector3<f64>> = (0..1000)
+            .map(|_| {
+                Vector3::new(
+                    rng.gen_range(-4.0, 4.0),
+                    rng.gen_range(-4.0, 4.0),
+                    rng.gen_range(-4.0, 4.0),
+                )
+            })
+            .collect();
+        for &point in &points {
+            tree.insert(region.discretize(point).unwrap(), point);
+        }
+
+        let (min, max) = tree.fold(&BoundingBoxFolder).unwrap();
+        for (_, point) in tree.iter_explore(|_| true) {
+            assert!((0..3).all(|i| min[i] <= point[i] && point[i] <= max[i]));
+        }
+        // The box is tight, so every face touches a point.
+        for i in 0..3 {
+            assert!(tree.iter_explore(|_| true).any(|(_, p)| p[i] == min[i]));
+            assert!(tree.iter_explore(|_| true).any(|(_, p)| p[i] == max[i]));
+        }
+    }
+
+    #[test]
+    fn test_weighted_centroid() {
+        let mut tree = LinearOctree::<Vector3<f64>, u64>::new();
+        let region = LeveledRegion(0);
+        let points = [
+            Vector3::new(0.5, 0.5, 0.5),
+            Vector3::new(-0.5, 0.25, 0.0),
+            Vector3::new(0.0, -0.75, 0.25),
+        ];
+        for &point in &points {
+            tree.insert(region.discretize(point).unwrap(), point);
+        }
+
+        // Unit weights give the same centroid as `CentroidFolder`.
+        let unit = tree
+            .fold(&WeightedCentroidFolder::new(|_: &Vector3<f64>| 1.0))
+            .unwrap();
+        let plain = tree.fold(&CentroidFolder).unwrap();
+        assert_eq!(unit.1, 3.0);
+        assert!((unit.centroid().unwrap() - plain.centroid()).norm() < 1e-12);
+
+        // Only the last point has any weight, so the centroid is at it.
+        let last = tree
+            .fold(&WeightedCentroidFolder::new(|p: &Vector3<f64>| {
+                if p.z == 0.25 {
+                    2.0
+                } else {
+                    0.0
+                }
+            }))
+            .unwrap();
+        assert!((last.centroid().unwrap() - points[2]).norm() < 1e-12);
+
+        let weightless = tree
+            .fold(&WeightedCentroidFolder::new(|_: &Vector3<f64>| 0.0))
+            .unwrap();
+        assert_eq!(weightless.centroid(), None);
+    }
+
+    #[test]
+    fn test_level_histogram() {
+        let mut rng = SmallRng::from_seed([2; 16]);
+        let mut tree = LinearOctree::<(), u64>::new();
+        assert_eq!(tree.fold(&LevelHistogramFolder), None);
+        let mut mortons: Vec<u64> = (0..1000)
+            .map(|_| rng.gen::<u64>() & u64::used_bits())
+            .collect();
+        for &m in &mortons {
+            tree.insert(m, ());
+        }
+        mortons.sort();
+
+        // A leaf is stored one level below the deepest level it shares with any other leaf, which is always shared
+        // with a neighbor in z-order.
+        let shared = |a: u64, b: u64| ((a ^ b).leading_zeros() as usize - 1) / 3;
+        let mut expected = vec![0; u64::dim_bits() + 1];
+        for (i, &m) in mortons.iter().enumerate() {
+            let before = if i > 0 { shared(mortons[i - 1], m) } else { 0 };
+            let after = mortons.get(i + 1).map_or(0, |&next| shared(m, next));
+            expected[before.max(after) + 1] += 1;
+        }
+        while expected.last() == Some(&0) {
+            expected.pop();
+        }
+        let histogram = tree.fold(&LevelHistogramFolder).unwrap();
+        assert_eq!(histogram, expected);
+        assert_eq!(histogram.iter().sum::<usize>(), mortons.len());
+    }
+
+    #[test]
+    fn test_centroid() {
+        let mut tree = LinearOctree::<Vector3<f64>, u64>::new();
+        let region = LeveledRegion(0);
+        let points = [
+            Vector3::new(0.5, 0.5, 0.5),
+            Vector3::new(-0.5, 0.25, 0.0),
+            Vector3::new(0.0, -0.75, 0.25),
+            Vector3::new(-0.5, -0.5, -0.5),
+        ];
+        for &point in &points {
+            tree.insert(region.discretize(point).unwrap(), point);
+        }
+
+        let sum = tree.fold(&CentroidFolder).unwrap();
+        assert_eq!(sum.1, points.len());
+        assert!((sum.centroid() - Vector3::new(-0.125, -0.125, 0.0625)).norm() < 1e-12);
+    }
+}
@@ -1,7 +1,38 @@
 use crate::*;
+use either::Either;
+use nalgebra::Vector3;
+use num_traits::{Float, FromPrimitive, ToPrimitive};
+use rand::Rng;
+use std::io::Read;
+
+/// The deepest level that `LinearOctree::to_dense_grid` rasterizes, which is a grid of `2**30` cells.
+const MAX_DENSE_GRID_LEVEL: u32 = 10;
+
+/// Gets the region of the deepest level that contains `m`, which is the cell of the leaf at `m`.
+///
+/// A leaf is stored at the first region where it is alone, which can be much bigger than its cell. Any query that
+/// decides what to visit by region must test the cell of a leaf it reaches as well, or it would also give back the
+/// leaves that are only in the same region.
+fn leaf_cell<M>(m: M) -> MortonRegion<M>
+where
+    M: Morton,
+{
+    MortonRegion {
+        morton: m,
+        level: M::dim_bits(),
+    }
+}
 
 /// A linear hashed octree. This has constant time lookup for a given region or morton code.
 ///
+/// Every query takes `&self` and nothing in the octree uses interior mutability, so it is `Send` and `Sync`
+/// whenever `T` is. An octree that has been built can be shared by reference across threads, such as in a `rayon`
+/// loop of queries, without being wrapped in a lock.
+///
+/// The leaves are kept in a hash map rather than in a sorted array, so there is no contiguous slice of mortons or
+/// items to borrow. To hand them to a GPU buffer or across FFI in z-order, collect them from
+/// `iter_explore(|_| true)` first.
+///
 /// ```
 /// use space::{LinearOctree, Morton};
 /// use nalgebra::Vector3;
@@ -29,6 +60,9 @@ pub struct LinearOctree<T, M> {
     /// The each internal node either contains a `null` Morton or a non-null Morton which points to a leaf.
     /// Nodes which are not explicity stated implicitly indicate that it must be traversed deeper.
     internals: MortonRegionMap<M, M>,
+    /// The number of leaves under each node that must be traversed deeper, which are the regions left out of
+    /// `internals`.
+    counts: MortonRegionMap<usize, M>,
 }
 
 impl<T, M> Default for LinearOctree<T, M>
@@ -48,6 +82,7 @@ where
         LinearOctree {
             leaves: MortonMap::<_, M>::default(),
             internals,
+            counts: MortonRegionMap::default(),
         }
     }
 }
@@ -67,6 +102,328 @@ where
         Default::default()
     }
 
+    /// Creates an empty octree with room for at least `n` items before it reallocates. The internal nodes are given
+    /// room for as many entries as any octree with `n` items needs at the least, which may be fewer than it ends up
+    /// using if the items share long prefixes.
+    ///
+    /// ```
+    /// use space::LinearOctree;
+    ///
+    /// let tree = LinearOctree::<String, u64>::with_capacity(100);
+    /// assert!(tree.capacity() >= 100);
+    /// assert!(tree.is_empty());
+    /// ```
+    pub fn with_capacity(n: usize) -> Self {
+        let mut internals = MortonRegionMap::with_capacity_and_hasher(n + 1, Default::default());
+        internals.insert(MortonRegion::default(), M::null());
+        LinearOctree {
+            leaves: MortonMap::with_capacity_and_hasher(n, Default::default()),
+            internals,
+            counts: MortonRegionMap::default(),
+        }
+    }
+
+    /// Gets the number of items the octree can hold before it reallocates.
+    pub fn capacity(&self) -> usize {
+        self.leaves.capacity()
+    }
+
+    /// Builds an octree from `items` that are already sorted by morton. If several items share a morton, the last
+    /// one is kept, the same as it would be with `insert`.
+    ///
+    /// This builds the octree's internal nodes directly in linear passes over `items` instead of inserting each
+    /// item one at a time and allocates the octree's maps once with the exact capacity they will need.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut items: Vec<(u64, &str)> = vec![
+    ///     (Morton::encode(Vector3::new(4, 5, 6)), "b"),
+    ///     (Morton::encode(Vector3::new(1, 2, 3)), "a"),
+    /// ];
+    /// items.sort_by_key(|&(m, _)| m);
+    ///
+    /// let tree = LinearOctree::from_sorted(items);
+    /// assert_eq!(tree.get(Morton::encode(Vector3::new(1, 2, 3))), Some(&"a"));
+    /// assert_eq!(tree.get(Morton::encode(Vector3::new(4, 5, 6))), Some(&"b"));
+    /// ```
+    pub fn from_sorted(mut items: Vec<(M, T)>) -> Self {
+        debug_assert!(
+            items.windows(2).all(|w| w[0].0 <= w[1].0),
+            "space::LinearOctree::from_sorted(): items must be sorted by morton"
+        );
+        items.dedup_by(|later, earlier| {
+            if later.0 == earlier.0 {
+                std::mem::swap(later, earlier);
+                true
+            } else {
+                false
+            }
+        });
+
+        let n = items.len();
+        if n < 2 {
+            let mut tree = Self::default();
+            for (morton, item) in items {
+                tree.insert(morton, item);
+            }
+            return tree;
+        }
+
+        // The number of levels that two neighboring mortons have in common.
+        let shared = |i: usize| shared_levels(items[i].0, items[i + 1].0);
+        // Each leaf is stored one level below where it splits off from both of its neighbors.
+        let leaf_level = |i: usize| {
+            let prev = if i == 0 { 0 } else { shared(i - 1) };
+            let next = if i + 1 == n { 0 } else { shared(i) };
+            1 + prev.max(next)
+        };
+        // A leaf adds the internal nodes below where it splits off from the previous leaf.
+        let first_new = |i: usize| if i == 0 { 0 } else { shared(i - 1) + 1 };
+
+        // Every internal node has 8 children and every child other than the internal nodes is stored.
+        let internal_count: usize = (0..n).map(|i| leaf_level(i) - first_new(i)).sum();
+        let mut internals =
+            MortonRegionMap::with_capacity_and_hasher(7 * internal_count + 1, Default::default());
+
+        let mut counts =
+            MortonRegionMap::with_capacity_and_hasher(internal_count, Default::default());
+
+        // This holds the internal nodes on the path to the current leaf, which of their children are occupied and how
+        // many leaves have been put under them so far.
+        let mut path: Vec<(MortonRegion<M>, u8, usize)> = Vec::with_capacity(M::dim_bits());
+        let close = |internals: &mut MortonRegionMap<M, M>,
+                     counts: &mut MortonRegionMap<usize, M>,
+                     path: &mut Vec<(MortonRegion<M>, u8, usize)>| {
+            let (region, occupied, count) = path.pop().unwrap();
+            for i in 0..8 {
+                if occupied & (1u8 << i) == 0 {
+                    internals.insert(region.enter(i), M::null());
+                }
+            }
+            counts.insert(region, count);
+            // The leaves under the node are under its parent too.
+            if let Some(parent) = path.last_mut() {
+                parent.2 += count;
+            }
+        };
+        for (i, &(morton, _)) in items.iter().enumerate() {
+            if i != 0 {
+                // Close the nodes that the previous leaf doesn't share with this one.
+                let common = shared(i - 1);
+                while path.last().map_or(false, |&(r, _, _)| r.level > common) {
+                    close(&mut internals, &mut counts, &mut path);
+                }
+                path.last_mut().unwrap().1 |= 1u8 << morton.get_level(common);
+            }
+            let level = leaf_level(i);
+            for l in first_new(i)..level {
+                path.push((
+                    MortonRegion { morton, level: l },
+                    1u8 << morton.get_level(l),
+                    0,
+                ));
+            }
+            internals.insert(MortonRegion { morton, level }, morton);
+            // The deepest node on the path is the parent of the leaf.
+            path.last_mut().unwrap().2 += 1;
+        }
+        while !path.is_empty() {
+            close(&mut internals, &mut counts, &mut path);
+        }
+
+        let mut leaves = MortonMap::with_capacity_and_hasher(n, Default::default());
+        leaves.extend(items.into_iter().map(|(m, item)| (MortonWrapper(m), item)));
+        LinearOctree {
+            leaves,
+            internals,
+            counts,
+        }
+    }
+
+    /// Same as `from_sorted`, but items that share a morton are combined with `combine` instead of only keeping the
+    /// last one, which leaves one item per leaf cell. `combine` is given the items in the order they are in `items`.
+    ///
+    /// An octree never holds more than one item per morton, so this is where many points that fall in one leaf cell
+    /// can be collapsed, such as after `LeveledRegion::discretize_sorted`.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let a: u64 = Morton::encode(Vector3::new(1, 2, 3));
+    /// let b: u64 = Morton::encode(Vector3::new(4, 5, 6));
+    /// let tree = LinearOctree::from_sorted_dedup_by(vec![(a, 1), (a, 2), (b, 3)], |x, y| x + y);
+    /// assert_eq!(tree.len(), 2);
+    /// assert_eq!(tree.get(a), Some(&3));
+    /// assert_eq!(tree.get(b), Some(&3));
+    /// ```
+    pub fn from_sorted_dedup_by<F>(items: Vec<(M, T)>, mut combine: F) -> Self
+    where
+        F: FnMut(T, T) -> T,
+    {
+        let mut deduped: Vec<(M, T)> = Vec::with_capacity(items.len());
+        for (morton, item) in items {
+            match deduped.pop() {
+                Some((last, previous)) if last == morton => {
+                    deduped.push((morton, combine(previous, item)))
+                }
+                Some(entry) => {
+                    deduped.push(entry);
+                    deduped.push((morton, item));
+                }
+                None => deduped.push((morton, item)),
+            }
+        }
+        Self::from_sorted(deduped)
+    }
+
+    /// Builds an octree from items that are points in `region`. The points are discretized and sorted by morton in
+    /// parallel using `rayon` before the octree is built with `from_sorted`. Items whose point is not in the region
+    /// are dropped.
+    ///
+    /// The sort is unstable, so if several items land in the same leaf cell it is unspecified which one is kept. Use
+    /// `LeveledRegion::discretize_sorted` with `from_sorted` to keep the last one.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let items = vec![
+    ///     (Vector3::new(0.5, 0.5, 0.5), "a"),
+    ///     (Vector3::new(-0.5, -0.5, -0.5), "b"),
+    ///     (Vector3::new(1.5, 0.5, 0.5), "outside"),
+    /// ];
+    /// let tree = LinearOctree::<_, u64>::from_points_parallel(region, items);
+    /// assert_eq!(tree.len(), 2);
+    /// assert_eq!(tree.get(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap()), Some(&"a"));
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn from_points_parallel<S>(region: LeveledRegion, items: Vec<(Vector3<S>, T)>) -> Self
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + Send + 'static,
+        M: std::fmt::Debug + Send + 'static,
+        T: Send,
+    {
+        use rayon::prelude::*;
+
+        let mut sorted: Vec<(M, T)> = items
+            .into_par_iter()
+            .filter_map(|(point, item)| region.discretize(point).map(|m| (m, item)))
+            .collect();
+        sorted.par_sort_unstable_by_key(|&(m, _)| m);
+        Self::from_sorted(sorted)
+    }
+
+    /// Merges two octrees that were built on the same region into one. Items at the same morton in both octrees
+    /// are combined with `merge_items`, which is given the item from `self` first.
+    ///
+    /// Both octrees are traversed in z-order and merged in one linear pass, so nothing is re-sorted.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut a = LinearOctree::<u32, u64>::new();
+    /// a.insert(Morton::encode(Vector3::new(1, 2, 3)), 1);
+    /// a.insert(Morton::encode(Vector3::new(4, 5, 6)), 2);
+    /// let mut b = LinearOctree::<u32, u64>::new();
+    /// b.insert(Morton::encode(Vector3::new(4, 5, 6)), 3);
+    ///
+    /// let merged = a.merge(b, |a, b| a + b);
+    /// assert_eq!(merged.get(Morton::encode(Vector3::new(1, 2, 3))), Some(&1));
+    /// assert_eq!(merged.get(Morton::encode(Vector3::new(4, 5, 6))), Some(&5));
+    /// ```
+    pub fn merge<F>(self, other: Self, mut merge_items: F) -> Self
+    where
+        F: FnMut(T, T) -> T,
+    {
+        use std::cmp::Ordering;
+
+        let mut a = self.into_sorted().into_iter().peekable();
+        let mut b = other.into_sorted().into_iter().peekable();
+        let mut items = Vec::with_capacity(a.len() + b.len());
+        loop {
+            let ordering = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) => x.0.cmp(&y.0),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => break,
+            };
+            match ordering {
+                Ordering::Less => items.extend(a.next()),
+                Ordering::Greater => items.extend(b.next()),
+                Ordering::Equal => {
+                    let (m, x) = a.next().unwrap();
+                    let (_, y) = b.next().unwrap();
+                    items.push((m, merge_items(x, y)));
+                }
+            }
+        }
+        Self::from_sorted(items)
+    }
+
+    /// Transforms every item with `f`, which is given the morton of the item too. The mortons and the internal nodes
+    /// are moved over unchanged, so nothing is re-inserted. The items are not visited in any particular order.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<f32, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), 0.25);
+    /// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), 0.75);
+    ///
+    /// let labels = tree.map_items(|_, value| if value > 0.5 { "high" } else { "low" });
+    /// assert_eq!(labels.get(Morton::encode(Vector3::new(1, 2, 3))), Some(&"low"));
+    /// assert_eq!(labels.get(Morton::encode(Vector3::new(4, 5, 6))), Some(&"high"));
+    /// ```
+    pub fn map_items<U, F>(self, mut f: F) -> LinearOctree<U, M>
+    where
+        F: FnMut(&M, T) -> U,
+    {
+        let mut leaves = MortonMap::with_capacity_and_hasher(self.leaves.len(), Default::default());
+        leaves.extend(
+            self.leaves
+                .into_iter()
+                .map(|(MortonWrapper(m), item)| (MortonWrapper(m), f(&m, item))),
+        );
+        LinearOctree {
+            leaves,
+            internals: self.internals,
+            counts: self.counts,
+        }
+    }
+
+    /// Moves every item into a `PointerOctree` with the same mortons.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a");
+    ///
+    /// let pointer = tree.into_pointer();
+    /// assert_eq!(pointer.get(Morton::encode(Vector3::new(1, 2, 3))), Some(&"a"));
+    /// ```
+    pub fn into_pointer(self) -> PointerOctree<T, M> {
+        let mut tree = PointerOctree::new();
+        tree.extend(self);
+        tree
+    }
+
+    /// Takes all of the items out of the octree in z-order.
+    fn into_sorted(mut self) -> Vec<(M, T)> {
+        let mortons: Vec<M> = self.iter_explore(|_| true).map(|(m, _)| m).collect();
+        mortons
+            .into_iter()
+            .map(|m| (m, self.leaves.remove(&MortonWrapper(m)).unwrap()))
+            .collect()
+    }
+
     /// Inserts the item into the octree.
     ///
     /// If another element occupied the exact same morton, it will be evicted and replaced.
@@ -107,6 +464,8 @@ where
                             // Keep making the tree deeper until both leaves differ.
                             // TODO: Some bittwiddling with mortons might be able to get the number of traversals.
                             for level in region.level..M::dim_bits() {
+                                // Every node made here holds just the two leaves.
+                                self.counts.insert(region, 2);
                                 let leaf_level = leaf.get_level(level);
                                 let item_level = morton.get_level(level);
                                 if leaf_level == item_level {
@@ -134,9 +493,87 @@ where
                             }
                             unreachable!();
                         }
+                    } else {
+                        // The region is an internal node, so the new leaf goes under it.
+                        *self.counts.get_mut(&region).expect(
+                            "space::LinearOctree::insert(): every internal node must have a count",
+                        ) += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes every item that `keep` gives back `false` for. The internal nodes of the removed leaves are collapsed
+    /// in place, so the octree is the same as if only the remaining items were ever inserted and nothing is rebuilt.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<u32, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), 10);
+    /// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), 0);
+    ///
+    /// tree.retain(|_, &lifetime| lifetime > 0);
+    /// assert_eq!(tree.len(), 1);
+    /// assert!(tree.get(Morton::encode(Vector3::new(4, 5, 6))).is_none());
+    /// ```
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&M, &T) -> bool,
+    {
+        let mut removed = vec![];
+        self.leaves.retain(|&MortonWrapper(m), item| {
+            let kept = keep(&m, item);
+            if !kept {
+                removed.push(m);
+            }
+            kept
+        });
+        for m in removed {
+            self.remove_internal(m);
+        }
+    }
+
+    /// Removes the leaf at `morton` from the internal nodes and collapses every region above it that is left with
+    /// at most one leaf and no internal nodes.
+    fn remove_internal(&mut self, morton: M) {
+        let mut region = morton_levels(morton)
+            .find(|region| self.internals.get(region) == Some(&morton))
+            .unwrap();
+        self.internals.insert(region, M::null());
+        // Every region above the leaf is an internal node that the leaf was counted in.
+        let mut ancestor = region;
+        while ancestor.level > 0 {
+            ancestor.exit();
+            *self.counts.get_mut(&ancestor).expect(
+                "space::LinearOctree::remove_internal(): every internal node must have a count",
+            ) -= 1;
+        }
+        while region.level > 0 {
+            let mut parent = region;
+            parent.exit();
+            let mut remaining = M::null();
+            for i in 0..8 {
+                match self.internals.get(&parent.enter(i)) {
+                    // This child is an internal node, so the parent must stay.
+                    None => return,
+                    Some(&m) if !m.is_null() => {
+                        if !remaining.is_null() {
+                            return;
+                        }
+                        remaining = m;
                     }
+                    Some(_) => {}
                 }
             }
+            for i in 0..8 {
+                self.internals.remove(&parent.enter(i));
+            }
+            self.internals.insert(parent, remaining);
+            self.counts.remove(&parent);
+            region = parent;
         }
     }
 
@@ -160,6 +597,64 @@ where
         self.leaves.get_mut(&MortonWrapper(morton))
     }
 
+    /// Returns the number of leaves in the tree.
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Removes every item from the octree, but keeps the memory that was allocated for it so that it can be filled
+    /// again without reallocating.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a");
+    /// tree.clear();
+    /// assert!(tree.is_empty());
+    /// assert_eq!(tree.get(Morton::encode(Vector3::new(1, 2, 3))), None);
+    /// ```
+    pub fn clear(&mut self) {
+        self.leaves.clear();
+        self.internals.clear();
+        self.internals.insert(MortonRegion::default(), M::null());
+        self.counts.clear();
+    }
+
+    /// Checks if the octree is empty.
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Gets the level of the deepest region that a leaf is stored in, which is `0` if there are less than two leaves.
+    ///
+    /// A leaf is stored one level below the deepest prefix that it shares with its neighbors in z-order, so only
+    /// adjacent mortons need to be compared.
+    ///
+    /// ```
+    /// use space::LinearOctree;
+    ///
+    /// let mut tree = LinearOctree::<(), u64>::new();
+    /// tree.insert(0, ());
+    /// assert_eq!(tree.max_depth(), 0);
+    /// // This differs from the first leaf in the top level octant.
+    /// tree.insert(1 << 60, ());
+    /// assert_eq!(tree.max_depth(), 1);
+    /// ```
+    pub fn max_depth(&self) -> u32 {
+        let mut mortons = self.iter_explore(|_| true).map(|(m, _)| m);
+        let first = match mortons.next() {
+            Some(m) => m,
+            None => return 0,
+        };
+        mortons
+            .fold((first, 0), |(prev, depth), m| {
+                (m, depth.max(shared_levels(prev, m) as u32 + 1))
+            })
+            .1
+    }
+
     /// This gathers the octree in a tree fold by gathering leaves with `gatherer` and folding with `folder`.
     /// This allows information to be folded up the tree so it doesn't have to be computed multiple times.
     /// This has O(n) (exactly `n`) `gather` operations and O(n) (approximately `8/7 * n`) `fold` operations,
@@ -206,18 +701,3410 @@ where
             _ => None,
         }
     }
-}
 
-impl<T, M> Extend<(M, T)> for LinearOctree<T, M>
-where
-    M: Morton + Default,
-{
-    fn extend<I>(&mut self, it: I)
+    /// Folds the whole octree down to the sum at the root by gathering leaves with `folder.gather()` and folding them
+    /// with `folder.fold()`. Gives back `None` if the octree is empty.
+    ///
+    /// This is the same as the root sum from `collect_fold`, but it doesn't keep the sums for every region.
+    pub fn fold<F>(&self, folder: &F) -> Option<F::Sum>
     where
-        I: IntoIterator<Item = (M, T)>,
+        F: Folder<T, M>,
     {
-        for (morton, item) in it.into_iter() {
-            self.insert(morton, item);
+        self.fold_region(MortonRegion::base(), folder)
+    }
+
+    /// Same as `fold`, but with a `SpatialFolder`, which is also given the prefix, level, and world space bounds in
+    /// `region` of every node that it folds.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree, SpatialFolder};
+    /// use nalgebra::Vector3;
+    ///
+    /// /// Finds the volume of the smallest node that holds every item.
+    /// struct SmallestVolume;
+    ///
+    /// impl<Item, M> SpatialFolder<Item, M, f64> for SmallestVolume {
+    ///     type Sum = Option<f64>;
+    ///
+    ///     fn gather(&self, _: M, _: &Item) -> Option<f64> {
+    ///         None
+    ///     }
+    ///
+    ///     fn fold<I>(&self, _: M, _: u32, (min, max): (Vector3<f64>, Vector3<f64>), it: I) -> Option<f64>
+    ///     where
+    ///         I: Iterator<Item = Option<f64>>,
+    ///     {
+    ///         let sums: Vec<Option<f64>> = it.collect();
+    ///         if sums.len() == 1 {
+    ///             sums[0]
+    ///         } else {
+    ///             Some((max - min).iter().product())
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<(), u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.1, 0.1, 0.1)).unwrap(), ());
+    /// tree.insert(region.discretize(Vector3::new(0.9, 0.9, 0.9)).unwrap(), ());
+    /// // Both items are in the positive octant, which is 1 on each side.
+    /// assert_eq!(tree.fold_spatial(region, &SmallestVolume), Some(Some(1.0)));
+    /// ```
+    pub fn fold_spatial<S, F>(&self, region: LeveledRegion, folder: &F) -> Option<F::Sum>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        F: SpatialFolder<T, M, S>,
+    {
+        self.fold_spatial_region(region, MortonRegion::base(), folder)
+    }
+
+    fn fold_spatial_region<S, F>(
+        &self,
+        region: LeveledRegion,
+        node: MortonRegion<M>,
+        folder: &F,
+    ) -> Option<F::Sum>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        F: SpatialFolder<T, M, S>,
+    {
+        match self.internals.get(&node) {
+            Some(m) if !m.is_null() => Some(folder.gather(*m, &self.leaves[&MortonWrapper(*m)])),
+            None => Some(folder.fold(
+                node.morton,
+                node.level as u32,
+                region.region_bounds(node),
+                (0..8).filter_map(|i| self.fold_spatial_region(region, node.enter(i), folder)),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Same as `fold`, but with a `TryFolder` that can stop the walk early. As soon as `gather` or `fold` gives back
+    /// `ControlFlow::Break`, nothing else is visited and that sum is given back. Gives back `None` if the octree is
+    /// empty.
+    ///
+    /// ```
+    /// use space::{ControlFlow, LinearOctree, Morton, TryFolder};
+    /// use nalgebra::Vector3;
+    ///
+    /// /// Checks if any item is tagged.
+    /// struct AnyTagged;
+    ///
+    /// impl<M> TryFolder<bool, M> for AnyTagged {
+    ///     type Sum = bool;
+    ///
+    ///     fn gather(&self, _: M, &tagged: &bool) -> ControlFlow<bool, bool> {
+    ///         if tagged {
+    ///             ControlFlow::Break(true)
+    ///         } else {
+    ///             ControlFlow::Continue(false)
+    ///         }
+    ///     }
+    ///
+    ///     fn fold<I>(&self, mut it: I) -> ControlFlow<bool, bool>
+    ///     where
+    ///         I: Iterator<Item = bool>,
+    ///     {
+    ///         ControlFlow::Continue(it.any(|tagged| tagged))
+    ///     }
+    /// }
+    ///
+    /// let mut tree = LinearOctree::<bool, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), false);
+    /// assert_eq!(tree.try_fold(&AnyTagged), Some(ControlFlow::Continue(false)));
+    /// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), true);
+    /// assert_eq!(tree.try_fold(&AnyTagged), Some(ControlFlow::Break(true)));
+    /// ```
+    pub fn try_fold<F>(&self, folder: &F) -> Option<ControlFlow<F::Sum, F::Sum>>
+    where
+        F: TryFolder<T, M>,
+    {
+        self.try_fold_region(MortonRegion::base(), folder)
+    }
+
+    fn try_fold_region<F>(
+        &self,
+        region: MortonRegion<M>,
+        folder: &F,
+    ) -> Option<ControlFlow<F::Sum, F::Sum>>
+    where
+        F: TryFolder<T, M>,
+    {
+        match self.internals.get(&region) {
+            Some(m) if !m.is_null() => Some(folder.gather(*m, &self.leaves[&MortonWrapper(*m)])),
+            None => {
+                let mut sums: smallvec::SmallVec<[F::Sum; 8]> = smallvec::SmallVec::new();
+                for i in 0..8 {
+                    match self.try_fold_region(region.enter(i), folder) {
+                        Some(ControlFlow::Break(sum)) => return Some(ControlFlow::Break(sum)),
+                        Some(ControlFlow::Continue(sum)) => sums.push(sum),
+                        None => {}
+                    }
+                }
+                Some(folder.fold(sums.into_iter()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Same as `fold`, but the 8 top level octants are folded in parallel using `rayon` and then joined with
+    /// `folder.fold()`. For any folder where the order that sums are folded in doesn't matter, this gives back the
+    /// same sum as `fold`.
+    #[cfg(feature = "rayon")]
+    pub fn fold_parallel<F>(&self, folder: &F) -> Option<F::Sum>
+    where
+        F: Folder<T, M> + Sync,
+        F::Sum: Send,
+        T: Sync,
+        M: Sync,
+    {
+        use rayon::prelude::*;
+
+        let base = MortonRegion::base();
+        match self.internals.get(&base) {
+            None => {
+                let sums: Vec<Option<F::Sum>> = (0..8)
+                    .into_par_iter()
+                    .map(|i| self.fold_region(base.enter(i), folder))
+                    .collect();
+                Some(folder.fold(sums.into_iter().filter_map(|sum| sum)))
+            }
+            _ => self.fold_region(base, folder),
+        }
+    }
+
+    fn fold_region<F>(&self, region: MortonRegion<M>, folder: &F) -> Option<F::Sum>
+    where
+        F: Folder<T, M>,
+    {
+        match self.internals.get(&region) {
+            Some(m) if !m.is_null() => Some(folder.gather(*m, &self.leaves[&MortonWrapper(*m)])),
+            None => {
+                Some(folder.fold((0..8).filter_map(|i| self.fold_region(region.enter(i), folder))))
+            }
+            _ => None,
         }
     }
-}
\ No newline at end of file
+
+    /// Iterates over the leaves of the octree in z-order. `explore` is called for every region before it is visited
+    /// and any region that it gives back `false` for is skipped along with everything in it.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a");
+    /// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), "b");
+    /// assert_eq!(tree.iter_explore(|_| true).count(), 2);
+    /// assert_eq!(tree.iter_explore(|_| false).count(), 0);
+    /// ```
+    pub fn iter_explore<E>(&self, explore: E) -> LinearExploreIter<'_, T, M, E>
+    where
+        E: FnMut(MortonRegion<M>) -> bool,
+    {
+        LinearExploreIter::new(self, explore)
+    }
+
+    /// Walks the octree from the top down with `visitor`, using `region` to convert between mortons and world space.
+    /// Nodes and leaves are visited depth-first in z-order, so the leaves are visited in ascending morton order.
+    /// Empty nodes are never visited.
+    ///
+    /// ```
+    /// use space::{Descent, LeveledRegion, LinearOctree, Visitor};
+    /// use nalgebra::Vector3;
+    ///
+    /// /// Collects the leaves in the positive x half of the region.
+    /// struct PositiveX(Vec<&'static str>);
+    ///
+    /// impl Visitor<&'static str, u64, f64> for PositiveX {
+    ///     fn enter_node(&mut self, _: u64, _: u32, (_, max): (Vector3<f64>, Vector3<f64>)) -> Descent {
+    ///         if max.x > 0.0 { Descent::Descend } else { Descent::Skip }
+    ///     }
+    ///
+    ///     fn leaf(&mut self, _: u64, item: &&'static str) {
+    ///         self.0.push(*item);
+    ///     }
+    /// }
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), "a");
+    /// tree.insert(region.discretize(Vector3::new(0.5, -0.5, 0.5)).unwrap(), "b");
+    /// tree.insert(region.discretize(Vector3::new(-0.5, 0.5, 0.5)).unwrap(), "c");
+    ///
+    /// let mut visitor = PositiveX(vec![]);
+    /// tree.visit(region, &mut visitor);
+    /// assert_eq!(visitor.0, vec!["b", "a"]);
+    /// ```
+    pub fn visit<S, V>(&self, region: LeveledRegion, visitor: &mut V)
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        V: Visitor<T, M, S>,
+    {
+        let mut nodes = vec![MortonRegion::base()];
+        while let Some(node) = nodes.pop() {
+            let leaf = match self.internals.get(&node) {
+                Some(m) if m.is_null() => continue,
+                Some(&m) => Some(m),
+                None => None,
+            };
+            let cut = 3 * (M::dim_bits() - node.level);
+            let prefix = (node.morton >> cut) << cut;
+            let bounds = region.region_bounds(node);
+            if visitor.enter_node(prefix, node.level as u32, bounds) == Descent::Skip {
+                continue;
+            }
+            match leaf {
+                Some(m) => visitor.leaf(m, &self.leaves[&MortonWrapper(m)]),
+                None => {
+                    // The children are pushed in reverse so that they are popped in z-order.
+                    for i in (0..8).rev() {
+                        nodes.push(node.enter(i));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Iterates over every item whose leaf cell touches the closed box `[min, max]`, using `region` to convert
+    /// between mortons and world space. Any subregion that doesn't touch the box is skipped.
+    ///
+    /// A box where `min == max` gives back the item in the leaf cell containing that point, if there is one,
+    /// and a box where `min > max` on any axis gives back nothing.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), "inside");
+    /// tree.insert(region.discretize(Vector3::new(-0.5, -0.5, -0.5)).unwrap(), "outside");
+    ///
+    /// let found: Vec<_> = tree
+    ///     .query_box(region, Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0))
+    ///     .map(|(_, item)| *item)
+    ///     .collect();
+    /// assert_eq!(found, vec!["inside"]);
+    /// ```
+    pub fn query_box<S>(
+        &self,
+        region: LeveledRegion,
+        min: Vector3<S>,
+        max: Vector3<S>,
+    ) -> impl Iterator<Item = (M, &T)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let range = region.grid_range(min, max);
+        let touches =
+            move |node: MortonRegion<M>| range.map_or(false, |r| super::grid_overlaps(node, &r));
+        self.iter_explore(touches)
+            .filter(move |&(m, _)| touches(leaf_cell(m)))
+    }
+
+    /// Iterates over every item whose leaf cell touches the sphere at `center` with `radius`, using `region` to
+    /// convert between mortons and world space. Any subregion whose closest point is farther than `radius` from
+    /// `center` is skipped. Only squared distances are used, so no `sqrt` is performed.
+    ///
+    /// A `radius` of `0` gives back the item in the leaf cell containing `center`, if there is one.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), "inside");
+    /// tree.insert(region.discretize(Vector3::new(-0.5, -0.5, -0.5)).unwrap(), "outside");
+    ///
+    /// let found: Vec<_> = tree
+    ///     .query_sphere(region, Vector3::new(0.4, 0.4, 0.4), 0.5)
+    ///     .map(|(_, item)| *item)
+    ///     .collect();
+    /// assert_eq!(found, vec!["inside"]);
+    /// ```
+    pub fn query_sphere<S>(
+        &self,
+        region: LeveledRegion,
+        center: Vector3<S>,
+        radius: S,
+    ) -> impl Iterator<Item = (M, &T)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let valid = radius >= S::zero();
+        let radius2 = radius * radius;
+        let touches = move |node: MortonRegion<M>| {
+            let (min, max) = region.region_bounds(node);
+            valid && super::box_distance2(center, min, max) <= radius2
+        };
+        self.iter_explore(touches)
+            .filter(move |&(m, _)| touches(leaf_cell(m)))
+    }
+
+    /// Counts the items that `query_box` would give back without visiting them one at a time.
+    ///
+    /// Subregions that miss the box are skipped and the octree keeps the number of leaves under each internal node,
+    /// so a subregion fully inside of the box is counted with one lookup. Only the regions along the boundary of the
+    /// box are visited.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<(), u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), ());
+    /// tree.insert(region.discretize(Vector3::new(0.25, 0.75, 0.5)).unwrap(), ());
+    /// tree.insert(region.discretize(Vector3::new(-0.5, 0.5, 0.5)).unwrap(), ());
+    ///
+    /// assert_eq!(tree.count_box(region, Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0)), 2);
+    /// ```
+    pub fn count_box<S>(&self, region: LeveledRegion, min: Vector3<S>, max: Vector3<S>) -> usize
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let range = match region.grid_range(min, max) {
+            Some(range) => range,
+            None => return 0,
+        };
+        self.count_by(
+            MortonRegion::base(),
+            &|node: MortonRegion<M>| super::grid_overlaps(node, &range),
+            &|node: MortonRegion<M>| super::grid_contains(node, &range),
+        )
+    }
+
+    /// Counts the items that `query_sphere` would give back without visiting them one at a time, in the same way
+    /// as `count_box`. A subregion is fully inside of the sphere when its farthest corner is.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<(), u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), ());
+    /// tree.insert(region.discretize(Vector3::new(-0.5, -0.5, -0.5)).unwrap(), ());
+    ///
+    /// assert_eq!(tree.count_sphere(region, Vector3::new(0.6, 0.6, 0.6), 0.5), 1);
+    /// assert_eq!(tree.count_sphere(region, Vector3::new(0.0, 0.0, 0.0), 2.0), 2);
+    /// ```
+    pub fn count_sphere<S>(&self, region: LeveledRegion, center: Vector3<S>, radius: S) -> usize
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let valid = radius >= S::zero();
+        if !valid {
+            return 0;
+        }
+        let radius2 = radius * radius;
+        self.count_by(
+            MortonRegion::base(),
+            &|node: MortonRegion<M>| {
+                let (min, max) = region.region_bounds(node);
+                super::box_distance2(center, min, max) <= radius2
+            },
+            &|node: MortonRegion<M>| {
+                let (min, max) = region.region_bounds(node);
+                super::box_max_distance2(center, min, max) <= radius2
+            },
+        )
+    }
+
+    /// Counts the leaves under `node` whose cell `touches` gives back `true` for, where `inside` gives back `true`
+    /// for regions that `touches` is `true` for everywhere.
+    fn count_by<O, I>(&self, node: MortonRegion<M>, touches: &O, inside: &I) -> usize
+    where
+        O: Fn(MortonRegion<M>) -> bool,
+        I: Fn(MortonRegion<M>) -> bool,
+    {
+        match self.internals.get(&node) {
+            Some(m) if m.is_null() => 0,
+            Some(&m) => touches(leaf_cell(m)) as usize,
+            None if inside(node) => self.counts[&node],
+            None => (0..8)
+                .map(|i| node.enter(i))
+                .filter(|&child| touches(child))
+                .map(|child| self.count_by(child, touches, inside))
+                .sum(),
+        }
+    }
+
+    /// Same as `query_sphere`, but `region` is treated as periodic, so the sphere wraps around to the opposite face
+    /// wherever it goes past one. Distances follow the minimum image convention: on each axis a cell is as far as its
+    /// closest copy. `center` may be outside of the region and is wrapped into it first.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(-0.95, 0.0, 0.0)).unwrap(), "across");
+    ///
+    /// let center = Vector3::new(0.95, 0.0, 0.0);
+    /// assert_eq!(tree.query_sphere(region, center, 0.2).count(), 0);
+    /// let found: Vec<_> = tree
+    ///     .query_sphere_periodic(region, center, 0.2)
+    ///     .map(|(_, item)| *item)
+    ///     .collect();
+    /// assert_eq!(found, vec!["across"]);
+    /// ```
+    pub fn query_sphere_periodic<S>(
+        &self,
+        region: LeveledRegion,
+        center: Vector3<S>,
+        radius: S,
+    ) -> impl Iterator<Item = (M, &T)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let valid = radius >= S::zero() && center.iter().all(|c| c.is_finite());
+        let radius2 = radius * radius;
+        let (low, high) = region.region_bounds::<S, M>(MortonRegion::default());
+        let period = high.x - low.x;
+        let center = center.map(|c| {
+            let wrapped = (c - low.x) % period;
+            if c >= low.x && c < high.x {
+                c
+            } else if wrapped < S::zero() {
+                low.x + wrapped + period
+            } else {
+                low.x + wrapped
+            }
+        });
+        let touches = move |node: MortonRegion<M>| {
+            let (min, max) = region.region_bounds(node);
+            valid && super::periodic_box_distance2(center, min, max, period) <= radius2
+        };
+        self.iter_explore(touches)
+            .filter(move |&(m, _)| touches(leaf_cell(m)))
+    }
+
+    /// Finds every unordered pair of items whose leaf cell centers are within `radius` of each other, using `region`
+    /// to convert between mortons and world space. Each pair is given back once, with the smaller morton first, and
+    /// an item is never paired with itself.
+    ///
+    /// Every item only searches the subregions within `radius` of its cell center, like `query_sphere`, so this
+    /// doesn't compare every pair of items.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// let a = region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap();
+    /// let b = region.discretize(Vector3::new(0.6, 0.5, 0.5)).unwrap();
+    /// tree.insert(a, "a");
+    /// tree.insert(b, "b");
+    /// tree.insert(region.discretize(Vector3::new(-0.5, -0.5, -0.5)).unwrap(), "far");
+    ///
+    /// let pairs: Vec<(u64, u64)> = tree.pairs_within(region, 0.2).collect();
+    /// assert_eq!(pairs, vec![(a.min(b), a.max(b))]);
+    /// ```
+    pub fn pairs_within<S>(
+        &self,
+        region: LeveledRegion,
+        radius: S,
+    ) -> impl Iterator<Item = (M, M)> + '_
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let radius2 = radius * radius;
+        self.iter_explore(|_| true).flat_map(move |(m, _)| {
+            let center: Vector3<S> = region.undiscretize(m);
+            // Only the pairs with a larger morton are given back so that each pair is only found once.
+            self.query_sphere(region, center, radius)
+                .filter(move |&(other, _)| {
+                    other > m && super::distance2(region.undiscretize(other), center) <= radius2
+                })
+                .map(move |(other, _)| (m, other))
+        })
+    }
+
+    /// Same as `query_sphere`, but only gives back the items whose exact `position` is in the sphere. The leaf cells
+    /// are still used to skip subregions, so every position must be inside the leaf cell of its item.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<Vector3<f64>, u64>::new();
+    /// for &point in &[Vector3::new(0.5, 0.5, 0.5), Vector3::new(0.95, 0.5, 0.5)] {
+    ///     tree.insert(region.discretize(point).unwrap(), point);
+    /// }
+    ///
+    /// let found: Vec<_> = tree
+    ///     .query_sphere_positioned(region, Vector3::new(0.4, 0.5, 0.5), 0.5)
+    ///     .map(|(_, item)| *item)
+    ///     .collect();
+    /// assert_eq!(found, vec![Vector3::new(0.5, 0.5, 0.5)]);
+    /// ```
+    pub fn query_sphere_positioned<S>(
+        &self,
+        region: LeveledRegion,
+        center: Vector3<S>,
+        radius: S,
+    ) -> impl Iterator<Item = (M, &T)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        T: Positioned<S>,
+    {
+        let valid = radius >= S::zero();
+        let radius2 = radius * radius;
+        let touches = move |node: MortonRegion<M>| {
+            let (min, max) = region.region_bounds(node);
+            valid && super::box_distance2(center, min, max) <= radius2
+        };
+        self.iter_explore(touches)
+            .filter(move |&(_, item)| super::distance2(item.position(), center) <= radius2)
+    }
+
+    /// Iterates over every item whose leaf cell is hit by the ray from `origin` along `dir`, using `region` to
+    /// convert between mortons and world space. Items are given back front-to-back by the distance along the ray
+    /// where it enters their cell, and any subregion that the ray misses is skipped.
+    ///
+    /// `origin` may be inside of `region`, in which case the cells behind it are not included. A ray that misses
+    /// `region` entirely gives back nothing.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.25, 0.0)).unwrap(), "far");
+    /// tree.insert(region.discretize(Vector3::new(-0.5, 0.25, 0.0)).unwrap(), "near");
+    /// tree.insert(region.discretize(Vector3::new(0.0, -0.5, 0.0)).unwrap(), "missed");
+    ///
+    /// let origin = Vector3::new(-2.0, 0.25, 0.0);
+    /// let found: Vec<_> = tree
+    ///     .ray_cells(region, origin, Vector3::new(1.0, 0.0, 0.0))
+    ///     .map(|(_, item)| *item)
+    ///     .collect();
+    /// assert_eq!(found, vec!["near", "far"]);
+    /// ```
+    pub fn ray_cells<S>(
+        &self,
+        region: LeveledRegion,
+        origin: Vector3<S>,
+        dir: Vector3<S>,
+    ) -> impl Iterator<Item = (M, &T)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let (min, max) = region.region_bounds(MortonRegion::base());
+        let nodes = if super::ray_interval(origin, dir, min, max).is_some() {
+            vec![MortonRegion::base()]
+        } else {
+            vec![]
+        };
+        LinearRayIter {
+            tree: self,
+            region,
+            origin,
+            dir,
+            nodes,
+        }
+    }
+
+    /// Finds the first item whose leaf cell is hit by the ray from `origin` along `dir`, along with the distance
+    /// `t` along the ray where it enters the cell, using `region` to convert between mortons and world space.
+    ///
+    /// This takes the first item from `ray_cells`, so the walk stops as soon as it is found and none of the cells
+    /// behind it are visited.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.25, 0.0)).unwrap(), "far");
+    /// tree.insert(region.discretize(Vector3::new(-0.5, 0.25, 0.0)).unwrap(), "near");
+    ///
+    /// let origin = Vector3::new(-2.0, 0.25, 0.0);
+    /// let (_, item, t) = tree.ray_first(region, origin, Vector3::new(1.0, 0.0, 0.0)).unwrap();
+    /// assert_eq!(*item, "near");
+    /// assert!((t - 1.5).abs() < 1e-5);
+    /// ```
+    pub fn ray_first<S>(
+        &self,
+        region: LeveledRegion,
+        origin: Vector3<S>,
+        dir: Vector3<S>,
+    ) -> Option<(M, &T, S)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let (m, item) = self.ray_cells(region, origin, dir).next()?;
+        let (min, max) = region.region_bounds(leaf_cell(m));
+        super::ray_interval(origin, dir, min, max).map(|(t, _)| (m, item, t))
+    }
+
+    /// Iterates over every item inside of the view frustum bounded by six `planes`, using `region` to convert
+    /// between mortons and world space. Each plane is a `(normal, offset)` pair and the inside of it is where
+    /// `normal.dot(point) + offset >= 0`. The position of an item is taken to be the center of its leaf cell.
+    ///
+    /// Any subregion fully outside of a plane is skipped and planes that a subregion is fully inside of are not
+    /// tested again for anything in it, so a subregion fully inside of the frustum has no per-item tests.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), "inside");
+    /// tree.insert(region.discretize(Vector3::new(-0.5, 0.5, 0.5)).unwrap(), "outside");
+    ///
+    /// // This is the box [0, 1] on every axis.
+    /// let planes = [
+    ///     (Vector3::new(1.0, 0.0, 0.0), 0.0),
+    ///     (Vector3::new(-1.0, 0.0, 0.0), 1.0),
+    ///     (Vector3::new(0.0, 1.0, 0.0), 0.0),
+    ///     (Vector3::new(0.0, -1.0, 0.0), 1.0),
+    ///     (Vector3::new(0.0, 0.0, 1.0), 0.0),
+    ///     (Vector3::new(0.0, 0.0, -1.0), 1.0),
+    /// ];
+    /// let found: Vec<_> = tree.query_frustum(region, planes).map(|(_, item)| *item).collect();
+    /// assert_eq!(found, vec!["inside"]);
+    /// ```
+    pub fn query_frustum<S>(
+        &self,
+        region: LeveledRegion,
+        planes: [(Vector3<S>, S); 6],
+    ) -> impl Iterator<Item = (M, &T)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let mut iter = LinearFrustumIter {
+            tree: self,
+            region,
+            planes,
+            nodes: vec![],
+        };
+        if let Some(active) = iter.classify(MortonRegion::base(), 0b11_1111) {
+            iter.nodes.push((MortonRegion::base(), active));
+        }
+        iter
+    }
+
+    /// Encodes the octree into a compact blob that `from_bytes` can decode. Each item is encoded with `bincode`.
+    ///
+    /// The layout is stable and everything in it is little-endian:
+    ///
+    /// - The number of items `n` as a `u64`.
+    /// - The `n` mortons in ascending order, each taking `M::BITS / 8` bytes.
+    /// - The `n` items in the same order, each as a `u64` byte length followed by that many bytes of `bincode`.
+    ///
+    /// Gives back an error if an item fails to serialize.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<String, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "test".to_string());
+    ///
+    /// let bytes = tree.to_bytes().unwrap();
+    /// // The string is encoded as its `u64` length and then its bytes.
+    /// assert_eq!(bytes.len(), 8 + 8 + 8 + 8 + "test".len());
+    /// let decoded = LinearOctree::<String, u64>::from_bytes(&bytes).unwrap();
+    /// assert_eq!(decoded.get(Morton::encode(Vector3::new(1, 2, 3))), Some(&"test".to_string()));
+    /// ```
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error>
+    where
+        T: serde::Serialize,
+    {
+        let morton_bytes = M::BITS / 8;
+        let mut bytes = Vec::with_capacity(8 + self.leaves.len() * (morton_bytes + 8));
+        bytes.extend_from_slice(&(self.leaves.len() as u64).to_le_bytes());
+        let leaves: Vec<(M, &T)> = self.iter_explore(|_| true).collect();
+        for &(m, _) in &leaves {
+            bytes.extend((0..morton_bytes).map(|b| {
+                ((m >> (8 * b)) & M::from_u8(0xFF).unwrap())
+                    .to_u8()
+                    .unwrap()
+            }));
+        }
+        for &(_, item) in &leaves {
+            let item = bincode::serialize(item)?;
+            bytes.extend_from_slice(&(item.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&item);
+        }
+        Ok(bytes)
+    }
+
+    /// Decodes an octree from a blob made by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FromBytesError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        // This prevents a huge bogus length from reading anything before the blob runs out.
+        if bytes.len() >= 8 {
+            let mut n = [0; 8];
+            n.copy_from_slice(&bytes[..8]);
+            let n = u64::from_le_bytes(n);
+            if (bytes.len() as u64) < n.saturating_mul(M::BITS as u64 / 8 + 8) {
+                return Err(FromBytesError::Truncated);
+            }
+        }
+        Self::from_reader(bytes)
+    }
+
+    /// Decodes an octree made by `to_bytes` from `reader` as it is read, so the whole blob never has to be in
+    /// memory at once. Only one item is buffered at a time, on top of the mortons and the items already decoded.
+    ///
+    /// The reader must end where the blob does. Wrap it in `Read::take` if the blob is followed by other data.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<String, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "test".to_string());
+    ///
+    /// let bytes = tree.to_bytes().unwrap();
+    /// let decoded = LinearOctree::<String, u64>::from_reader(std::io::Cursor::new(bytes)).unwrap();
+    /// assert_eq!(decoded.get(Morton::encode(Vector3::new(1, 2, 3))), Some(&"test".to_string()));
+    /// ```
+    pub fn from_reader<R>(mut reader: R) -> Result<Self, FromBytesError>
+    where
+        R: std::io::Read,
+        T: serde::de::DeserializeOwned,
+    {
+        let n = read_u64(&mut reader)?;
+        // The length can't be trusted until the mortons are read, so only so much is reserved up front.
+        let mut mortons = Vec::with_capacity(n.min(1 << 16) as usize);
+        let mut buf = vec![0u8; M::BITS / 8];
+        for _ in 0..n {
+            read_exact(&mut reader, &mut buf)?;
+            let m = buf.iter().enumerate().fold(M::zero(), |m, (b, &byte)| {
+                m | M::from_u8(byte).unwrap() << (8 * b)
+            });
+            if m & M::unused_bits() != M::zero() || mortons.last().map_or(false, |&last| last >= m)
+            {
+                return Err(FromBytesError::InvalidMorton);
+            }
+            mortons.push(m);
+        }
+
+        let mut items = Vec::with_capacity(mortons.len());
+        for m in mortons {
+            let len = read_u64(&mut reader)?;
+            buf.clear();
+            // This only grows the buffer as the bytes arrive, so a bogus length can't allocate too much.
+            reader
+                .by_ref()
+                .take(len)
+                .read_to_end(&mut buf)
+                .map_err(FromBytesError::Io)?;
+            if (buf.len() as u64) < len {
+                return Err(FromBytesError::Truncated);
+            }
+            let item = bincode::deserialize(&buf).map_err(FromBytesError::Item)?;
+            items.push((m, item));
+        }
+        match reader.read(&mut [0]) {
+            Ok(0) => Ok(Self::from_sorted(items)),
+            Ok(_) => Err(FromBytesError::TrailingBytes),
+            Err(e) => Err(FromBytesError::Io(e)),
+        }
+    }
+
+    /// Iterates in z-order over the mortons that are occupied in both `self` and `other`.
+    ///
+    /// The smaller octree is traversed and every morton is looked up in the larger one, which makes this linear in
+    /// the size of the smaller octree. Regions of the smaller octree that the larger one knows to be empty are skipped.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut a = LinearOctree::<&str, u64>::new();
+    /// a.insert(Morton::encode(Vector3::new(1, 2, 3)), "a1");
+    /// a.insert(Morton::encode(Vector3::new(4, 5, 6)), "a2");
+    /// let mut b = LinearOctree::<u32, u64>::new();
+    /// b.insert(Morton::encode(Vector3::new(4, 5, 6)), 7);
+    ///
+    /// let both: Vec<u64> = a.intersect_cells(&b).collect();
+    /// assert_eq!(both, vec![Morton::encode(Vector3::new(4, 5, 6))]);
+    /// ```
+    pub fn intersect_cells<'a, O>(
+        &'a self,
+        other: &'a LinearOctree<O, M>,
+    ) -> impl Iterator<Item = M> + 'a {
+        if self.leaves.len() <= other.leaves.len() {
+            Either::Left(self.occupied_in(other))
+        } else {
+            Either::Right(other.occupied_in(self))
+        }
+    }
+
+    /// Iterates in z-order over the mortons that are occupied in `self` but not in `other`, which together with
+    /// `intersect_cells` can find what changed between two octrees.
+    ///
+    /// `self` is traversed and every morton is looked up in `other`, which makes this linear in the size of `self`.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut a = LinearOctree::<&str, u64>::new();
+    /// a.insert(Morton::encode(Vector3::new(1, 2, 3)), "a1");
+    /// a.insert(Morton::encode(Vector3::new(4, 5, 6)), "a2");
+    /// let mut b = LinearOctree::<u32, u64>::new();
+    /// b.insert(Morton::encode(Vector3::new(4, 5, 6)), 7);
+    ///
+    /// let only_a: Vec<u64> = a.difference_cells(&b).collect();
+    /// assert_eq!(only_a, vec![Morton::encode(Vector3::new(1, 2, 3))]);
+    /// assert_eq!(b.difference_cells(&a).count(), 0);
+    /// ```
+    pub fn difference_cells<'a, O>(
+        &'a self,
+        other: &'a LinearOctree<O, M>,
+    ) -> impl Iterator<Item = M> + 'a {
+        self.iter_explore(|_| true)
+            .map(|(m, _)| m)
+            .filter(move |&m| other.get(m).is_none())
+    }
+
+    /// Iterates in z-order over the mortons of `self` that are also occupied in `other`.
+    fn occupied_in<'a, O>(&'a self, other: &'a LinearOctree<O, M>) -> impl Iterator<Item = M> + 'a {
+        let explore = move |region: MortonRegion<M>| {
+            other.internals.get(&region).map_or(true, |m| !m.is_null())
+        };
+        self.iter_explore(explore)
+            .map(|(m, _)| m)
+            .filter(move |&m| other.get(m).is_some())
+    }
+
+    /// Gives back every empty leaf cell that shares a face with an occupied one in z-order, which is the shell around
+    /// the occupied cells. Each cell is only given back once, even if it touches several occupied cells.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<(), u64>::new();
+    /// tree.insert(u64::encode(Vector3::new(5, 5, 5)), ());
+    /// tree.insert(u64::encode(Vector3::new(6, 5, 5)), ());
+    ///
+    /// let shell: Vec<u64> = tree.empty_neighbors().collect();
+    /// assert_eq!(shell.len(), 10);
+    /// assert!(shell.contains(&u64::encode(Vector3::new(4, 5, 5))));
+    /// assert!(!shell.contains(&u64::encode(Vector3::new(6, 5, 5))));
+    /// ```
+    pub fn empty_neighbors(&self) -> impl Iterator<Item = M> {
+        let mut shell = vec![];
+        for &MortonWrapper(m) in self.leaves.keys() {
+            for axis in 0..3 {
+                for &positive in &[false, true] {
+                    if let Some(neighbor) = m.neighbor(axis, positive) {
+                        if self.get(neighbor).is_none() {
+                            shell.push(neighbor);
+                        }
+                    }
+                }
+            }
+        }
+        shell.sort_unstable();
+        shell.dedup();
+        shell.into_iter()
+    }
+
+    /// Rasterizes the octree into a dense occupancy grid of the cells at `level`, which is `2**level` cells on a side.
+    /// The grid is given back along with its side length and the cell at `(x, y, z)` is at index
+    /// `x + side * (y + side * z)`. A cell is `true` if any item is in it.
+    ///
+    /// The grid has `2**(3 * level)` cells, so this gives back `None` for any `level` above `10` instead of trying to
+    /// allocate more than `2**30` of them.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<(), u64>::new();
+    /// // This is in the cell at `(1, 0, 0)` on level 1.
+    /// tree.insert(Morton::encode(Vector3::new(1 << 20, 5, 7)), ());
+    ///
+    /// let (grid, side) = tree.to_dense_grid(1).unwrap();
+    /// assert_eq!(side, 2);
+    /// assert_eq!(grid, vec![false, true, false, false, false, false, false, false]);
+    /// assert!(tree.to_dense_grid(11).is_none());
+    /// ```
+    pub fn to_dense_grid(&self, level: u32) -> Option<(Vec<bool>, usize)> {
+        if level > MAX_DENSE_GRID_LEVEL || level as usize > M::dim_bits() {
+            return None;
+        }
+        let side = 1usize << level;
+        let cut = M::dim_bits() - level as usize;
+        let mut grid = vec![false; side * side * side];
+        for &MortonWrapper(m) in self.leaves.keys() {
+            let cell = m.decode().map(|d| (d >> cut).to_usize().unwrap());
+            grid[cell.x + side * (cell.y + side * cell.z)] = true;
+        }
+        Some((grid, side))
+    }
+
+    /// Groups the items in z-order by the cell at `level` that they are in. Every occupied cell at `level` is given
+    /// back once as its morton with every bit below `level` cleared, along with an iterator over the items in it in
+    /// z-order. A `level` of `0` puts every item in one group.
+    ///
+    /// This is one traversal of the octree that stops descending at `level`, so it doesn't visit any region twice.
+    ///
+    /// Panics if `level` is greater than `M::dim_bits()`.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a");
+    /// tree.insert(Morton::encode(Vector3::new(2, 1, 3)), "b");
+    /// tree.insert(Morton::encode(Vector3::new(1 << 20, 0, 0)), "c");
+    ///
+    /// let groups: Vec<(u64, Vec<&str>)> = tree
+    ///     .buckets(1)
+    ///     .map(|(cell, items)| (cell, items.cloned().collect()))
+    ///     .collect();
+    /// assert_eq!(groups, vec![(0, vec!["b", "a"]), (1 << 60, vec!["c"])]);
+    /// ```
+    pub fn buckets(&self, level: u32) -> impl Iterator<Item = (M, impl Iterator<Item = &T>)> {
+        assert!(
+            level as usize <= M::dim_bits(),
+            "space::LinearOctree::buckets(): level exceeds the number of levels in the morton"
+        );
+        LinearBucketIter {
+            tree: self,
+            nodes: vec![MortonRegion::base()],
+            level: level as usize,
+        }
+        .map(|(cell, items)| (cell, items.map(|(_, item)| item)))
+    }
+
+    /// Splits the octree into the largest cells that each hold at most `max_items` items, giving back the morton
+    /// prefix, level, and number of items of each cell in z-order, like a `CountFolder` over every cell. Every item is
+    /// in exactly one cell and empty cells are left out, which makes this an adaptive level of detail: dense areas are
+    /// given back as many small cells and sparse areas as a few big ones.
+    ///
+    /// A single item can't be split, so it always makes a cell, even when `max_items` is `0`. The counts are found
+    /// bottom-up in one pass over the octree.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<(), u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(0, 0, 0)), ());
+    /// tree.insert(Morton::encode(Vector3::new(1, 0, 0)), ());
+    /// tree.insert(Morton::encode(Vector3::new(1 << 20, 0, 0)), ());
+    ///
+    /// // The first two items are in the same top level octant.
+    /// let cells: Vec<(u64, u32, usize)> = tree.lod_cells(2).collect();
+    /// assert_eq!(cells, vec![(0, 1, 2), (1 << 60, 1, 1)]);
+    /// assert_eq!(tree.lod_cells(3).collect::<Vec<_>>(), vec![(0, 0, 3)]);
+    /// ```
+    pub fn lod_cells(&self, max_items: usize) -> impl Iterator<Item = (M, u32, usize)> {
+        let mut cells = vec![];
+        self.lod_region(MortonRegion::base(), max_items, &mut cells);
+        cells.into_iter()
+    }
+
+    /// Pushes the cells of `lod_cells` under `node` onto `cells` and gives back the number of items under it.
+    fn lod_region(
+        &self,
+        node: MortonRegion<M>,
+        max_items: usize,
+        cells: &mut Vec<(M, u32, usize)>,
+    ) -> usize {
+        match self.internals.get(&node) {
+            Some(m) if m.is_null() => 0,
+            Some(_) => {
+                cells.push((node.morton, node.level as u32, 1));
+                1
+            }
+            None => {
+                let start = cells.len();
+                let count: usize = (0..8)
+                    .map(|i| self.lod_region(node.enter(i), max_items, cells))
+                    .sum();
+                // The whole node fits, so it replaces the cells of its children.
+                if count <= max_items {
+                    cells.truncate(start);
+                    cells.push((node.morton, node.level as u32, count));
+                }
+                count
+            }
+        }
+    }
+
+    /// Iterates over the items in the cell at `level` that contains `prefix`, in z-order. Only the nodes on the
+    /// way down to that cell and the nodes inside it are visited, so this doesn't scan the whole tree.
+    ///
+    /// Only the top `level` octants of `prefix` matter, and a `level` of `0` gives back every item.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a");
+    /// tree.insert(Morton::encode(Vector3::new(2, 1, 3)), "b");
+    /// tree.insert(Morton::encode(Vector3::new(1 << 20, 0, 0)), "c");
+    ///
+    /// let found: Vec<&str> = tree.subtree(1 << 60, 1).map(|(_, item)| *item).collect();
+    /// assert_eq!(found, vec!["c"]);
+    /// assert_eq!(tree.subtree(0, 1).count(), 2);
+    /// assert_eq!(tree.subtree(7 << 60, 1).count(), 0);
+    /// ```
+    pub fn subtree(&self, prefix: M, level: u32) -> impl Iterator<Item = (M, &T)> {
+        assert!(
+            level as usize <= M::dim_bits(),
+            "space::LinearOctree::subtree(): level exceeds the number of levels in the morton"
+        );
+        let cell = MortonRegion {
+            morton: prefix,
+            level: level as usize,
+        };
+        self.iter_explore(move |region: MortonRegion<M>| {
+            if region.level < cell.level {
+                region.contains(cell.morton)
+            } else {
+                cell.contains(region.morton)
+            }
+        })
+        // A leaf stored above `level` may be in a different cell than `prefix`.
+        .filter(move |&(m, _)| cell.contains(m))
+    }
+
+    /// Gives back the item in the leaf cell that `point` discretizes into in `region`, or `None` if that cell is
+    /// empty or the point is outside of the region. Unlike `nearest`, this only looks up that one cell.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), "a");
+    ///
+    /// assert_eq!(tree.locate(region, Vector3::new(0.5, 0.5, 0.5)).map(|(_, &item)| item), Some("a"));
+    /// assert!(tree.locate(region, Vector3::new(-0.5, 0.5, 0.5)).is_none());
+    /// assert!(tree.locate(region, Vector3::new(1.5, 0.5, 0.5)).is_none());
+    /// ```
+    pub fn locate<S>(&self, region: LeveledRegion, point: Vector3<S>) -> Option<(M, &T)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let morton: M = region.discretize(point)?;
+        self.get(morton).map(|item| (morton, item))
+    }
+
+    /// Picks an item uniformly at random, or `None` if the octree is empty.
+    ///
+    /// This picks an index into the items in z-order and descends to it using the number of leaves under each
+    /// internal node, so it only visits the nodes on the path to the item. The same `rng` state always gives back
+    /// the same item for octrees with the same items.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use rand::{rngs::SmallRng, SeedableRng};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a");
+    ///
+    /// let mut rng = SmallRng::from_seed([0; 16]);
+    /// assert_eq!(tree.sample(&mut rng).map(|(_, &item)| item), Some("a"));
+    /// ```
+    pub fn sample<R>(&self, rng: &mut R) -> Option<(M, &T)>
+    where
+        R: Rng,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        let mut ix = rng.gen_range(0, self.len());
+        let mut region = MortonRegion::base();
+        loop {
+            match self.internals.get(&region) {
+                // Only regions with leaves are entered, so this is the leaf.
+                Some(&m) => return Some((m, &self.leaves[&MortonWrapper(m)])),
+                None => {
+                    // Find the child that the leaf at `ix` is in, and where it is in that child.
+                    for i in 0..8 {
+                        let child = region.enter(i);
+                        let count = match self.internals.get(&child) {
+                            Some(m) if m.is_null() => 0,
+                            Some(_) => 1,
+                            None => self.counts[&child],
+                        };
+                        if ix < count {
+                            region = child;
+                            break;
+                        }
+                        ix -= count;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Prepares to draw items at random with a probability proportional to their `weight`, which must be finite and
+    /// not negative. Items with a weight of `0` are never drawn.
+    ///
+    /// This computes the running total of the weights in z-order once, which makes each sample from the
+    /// `WeightedSampler` a binary search.
+    ///
+    /// Panics if `weight` gives back a negative or non-finite weight.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use rand::{rngs::SmallRng, SeedableRng};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<f64, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), 0.0);
+    /// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), 2.0);
+    ///
+    /// let sampler = tree.sample_weighted(|&weight| weight);
+    /// assert_eq!(sampler.total(), 2.0);
+    /// let mut rng = SmallRng::from_seed([0; 16]);
+    /// for _ in 0..10 {
+    ///     assert_eq!(sampler.sample(&mut rng).map(|(_, &weight)| weight), Some(2.0));
+    /// }
+    /// ```
+    pub fn sample_weighted<F>(&self, mut weight: F) -> WeightedSampler<'_, T, M>
+    where
+        F: FnMut(&T) -> f64,
+    {
+        let mut total = 0.0;
+        let mut totals = vec![];
+        for (m, item) in self.iter_explore(|_| true) {
+            let w = weight(item);
+            assert!(
+                w.is_finite() && w >= 0.0,
+                "space::LinearOctree::sample_weighted(): weights must be finite and not negative"
+            );
+            if w > 0.0 {
+                total += w;
+                totals.push((total, m));
+            }
+        }
+        WeightedSampler { tree: self, totals }
+    }
+
+    /// Finds the item closest to `point`, using `region` to convert between mortons and world space.
+    ///
+    /// This is the same as `k_nearest` with a `k` of `1`. `point` does not need to be inside of `region`.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), "near");
+    /// tree.insert(region.discretize(Vector3::new(-0.5, -0.5, -0.5)).unwrap(), "far");
+    ///
+    /// let (_, item) = tree.nearest(region, Vector3::new(3.0, 2.0, 1.0)).unwrap();
+    /// assert_eq!(*item, "near");
+    /// ```
+    pub fn nearest<S>(&self, region: LeveledRegion, point: Vector3<S>) -> Option<(M, &T)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        self.k_nearest(region, point, 1)
+            .pop()
+            .map(|(m, item, _)| (m, item))
+    }
+
+    /// Same as `nearest`, but the distance is measured to the exact `position` of each item.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<Vector3<f64>, u64>::new();
+    /// for &point in &[Vector3::new(0.5, 0.5, 0.5), Vector3::new(-0.5, -0.5, -0.5)] {
+    ///     tree.insert(region.discretize(point).unwrap(), point);
+    /// }
+    ///
+    /// let (_, item) = tree.nearest_positioned(region, Vector3::new(3.0, 2.0, 1.0)).unwrap();
+    /// assert_eq!(*item, Vector3::new(0.5, 0.5, 0.5));
+    /// ```
+    pub fn nearest_positioned<S>(&self, region: LeveledRegion, point: Vector3<S>) -> Option<(M, &T)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        T: Positioned<S>,
+    {
+        self.k_nearest_positioned(region, point, 1)
+            .pop()
+            .map(|(m, item, _)| (m, item))
+    }
+
+    /// Same as `nearest`, but gives back the center of the nearest occupied leaf cell and its distance to `point`
+    /// instead of the item. This works as a coarse distance field to the cells that are occupied.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<(), u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), ());
+    ///
+    /// let (position, distance) = tree.nearest_position(region, Vector3::new(0.5, 0.5, 3.5)).unwrap();
+    /// assert!((position - Vector3::new(0.5, 0.5, 0.5)).norm() < 1e-5);
+    /// assert!((distance - 3.0).abs() < 1e-5);
+    /// ```
+    pub fn nearest_position<S>(
+        &self,
+        region: LeveledRegion,
+        point: Vector3<S>,
+    ) -> Option<(Vector3<S>, S)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        self.k_nearest(region, point, 1)
+            .pop()
+            .map(|(m, _, distance)| (region.undiscretize(m), distance))
+    }
+
+    /// Finds up to `k` items closest to `point` along with their distance to `point`, using `region` to convert
+    /// between mortons and world space. The items are sorted by ascending distance and items at the same distance
+    /// are sorted by morton.
+    ///
+    /// The position of an item is taken to be the center of its leaf cell. Subregions are explored nearest-first
+    /// and any subregion that can't contain anything closer than the `k` best candidates so far is skipped.
+    /// `point` does not need to be inside of `region`.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), "a");
+    /// tree.insert(region.discretize(Vector3::new(0.0, 0.0, 0.0)).unwrap(), "b");
+    /// tree.insert(region.discretize(Vector3::new(-0.5, -0.5, -0.5)).unwrap(), "c");
+    ///
+    /// let found = tree.k_nearest(region, Vector3::new(0.6, 0.6, 0.6), 2);
+    /// assert_eq!(found.iter().map(|&(_, item, _)| *item).collect::<Vec<_>>(), vec!["a", "b"]);
+    /// ```
+    pub fn k_nearest<S>(
+        &self,
+        region: LeveledRegion,
+        point: Vector3<S>,
+        k: usize,
+    ) -> Vec<(M, &T, S)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        self.k_nearest_by(region, point, k, |m, _| region.undiscretize(m))
+    }
+
+    /// Same as `k_nearest`, but the distance is measured to the exact `position` of each item instead of to the
+    /// center of its leaf cell, so the results don't depend on how the positions were discretized. The leaf cells
+    /// are still used to skip subregions, so every position must be inside the leaf cell of its item.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<Vector3<f64>, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), Vector3::new(0.5, 0.5, 0.5));
+    /// tree.insert(region.discretize(Vector3::new(0.0, 0.0, 0.0)).unwrap(), Vector3::new(0.0, 0.0, 0.0));
+    /// tree.insert(region.discretize(Vector3::new(-0.5, -0.5, -0.5)).unwrap(), Vector3::new(-0.5, -0.5, -0.5));
+    ///
+    /// let found = tree.k_nearest_positioned(region, Vector3::new(0.6, 0.6, 0.6), 2);
+    /// assert_eq!(*found[0].1, Vector3::new(0.5, 0.5, 0.5));
+    /// assert!((found[0].2 - 0.03f64.sqrt()).abs() < 1e-12);
+    /// assert_eq!(*found[1].1, Vector3::new(0.0, 0.0, 0.0));
+    /// ```
+    pub fn k_nearest_positioned<S>(
+        &self,
+        region: LeveledRegion,
+        point: Vector3<S>,
+        k: usize,
+    ) -> Vec<(M, &T, S)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        T: Positioned<S>,
+    {
+        self.k_nearest_by(region, point, k, |_, item| item.position())
+    }
+
+    /// Iterates over every item in order of ascending distance from `point` to the center of its leaf cell, using
+    /// `region` to convert between mortons and world space. Items at the same distance are given back by morton.
+    ///
+    /// Unlike `k_nearest`, this is lazy and has no limit, so it can be stopped whenever enough items have been seen.
+    /// Subregions are kept in a priority queue by their distance from `point` and are only explored once everything
+    /// closer has been given back, so the items are never all sorted up front. `point` does not need to be inside of
+    /// `region`.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, LinearOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = LinearOctree::<&str, u64>::new();
+    /// tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), "a");
+    /// tree.insert(region.discretize(Vector3::new(0.0, 0.0, 0.0)).unwrap(), "b");
+    /// tree.insert(region.discretize(Vector3::new(-0.5, -0.5, -0.5)).unwrap(), "c");
+    ///
+    /// let found: Vec<_> = tree
+    ///     .iter_by_distance(region, Vector3::new(0.6, 0.6, 0.6))
+    ///     .map(|(_, item)| *item)
+    ///     .collect();
+    /// assert_eq!(found, vec!["a", "b", "c"]);
+    /// ```
+    pub fn iter_by_distance<S>(
+        &self,
+        region: LeveledRegion,
+        point: Vector3<S>,
+    ) -> impl Iterator<Item = (M, &T)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+    {
+        let mut queue = std::collections::BinaryHeap::new();
+        queue.push(DistanceEntry {
+            distance2: S::zero(),
+            leaf: false,
+            node: MortonRegion::base(),
+        });
+        LinearDistanceIter {
+            tree: self,
+            region,
+            point,
+            queue,
+        }
+    }
+
+    /// Finds up to `k` items closest to `point` where `position` gives the position of each item, which must be
+    /// inside its leaf cell.
+    fn k_nearest_by<S, P>(
+        &self,
+        region: LeveledRegion,
+        point: Vector3<S>,
+        k: usize,
+        position: P,
+    ) -> Vec<(M, &T, S)>
+    where
+        S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+        P: Fn(M, &T) -> Vector3<S>,
+    {
+        // This is kept sorted by squared distance and then by morton.
+        let mut found: Vec<(S, M)> = Vec::with_capacity(k.min(self.leaves.len()));
+        let mut nodes = vec![(S::zero(), MortonRegion::base())];
+        while let Some((bound, node)) = nodes.pop() {
+            // Skip regions that can't possibly contain anything closer than the worst candidate.
+            // Regions at the same distance must still be explored because they might win the morton tie-break.
+            if k == 0 || (found.len() == k && bound > found[k - 1].0) {
+                continue;
+            }
+            match self.internals.get(&node) {
+                Some(m) if m.is_null() => {}
+                Some(&m) => {
+                    let item = &self.leaves[&MortonWrapper(m)];
+                    let candidate = (super::distance2(position(m, item), point), m);
+                    let ix = found
+                        .iter()
+                        .position(|&other| candidate < other)
+                        .unwrap_or(found.len());
+                    if ix < k {
+                        found.insert(ix, candidate);
+                        found.truncate(k);
+                    }
+                }
+                None => {
+                    let mut children: smallvec::SmallVec<[(S, MortonRegion<M>); 8]> = (0..8)
+                        .map(|i| {
+                            let child = node.enter(i);
+                            let (min, max) = region.region_bounds(child);
+                            (super::box_distance2(point, min, max), child)
+                        })
+                        .collect();
+                    // Push the farthest first so that the nearest is explored first.
+                    children
+                        .sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+                    nodes.extend(children);
+                }
+            }
+        }
+        found
+            .into_iter()
+            .map(|(distance, m)| (m, &self.leaves[&MortonWrapper(m)], distance.sqrt()))
+            .collect()
+    }
+}
+
+/// An `Iterator` over the leaves of a `LinearOctree` in z-order that uses a closure to limit the exploration space.
+///
+/// Produced by `LinearOctree::iter_explore`.
+pub struct LinearExploreIter<'a, T, M, E> {
+    tree: &'a LinearOctree<T, M>,
+    nodes: Vec<MortonRegion<M>>,
+    explore: E,
+}
+
+impl<'a, T, M, E> LinearExploreIter<'a, T, M, E>
+where
+    M: Morton,
+    E: FnMut(MortonRegion<M>) -> bool,
+{
+    fn new(tree: &'a LinearOctree<T, M>, mut explore: E) -> Self {
+        let nodes = if explore(MortonRegion::base()) {
+            vec![MortonRegion::base()]
+        } else {
+            vec![]
+        };
+        LinearExploreIter {
+            tree,
+            nodes,
+            explore,
+        }
+    }
+}
+
+impl<'a, T, M, E> Iterator for LinearExploreIter<'a, T, M, E>
+where
+    M: Morton,
+    E: FnMut(MortonRegion<M>) -> bool,
+{
+    type Item = (M, &'a T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(region) = self.nodes.pop() {
+            match self.tree.internals.get(&region) {
+                Some(m) if m.is_null() => {}
+                Some(&m) => return Some((m, &self.tree.leaves[&MortonWrapper(m)])),
+                None => {
+                    // The children are pushed in reverse so that they are popped in z-order.
+                    for i in (0..8).rev() {
+                        let child = region.enter(i);
+                        if (self.explore)(child) {
+                            self.nodes.push(child);
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The leaves in one of the cells given back by `LinearBucketIter`, which is a single leaf if it was stored above
+/// the level of the cell.
+type BucketLeaves<'a, T, M> =
+    Either<LinearExploreIter<'a, T, M, fn(MortonRegion<M>) -> bool>, std::iter::Once<(M, &'a T)>>;
+
+/// An `Iterator` over the occupied cells of a `LinearOctree` at a level, along with the leaves in each of them.
+struct LinearBucketIter<'a, T, M> {
+    tree: &'a LinearOctree<T, M>,
+    nodes: Vec<MortonRegion<M>>,
+    level: usize,
+}
+
+impl<'a, T, M> Iterator for LinearBucketIter<'a, T, M>
+where
+    M: Morton,
+{
+    type Item = (M, BucketLeaves<'a, T, M>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cut = 3 * (M::dim_bits() - self.level);
+        while let Some(region) = self.nodes.pop() {
+            match self.tree.internals.get(&region) {
+                Some(m) if m.is_null() => {}
+                // A leaf stored above `level` is alone in its cell at `level`.
+                Some(&m) => {
+                    let item = &self.tree.leaves[&MortonWrapper(m)];
+                    return Some(((m >> cut) << cut, Either::Right(std::iter::once((m, item)))));
+                }
+                None if region.level == self.level => {
+                    let explore: fn(MortonRegion<M>) -> bool = |_| true;
+                    let items = LinearExploreIter {
+                        tree: self.tree,
+                        nodes: vec![region],
+                        explore,
+                    };
+                    return Some(((region.morton >> cut) << cut, Either::Left(items)));
+                }
+                None => {
+                    // The children are pushed in reverse so that they are popped in z-order.
+                    for i in (0..8).rev() {
+                        self.nodes.push(region.enter(i));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// An `Iterator` over the leaves of a `LinearOctree` hit by a ray, in front-to-back order.
+///
+/// Produced by `LinearOctree::ray_cells`.
+struct LinearRayIter<'a, T, M, S> {
+    tree: &'a LinearOctree<T, M>,
+    region: LeveledRegion,
+    origin: Vector3<S>,
+    dir: Vector3<S>,
+    nodes: Vec<MortonRegion<M>>,
+}
+
+impl<'a, T, M, S> LinearRayIter<'a, T, M, S>
+where
+    M: Morton,
+    S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+{
+    /// Gets the distance along the ray where it enters `node`, if it hits `node` at all.
+    fn entry(&self, node: MortonRegion<M>) -> Option<S> {
+        let (min, max) = self.region.region_bounds(node);
+        super::ray_interval(self.origin, self.dir, min, max).map(|(entry, _)| entry)
+    }
+}
+
+impl<'a, T, M, S> Iterator for LinearRayIter<'a, T, M, S>
+where
+    M: Morton,
+    S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+{
+    type Item = (M, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.nodes.pop() {
+            match self.tree.internals.get(&node) {
+                Some(m) if m.is_null() => {}
+                Some(&m) => {
+                    let cell = leaf_cell(m);
+                    if self.entry(cell).is_some() {
+                        return Some((m, &self.tree.leaves[&MortonWrapper(m)]));
+                    }
+                }
+                None => {
+                    let mut children: smallvec::SmallVec<[(S, MortonRegion<M>); 8]> = (0..8)
+                        .filter_map(|i| {
+                            let child = node.enter(i);
+                            self.entry(child).map(|entry| (entry, child))
+                        })
+                        .collect();
+                    // The children don't overlap, so pushing the farthest entry first visits them front-to-back.
+                    children
+                        .sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+                    self.nodes
+                        .extend(children.into_iter().map(|(_, child)| child));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Draws items from a `LinearOctree` at random with a probability proportional to their weight.
+///
+/// Produced by `LinearOctree::sample_weighted`.
+pub struct WeightedSampler<'a, T, M> {
+    tree: &'a LinearOctree<T, M>,
+    /// The running total of the weights in z-order up to and including each leaf that has a positive weight.
+    totals: Vec<(f64, M)>,
+}
+
+impl<'a, T, M> WeightedSampler<'a, T, M>
+where
+    M: Morton,
+{
+    /// Gets the sum of the weights of every item.
+    pub fn total(&self) -> f64 {
+        self.totals.last().map_or(0.0, |&(total, _)| total)
+    }
+
+    /// Picks an item at random with a probability proportional to its weight, or `None` if no item has a positive
+    /// weight. The same `rng` state always gives back the same item.
+    pub fn sample<R>(&self, rng: &mut R) -> Option<(M, &'a T)>
+    where
+        R: Rng,
+    {
+        if self.totals.is_empty() {
+            return None;
+        }
+        let target = rng.gen::<f64>() * self.total();
+        // Find the first leaf whose running total is past the target.
+        let ix = self
+            .totals
+            .binary_search_by(|&(total, _)| {
+                if total <= target {
+                    std::cmp::Ordering::Less
+                } else {
+                    std::cmp::Ordering::Greater
+                }
+            })
+            .unwrap_or_else(|ix| ix);
+        // Rounding might put the target right at the total.
+        let (_, m) = self.totals[ix.min(self.totals.len() - 1)];
+        Some((m, &self.tree.leaves[&MortonWrapper(m)]))
+    }
+}
+
+/// A region or leaf waiting to be visited by a `LinearDistanceIter`.
+///
+/// It is ordered so that the `BinaryHeap` pops the smallest squared distance first. At the same distance regions are
+/// popped before leaves, since a region might hold a leaf at that distance that wins the morton tie-break, and then
+/// the lowest morton comes first.
+struct DistanceEntry<S, M> {
+    distance2: S,
+    leaf: bool,
+    node: MortonRegion<M>,
+}
+
+impl<S, M> PartialEq for DistanceEntry<S, M>
+where
+    M: Morton,
+    S: Float,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl<S, M> Eq for DistanceEntry<S, M>
+where
+    M: Morton,
+    S: Float,
+{
+}
+
+impl<S, M> PartialOrd for DistanceEntry<S, M>
+where
+    M: Morton,
+    S: Float,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S, M> Ord for DistanceEntry<S, M>
+where
+    M: Morton,
+    S: Float,
+{
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .distance2
+            .partial_cmp(&self.distance2)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(other.leaf.cmp(&self.leaf))
+            .then(other.node.cmp(&self.node))
+    }
+}
+
+/// An `Iterator` over the leaves of a `LinearOctree` in order of ascending distance from a point.
+///
+/// Produced by `LinearOctree::iter_by_distance`.
+struct LinearDistanceIter<'a, T, M, S> {
+    tree: &'a LinearOctree<T, M>,
+    region: LeveledRegion,
+    point: Vector3<S>,
+    queue: std::collections::BinaryHeap<DistanceEntry<S, M>>,
+}
+
+impl<'a, T, M, S> Iterator for LinearDistanceIter<'a, T, M, S>
+where
+    M: Morton,
+    S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+{
+    type Item = (M, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(entry) = self.queue.pop() {
+            if entry.leaf {
+                let m = entry.node.morton;
+                return Some((m, &self.tree.leaves[&MortonWrapper(m)]));
+            }
+            match self.tree.internals.get(&entry.node) {
+                Some(m) if m.is_null() => {}
+                Some(&m) => {
+                    // The region bounds the leaf, so the leaf can't be closer than the region was.
+                    let center: Vector3<S> = self.region.undiscretize(m);
+                    self.queue.push(DistanceEntry {
+                        distance2: super::distance2(center, self.point),
+                        leaf: true,
+                        node: leaf_cell(m),
+                    });
+                }
+                None => {
+                    for i in 0..8 {
+                        let child = entry.node.enter(i);
+                        let (min, max) = self.region.region_bounds(child);
+                        self.queue.push(DistanceEntry {
+                            distance2: super::box_distance2(self.point, min, max),
+                            leaf: false,
+                            node: child,
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// An `Iterator` over the leaves of a `LinearOctree` inside of a view frustum in z-order.
+///
+/// Produced by `LinearOctree::query_frustum`.
+struct LinearFrustumIter<'a, T, M, S> {
+    tree: &'a LinearOctree<T, M>,
+    region: LeveledRegion,
+    planes: [(Vector3<S>, S); 6],
+    /// Each region is stored with the mask of the planes that it isn't known to be fully inside of.
+    nodes: Vec<(MortonRegion<M>, u8)>,
+}
+
+impl<'a, T, M, S> LinearFrustumIter<'a, T, M, S>
+where
+    M: Morton,
+    S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+{
+    /// Gets the part of the `active` plane mask that `node` isn't fully inside of,
+    /// or `None` if `node` is fully outside of any plane.
+    fn classify(&self, node: MortonRegion<M>, active: u8) -> Option<u8> {
+        let (min, max) = self.region.region_bounds(node);
+        let mut remaining = active;
+        for (i, &(normal, offset)) in self.planes.iter().enumerate() {
+            if active & (1 << i) == 0 {
+                continue;
+            }
+            // The corners of the box that are the farthest along the normal and against it.
+            let mut far = offset;
+            let mut near = offset;
+            for axis in 0..3 {
+                let (towards, away) = if normal[axis] >= S::zero() {
+                    (max[axis], min[axis])
+                } else {
+                    (min[axis], max[axis])
+                };
+                far = far + normal[axis] * towards;
+                near = near + normal[axis] * away;
+            }
+            if far < S::zero() {
+                return None;
+            }
+            if near >= S::zero() {
+                remaining &= !(1 << i);
+            }
+        }
+        Some(remaining)
+    }
+}
+
+impl<'a, T, M, S> Iterator for LinearFrustumIter<'a, T, M, S>
+where
+    M: Morton,
+    S: Float + ToPrimitive + FromPrimitive + std::fmt::Debug + 'static,
+{
+    type Item = (M, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, active)) = self.nodes.pop() {
+            match self.tree.internals.get(&node) {
+                Some(m) if m.is_null() => {}
+                Some(&m) => {
+                    let center: Vector3<S> = self.region.undiscretize(m);
+                    let inside = self
+                        .planes
+                        .iter()
+                        .enumerate()
+                        .all(|(i, &(normal, offset))| {
+                            active & (1 << i) == 0
+                                || (0..3)
+                                    .fold(offset, |acc, axis| acc + normal[axis] * center[axis])
+                                    >= S::zero()
+                        });
+                    if inside {
+                        return Some((m, &self.tree.leaves[&MortonWrapper(m)]));
+                    }
+                }
+                None => {
+                    // The children are pushed in reverse so that they are popped in z-order.
+                    for i in (0..8).rev() {
+                        let child = node.enter(i);
+                        if let Some(active) = self.classify(child, active) {
+                            self.nodes.push((child, active));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<T, M> Extend<(M, T)> for LinearOctree<T, M>
+where
+    M: Morton + Default,
+{
+    fn extend<I>(&mut self, it: I)
+    where
+        I: IntoIterator<Item = (M, T)>,
+    {
+        for (morton, item) in it.into_iter() {
+            self.insert(morton, item);
+        }
+    }
+}
+
+impl<T, M> IntoIterator for LinearOctree<T, M>
+where
+    M: Morton,
+{
+    type Item = (M, T);
+    type IntoIter = std::vec::IntoIter<(M, T)>;
+
+    /// Moves every item out of the octree in z-order.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = LinearOctree::<String, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), "b".to_string());
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a".to_string());
+    ///
+    /// let items: Vec<(u64, String)> = tree.into_iter().collect();
+    /// assert_eq!(items, vec![
+    ///     (Morton::encode(Vector3::new(1, 2, 3)), "a".to_string()),
+    ///     (Morton::encode(Vector3::new(4, 5, 6)), "b".to_string()),
+    /// ]);
+    /// ```
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_sorted().into_iter()
+    }
+}
+
+impl<T, M> PartialEq for LinearOctree<T, M>
+where
+    T: PartialEq,
+    M: Morton,
+{
+    /// Two octrees are equal if they have the same items at the same mortons, regardless of the order they were
+    /// inserted in.
+    ///
+    /// ```
+    /// use space::{LinearOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let a: u64 = Morton::encode(Vector3::new(1, 2, 3));
+    /// let b: u64 = Morton::encode(Vector3::new(4, 5, 6));
+    /// let mut first = LinearOctree::<&str, u64>::new();
+    /// first.insert(a, "a");
+    /// first.insert(b, "b");
+    /// let mut second = LinearOctree::<&str, u64>::new();
+    /// second.insert(b, "b");
+    /// second.insert(a, "a");
+    /// assert!(first == second);
+    /// second.insert(a, "c");
+    /// assert!(first != second);
+    /// ```
+    fn eq(&self, other: &Self) -> bool {
+        // The internal nodes only index the leaves, so the leaves are all that needs to be compared.
+        self.leaves == other.leaves
+    }
+}
+
+impl<T, M> Eq for LinearOctree<T, M>
+where
+    T: Eq,
+    M: Morton,
+{
+}
+
+/// The items are written as a sequence of `(morton, item)` pairs in z-order. The internal nodes are keyed by regions,
+/// which many formats can't use as map keys, so they are rebuilt with `from_sorted` when deserializing instead.
+impl<T, M> serde::Serialize for LinearOctree<T, M>
+where
+    T: serde::Serialize,
+    M: Morton + serde::Serialize,
+{
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    where
+        Ser: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter_explore(|_| true))
+    }
+}
+
+impl<'de, T, M> serde::Deserialize<'de> for LinearOctree<T, M>
+where
+    T: serde::Deserialize<'de>,
+    M: Morton + serde::Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        let items = <Vec<(M, T)> as serde::Deserialize>::deserialize(deserializer)?;
+        let sorted = items.windows(2).all(|w| w[0].0 < w[1].0);
+        if !sorted
+            || items
+                .iter()
+                .any(|&(m, _)| m & M::unused_bits() != M::zero())
+        {
+            return Err(D::Error::custom("octree has an invalid or unsorted morton"));
+        }
+        Ok(Self::from_sorted(items))
+    }
+}
+
+/// The error given back by `LinearOctree::from_bytes` when a blob can't be decoded.
+#[derive(Debug)]
+pub enum FromBytesError {
+    /// The blob ended before everything that it describes was read.
+    Truncated,
+    /// The blob continued after everything that it describes was read.
+    TrailingBytes,
+    /// A morton had unused bits set or the mortons were not in strictly ascending order.
+    InvalidMorton,
+    /// An item failed to deserialize.
+    Item(bincode::Error),
+    /// The reader given to `LinearOctree::from_reader` failed.
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for FromBytesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FromBytesError::Truncated => write!(f, "octree blob is truncated"),
+            FromBytesError::TrailingBytes => write!(f, "octree blob has trailing bytes"),
+            FromBytesError::InvalidMorton => {
+                write!(f, "octree blob has an invalid or unsorted morton")
+            }
+            FromBytesError::Item(e) => write!(f, "octree blob has an invalid item: {}", e),
+            FromBytesError::Io(e) => write!(f, "octree blob could not be read: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FromBytesError {}
+
+/// Fills `buf` from `reader` for `LinearOctree::from_reader`, where running out of bytes means the blob is truncated.
+fn read_exact<R>(reader: &mut R, buf: &mut [u8]) -> Result<(), FromBytesError>
+where
+    R: std::io::Read,
+{
+    reader.read_exact(buf).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::UnexpectedEof {
+            FromBytesError::Truncated
+        } else {
+            FromBytesError::Io(e)
+        }
+    })
+}
+
+/// Reads a little-endian `u64` from `reader` for `LinearOctree::from_reader`.
+fn read_u64<R>(reader: &mut R) -> Result<u64, FromBytesError>
+where
+    R: std::io::Read,
+{
+    let mut buf = [0; 8];
+    read_exact(reader, &mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Gets the number of levels, starting from the top, that two mortons have in common.
+fn shared_levels<M>(a: M, b: M) -> usize
+where
+    M: Morton,
+{
+    common_ancestor(a, b).1 as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_vector(rng: &mut SmallRng, bound: f64) -> Vector3<f64> {
+        Vector3::new(
+            rng.gen_range(-bound, bound),
+            rng.gen_range(-bound, bound),
+            rng.gen_range(-bound, bound),
+        )
+    }
+
+    fn random_tree(
+        rng: &mut SmallRng,
+        region: LeveledRegion,
+        n: usize,
+    ) -> (LinearOctree<usize, u64>, Vec<u64>) {
+        let mut tree = LinearOctree::new();
+        let mut mortons = Vec::new();
+        for i in 0..n {
+            let morton = region.discretize(random_vector(rng, 1.0)).unwrap();
+            tree.insert(morton, i);
+            mortons.push(morton);
+        }
+        mortons.sort();
+        mortons.dedup();
+        (tree, mortons)
+    }
+
+    #[test]
+    fn test_nearest_brute_force() {
+        let mut rng = SmallRng::from_seed([1; 16]);
+        let region = LeveledRegion(0);
+        let (tree, mortons) = random_tree(&mut rng, region, 500);
+        for _ in 0..200 {
+            // Some of these queries are outside of the region.
+            let point = random_vector(&mut rng, 3.0);
+            let distance = |m: u64| (region.undiscretize::<f64, u64>(m) - point).norm();
+            let (m, _) = tree.nearest(region, point).unwrap();
+            let brute = mortons
+                .iter()
+                .map(|&m| distance(m))
+                .fold(std::f64::INFINITY, f64::min);
+            assert!((distance(m) - brute).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_nearest_position() {
+        let mut rng = SmallRng::from_seed([32; 16]);
+        let region = LeveledRegion(0);
+        let (tree, _) = random_tree(&mut rng, region, 300);
+        for _ in 0..100 {
+            let point = random_vector(&mut rng, 2.0);
+            let (m, _) = tree.nearest(region, point).unwrap();
+            let (position, distance) = tree.nearest_position(region, point).unwrap();
+            assert_eq!(position, region.undiscretize::<f64, u64>(m));
+            assert!((distance - (position - point).norm()).abs() < 1e-12);
+        }
+        assert!(LinearOctree::<usize, u64>::new()
+            .nearest_position(region, Vector3::new(0.0, 0.0, 0.0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_k_nearest_brute_force() {
+        let mut rng = SmallRng::from_seed([2; 16]);
+        let region = LeveledRegion(0);
+        let (tree, mortons) = random_tree(&mut rng, region, 300);
+        for k in &[0, 1, 5, 40, 1000] {
+            let point = random_vector(&mut rng, 1.5);
+            let distance = |m: u64| (region.undiscretize::<f64, u64>(m) - point).norm();
+            let found = tree.k_nearest(region, point, *k);
+            let mut brute: Vec<f64> = mortons.iter().map(|&m| distance(m)).collect();
+            brute.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            brute.truncate(*k);
+            assert_eq!(found.len(), brute.len());
+            for (&(m, _, d), &b) in found.iter().zip(brute.iter()) {
+                assert!((d - b).abs() < 1e-12);
+                assert!((distance(m) - b).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_iter_by_distance_brute_force() {
+        let mut rng = SmallRng::from_seed([36; 16]);
+        let region = LeveledRegion(0);
+        let (tree, mortons) = random_tree(&mut rng, region, 300);
+        for _ in 0..10 {
+            let point = random_vector(&mut rng, 1.5);
+            let distance = |m: u64| (region.undiscretize::<f64, u64>(m) - point).norm();
+            let found: Vec<u64> = tree
+                .iter_by_distance(region, point)
+                .map(|(m, _)| m)
+                .collect();
+            let mut brute = mortons.clone();
+            brute.sort_by(|&a, &b| {
+                distance(a)
+                    .partial_cmp(&distance(b))
+                    .unwrap()
+                    .then(a.cmp(&b))
+            });
+            assert_eq!(found.len(), brute.len());
+            for (&m, &b) in found.iter().zip(brute.iter()) {
+                assert!((distance(m) - distance(b)).abs() < 1e-12);
+            }
+            // Taking a prefix gives the same items as `k_nearest`.
+            let nearest: Vec<u64> = tree
+                .k_nearest(region, point, 20)
+                .iter()
+                .map(|&(m, _, _)| m)
+                .collect();
+            assert_eq!(found[..20].to_vec(), nearest);
+        }
+        assert_eq!(
+            LinearOctree::<usize, u64>::new()
+                .iter_by_distance(region, Vector3::new(0.0, 0.0, 0.0))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_count_matches_queries() {
+        let mut rng = SmallRng::from_seed([37; 16]);
+        let region = LeveledRegion(0);
+        let (tree, _) = random_tree(&mut rng, region, 1000);
+        for _ in 0..50 {
+            let a = random_vector(&mut rng, 1.2);
+            let b = random_vector(&mut rng, 1.2);
+            let min = Vector3::from_iterator((0..3).map(|i| a[i].min(b[i])));
+            let max = Vector3::from_iterator((0..3).map(|i| a[i].max(b[i])));
+            assert_eq!(
+                tree.count_box(region, min, max),
+                tree.query_box(region, min, max).count()
+            );
+
+            let radius = rng.gen_range(0.0, 1.5);
+            assert_eq!(
+                tree.count_sphere(region, a, radius),
+                tree.query_sphere(region, a, radius).count()
+            );
+        }
+        let (min, max) = (Vector3::new(-2.0, -2.0, -2.0), Vector3::new(2.0, 2.0, 2.0));
+        assert_eq!(tree.count_box(region, min, max), tree.len());
+        assert_eq!(tree.count_box(region, max, min), 0);
+        assert_eq!(tree.count_sphere(region, Vector3::<f64>::zeros(), -1.0), 0);
+    }
+
+    #[test]
+    fn test_sample_distribution() {
+        let mut tree = LinearOctree::<usize, u64>::new();
+        for i in 0..10 {
+            tree.insert(u64::encode(Vector3::new(i, 2 * i, 0)), i as usize);
+        }
+        // Every other item has no weight and the rest have a weight of their index.
+        let sampler = tree.sample_weighted(|&i| if i % 2 == 0 { 0.0 } else { i as f64 });
+        assert_eq!(sampler.total(), 25.0);
+
+        let mut rng = SmallRng::from_seed([38; 16]);
+        let mut uniform = [0usize; 10];
+        let mut weighted = [0usize; 10];
+        for _ in 0..25000 {
+            uniform[*tree.sample(&mut rng).unwrap().1] += 1;
+            weighted[*sampler.sample(&mut rng).unwrap().1] += 1;
+        }
+        for i in 0..10 {
+            assert!(uniform[i] > 2200 && uniform[i] < 2800);
+            if i % 2 == 0 {
+                assert_eq!(weighted[i], 0);
+            } else {
+                let expected = 1000 * i;
+                assert!(weighted[i] > expected * 8 / 10 && weighted[i] < expected * 12 / 10);
+            }
+        }
+
+        // The same seed draws the same items.
+        let draw = |seed: u8| -> Vec<u64> {
+            let mut rng = SmallRng::from_seed([seed; 16]);
+            (0..20)
+                .map(|_| sampler.sample(&mut rng).unwrap().0)
+                .chain((0..20).map(|_| tree.sample(&mut rng).unwrap().0))
+                .collect()
+        };
+        assert_eq!(draw(39), draw(39));
+        // Uniform sampling gives back the item at a random index in z-order.
+        let mut rng_a = SmallRng::from_seed([40; 16]);
+        let mut rng_b = SmallRng::from_seed([40; 16]);
+        for _ in 0..20 {
+            let ix = rng_b.gen_range(0, tree.len());
+            assert_eq!(tree.sample(&mut rng_a), tree.iter_explore(|_| true).nth(ix));
+        }
+
+        let empty = LinearOctree::<usize, u64>::new();
+        assert!(empty.sample(&mut rng).is_none());
+        assert!(empty.sample_weighted(|_| 1.0).sample(&mut rng).is_none());
+        assert!(tree.sample_weighted(|_| 0.0).sample(&mut rng).is_none());
+    }
+
+    #[test]
+    fn test_lod_cells() {
+        let mut rng = SmallRng::from_seed([39; 16]);
+        let (tree, mortons) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        for &max_items in &[0, 1, 7, 50, 1000] {
+            let cells: Vec<(u64, u32, usize)> = tree.lod_cells(max_items).collect();
+            assert!(cells.windows(2).all(|w| w[0].0 < w[1].0));
+            assert_eq!(
+                cells.iter().map(|&(_, _, count)| count).sum::<usize>(),
+                mortons.len()
+            );
+            for &(prefix, level, count) in &cells {
+                assert_eq!(tree.subtree(prefix, level).count(), count);
+                assert!(count <= max_items.max(1));
+                // The parent was too big, or the cell would have been merged into it.
+                if level > 0 {
+                    assert!(tree.subtree(prefix, level - 1).count() > max_items);
+                }
+            }
+        }
+        assert_eq!(
+            tree.lod_cells(1000).collect::<Vec<_>>(),
+            vec![(0, 0, mortons.len())]
+        );
+        assert_eq!(LinearOctree::<usize, u64>::new().lod_cells(4).count(), 0);
+    }
+
+    #[test]
+    fn test_positioned_brute_force() {
+        let mut rng = SmallRng::from_seed([18; 16]);
+        let region = LeveledRegion(0);
+        let mut tree = LinearOctree::<Vector3<f64>, u64>::new();
+        let points: Vec<Vector3<f64>> = (0..300).map(|_| random_vector(&mut rng, 1.0)).collect();
+        for &point in &points {
+            tree.insert(region.discretize(point).unwrap(), point);
+        }
+        for k in &[0, 1, 5, 40, 1000] {
+            let center = random_vector(&mut rng, 1.5);
+            let found = tree.k_nearest_positioned(region, center, *k);
+            let mut brute: Vec<f64> = points.iter().map(|&p| (p - center).norm()).collect();
+            brute.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            brute.truncate(*k);
+            assert_eq!(found.len(), brute.len());
+            for (&(_, item, d), &b) in found.iter().zip(brute.iter()) {
+                assert!((d - b).abs() < 1e-12);
+                assert!(((*item - center).norm() - b).abs() < 1e-12);
+            }
+
+            let radius = rng.gen_range(0.0, 1.0);
+            let mut found: Vec<Vector3<f64>> = tree
+                .query_sphere_positioned(region, center, radius)
+                .map(|(_, &item)| item)
+                .collect();
+            let mut brute: Vec<Vector3<f64>> = points
+                .iter()
+                .cloned()
+                .filter(|&p| super::super::distance2(p, center) <= radius * radius)
+                .collect();
+            let order = |a: &Vector3<f64>, b: &Vector3<f64>| {
+                a.as_slice().partial_cmp(b.as_slice()).unwrap()
+            };
+            found.sort_by(order);
+            brute.sort_by(order);
+            assert_eq!(found, brute);
+        }
+    }
+
+    #[test]
+    fn test_k_nearest_ties() {
+        let region = LeveledRegion(0);
+        let point: Vector3<f64> =
+            region.undiscretize(region.discretize::<f64, u64>(Vector3::zeros()).unwrap());
+        let mut tree = LinearOctree::<usize, u64>::new();
+        for (i, offset) in [
+            Vector3::new(0.5, 0.0, 0.0),
+            Vector3::new(-0.5, 0.0, 0.0),
+            Vector3::new(0.0, 0.5, 0.0),
+            Vector3::new(0.0, 0.0, -0.5),
+        ]
+        .iter()
+        .enumerate()
+        {
+            tree.insert(region.discretize(point + offset).unwrap(), i);
+        }
+        let found = tree.k_nearest(region, point, 3);
+        assert_eq!(found.len(), 3);
+        assert!(found.iter().all(|&(_, _, d)| d == 0.5));
+        assert!(found.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_query_box_brute_force() {
+        let mut rng = SmallRng::from_seed([3; 16]);
+        let region = LeveledRegion(0);
+        let (tree, mortons) = random_tree(&mut rng, region, 500);
+        let half_width = 2.0f64.powi(-(u64::dim_bits() as i32));
+        for _ in 0..50 {
+            let a = random_vector(&mut rng, 1.2);
+            let b = random_vector(&mut rng, 1.2);
+            let min = Vector3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+            let max = Vector3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+            let mut found: Vec<u64> = tree.query_box(region, min, max).map(|(m, _)| m).collect();
+            found.sort();
+            let brute: Vec<u64> = mortons
+                .iter()
+                .cloned()
+                .filter(|&m| {
+                    let center: Vector3<f64> = region.undiscretize(m);
+                    (0..3).all(|i| {
+                        center[i] - half_width <= max[i] && center[i] + half_width > min[i]
+                    })
+                })
+                .collect();
+            assert_eq!(found, brute);
+        }
+    }
+
+    #[test]
+    fn test_query_box_degenerate() {
+        let region = LeveledRegion(0);
+        let mut tree = LinearOctree::<usize, u64>::new();
+        let point = Vector3::new(0.25, -0.5, 0.125);
+        tree.insert(region.discretize(point).unwrap(), 0);
+        tree.insert(region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap(), 1);
+        let found: Vec<usize> = tree
+            .query_box(region, point, point)
+            .map(|(_, &i)| i)
+            .collect();
+        assert_eq!(found, vec![0]);
+        let inverted = point.map(|n| n - 0.5);
+        assert_eq!(tree.query_box(region, point, inverted).count(), 0);
+    }
+
+    #[test]
+    fn test_query_sphere_brute_force() {
+        let mut rng = SmallRng::from_seed([4; 16]);
+        let region = LeveledRegion(0);
+        let (tree, mortons) = random_tree(&mut rng, region, 500);
+        let half_width = 2.0f64.powi(-(u64::dim_bits() as i32));
+        for _ in 0..50 {
+            let center = random_vector(&mut rng, 1.2);
+            let radius = rng.gen_range(0.0, 0.8);
+            let mut found: Vec<u64> = tree
+                .query_sphere(region, center, radius)
+                .map(|(m, _)| m)
+                .collect();
+            found.sort();
+            let brute: Vec<u64> = mortons
+                .iter()
+                .cloned()
+                .filter(|&m| {
+                    let cell: Vector3<f64> = region.undiscretize(m);
+                    let distance2: f64 = (0..3)
+                        .map(|i| {
+                            let delta = (center[i] - cell[i]).abs() - half_width;
+                            delta.max(0.0).powi(2)
+                        })
+                        .sum();
+                    distance2 <= radius * radius
+                })
+                .collect();
+            assert_eq!(found, brute);
+        }
+    }
+
+    #[test]
+    fn test_query_sphere_radius_extremes() {
+        let mut rng = SmallRng::from_seed([5; 16]);
+        let region = LeveledRegion(0);
+        let (tree, mortons) = random_tree(&mut rng, region, 100);
+        assert_eq!(
+            tree.query_sphere(region, Vector3::new(0.0, 0.0, 0.0), 4.0)
+                .count(),
+            mortons.len()
+        );
+        let center: Vector3<f64> = region.undiscretize(mortons[7]);
+        let found: Vec<u64> = tree
+            .query_sphere(region, center, 0.0)
+            .map(|(m, _)| m)
+            .collect();
+        assert_eq!(found, vec![mortons[7]]);
+    }
+
+    #[test]
+    fn test_ray_cells_brute_force() {
+        let mut rng = SmallRng::from_seed([9; 16]);
+        let region = LeveledRegion(0);
+        for _ in 0..20 {
+            // Half of the rays start inside of the region.
+            let origin = random_vector(&mut rng, 1.5);
+            let dir = random_vector(&mut rng, 1.0);
+            let (mut tree, _) = random_tree(&mut rng, region, 200);
+            // Put some items on the ray so that it is guaranteed to hit something most of the time.
+            for i in 0..20 {
+                let point = origin + dir * rng.gen_range(0.0, 3.0);
+                if let Some(morton) = region.discretize(point) {
+                    tree.insert(morton, 1000 + i);
+                }
+            }
+            let entry = |m: u64| {
+                let (min, max) = region.region_bounds(MortonRegion {
+                    morton: m,
+                    level: u64::dim_bits(),
+                });
+                super::super::ray_interval(origin, dir, min, max).map(|(entry, _)| entry)
+            };
+            let found: Vec<u64> = tree
+                .ray_cells(region, origin, dir)
+                .map(|(m, _)| m)
+                .collect();
+            let entries: Vec<f64> = found.iter().map(|&m| entry(m).unwrap()).collect();
+            assert!(entries.windows(2).all(|w| w[0] <= w[1]));
+
+            let mut found = found;
+            found.sort();
+            let brute: Vec<u64> = tree
+                .iter_explore(|_| true)
+                .map(|(m, _)| m)
+                .filter(|&m| entry(m).is_some())
+                .collect();
+            assert_eq!(found, brute);
+        }
+    }
+
+    #[test]
+    fn test_ray_first_brute_force() {
+        let mut rng = SmallRng::from_seed([26; 16]);
+        let region = LeveledRegion(0);
+        let (mut tree, _) = random_tree(&mut rng, region, 200);
+        for _ in 0..50 {
+            let origin = random_vector(&mut rng, 1.5);
+            let dir = random_vector(&mut rng, 1.0);
+            if let Some(morton) = region.discretize(origin + dir * rng.gen_range(0.0, 3.0)) {
+                tree.insert(morton, 1000);
+            }
+            let entry = |m: u64| {
+                let (min, max) = region.region_bounds(MortonRegion {
+                    morton: m,
+                    level: u64::dim_bits(),
+                });
+                super::super::ray_interval(origin, dir, min, max).map(|(entry, _)| entry)
+            };
+            let brute = tree
+                .iter_explore(|_| true)
+                .filter_map(|(m, _)| entry(m))
+                .fold(None, |best: Option<f64>, t| {
+                    Some(best.map_or(t, |b| b.min(t)))
+                });
+            match tree.ray_first(region, origin, dir) {
+                Some((m, _, t)) => {
+                    assert_eq!(Some(t), entry(m));
+                    assert_eq!(Some(t), brute);
+                }
+                None => assert_eq!(brute, None),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ray_cells_miss() {
+        let mut rng = SmallRng::from_seed([10; 16]);
+        let region = LeveledRegion(0);
+        let (tree, _) = random_tree(&mut rng, region, 200);
+        let origin = Vector3::new(-2.0, 0.0, 0.0);
+        // This points away from the region.
+        assert_eq!(
+            tree.ray_cells(region, origin, Vector3::new(-1.0, 0.0, 0.0))
+                .count(),
+            0
+        );
+        // This passes above the region.
+        assert_eq!(
+            tree.ray_cells(region, origin, Vector3::new(1.0, 2.0, 0.0))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_query_frustum_brute_force() {
+        let mut rng = SmallRng::from_seed([11; 16]);
+        let region = LeveledRegion(0);
+        let (tree, mortons) = random_tree(&mut rng, region, 500);
+        for _ in 0..50 {
+            // Every plane passes near the origin so that the frustum usually contains something.
+            let mut planes = [(Vector3::zeros(), 0.0); 6];
+            for plane in planes.iter_mut() {
+                let normal = random_vector(&mut rng, 1.0);
+                let point = random_vector(&mut rng, 0.5);
+                *plane = (normal, -normal.dot(&point));
+            }
+            let mut found: Vec<u64> = tree.query_frustum(region, planes).map(|(m, _)| m).collect();
+            found.sort();
+            let brute: Vec<u64> = mortons
+                .iter()
+                .cloned()
+                .filter(|&m| {
+                    let center: Vector3<f64> = region.undiscretize(m);
+                    planes.iter().all(|&(normal, offset)| {
+                        (0..3).fold(offset, |acc, i| acc + normal[i] * center[i]) >= 0.0
+                    })
+                })
+                .collect();
+            assert_eq!(found, brute);
+        }
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut rng = SmallRng::from_seed([12; 16]);
+        let (tree, _) = random_tree(&mut rng, LeveledRegion(0), 300);
+        let bytes = tree.to_bytes().unwrap();
+        let decoded = LinearOctree::<usize, u64>::from_bytes(&bytes).unwrap();
+        let expected: Vec<(u64, &usize)> = tree.iter_explore(|_| true).collect();
+        let found: Vec<(u64, &usize)> = decoded.iter_explore(|_| true).collect();
+        assert_eq!(found, expected);
+        assert_eq!(decoded.internals, tree.internals);
+        // The blob doesn't depend on the order that the tree was built in.
+        assert_eq!(decoded.to_bytes().unwrap(), bytes);
+
+        let empty = LinearOctree::<usize, u64>::new();
+        let decoded = LinearOctree::<usize, u64>::from_bytes(&empty.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.iter_explore(|_| true).count(), 0);
+    }
+
+    #[test]
+    fn test_bytes_invalid() {
+        let mut tree = LinearOctree::<usize, u64>::new();
+        tree.insert(1, 10);
+        tree.insert(2, 20);
+        let bytes = tree.to_bytes().unwrap();
+        for len in 0..bytes.len() {
+            match LinearOctree::<usize, u64>::from_bytes(&bytes[..len]) {
+                Err(FromBytesError::Truncated) => {}
+                other => panic!(
+                    "expected truncation at {} bytes, got {:?}",
+                    len,
+                    other.err()
+                ),
+            }
+        }
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        match LinearOctree::<usize, u64>::from_bytes(&trailing) {
+            Err(FromBytesError::TrailingBytes) => {}
+            other => panic!("unexpected result {:?}", other.err()),
+        }
+
+        // Swap the two mortons so that they are out of order.
+        let mut unsorted = bytes.clone();
+        unsorted[8] = 2;
+        unsorted[16] = 1;
+        match LinearOctree::<usize, u64>::from_bytes(&unsorted) {
+            Err(FromBytesError::InvalidMorton) => {}
+            other => panic!("unexpected result {:?}", other.err()),
+        }
+
+        // Shorten the last item by a byte so that it no longer holds a whole `usize`.
+        let mut bad_item = bytes;
+        bad_item.pop();
+        let len_at = bad_item.len() - 7 - 8;
+        bad_item[len_at] = 7;
+        match LinearOctree::<usize, u64>::from_bytes(&bad_item) {
+            Err(FromBytesError::Item(_)) => {}
+            other => panic!("unexpected result {:?}", other.err()),
+        }
+    }
+
+    /// Gives back at most a few bytes per read, like a slow stream, and fails once it runs out.
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        fail: bool,
+    }
+
+    impl<'a> std::io::Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.bytes.is_empty() && self.fail {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "stream failed",
+                ));
+            }
+            let len = buf.len().min(self.bytes.len()).min(3);
+            buf[..len].copy_from_slice(&self.bytes[..len]);
+            self.bytes = &self.bytes[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_from_reader_matches_from_bytes() {
+        let mut rng = SmallRng::from_seed([24; 16]);
+        let (tree, _) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        let bytes = tree.to_bytes().unwrap();
+        let streamed = LinearOctree::<usize, u64>::from_reader(Trickle {
+            bytes: &bytes,
+            fail: false,
+        })
+        .unwrap();
+        assert!(streamed == tree);
+        assert!(streamed.internals == tree.internals);
+
+        // A blob followed by other data can be read by limiting the reader.
+        let mut followed = bytes.clone();
+        followed.extend_from_slice(b"more");
+        let mut reader = std::io::Cursor::new(&followed);
+        let limited = (&mut reader).take(bytes.len() as u64);
+        assert!(LinearOctree::<usize, u64>::from_reader(limited).unwrap() == tree);
+        assert!(LinearOctree::<usize, u64>::from_reader(&followed[..]).is_err());
+
+        match LinearOctree::<usize, u64>::from_reader(Trickle {
+            bytes: &bytes[..bytes.len() / 2],
+            fail: true,
+        }) {
+            Err(FromBytesError::Io(_)) => {}
+            other => panic!("unexpected result {:?}", other.err()),
+        }
+        match LinearOctree::<usize, u64>::from_reader(&bytes[..bytes.len() / 2]) {
+            Err(FromBytesError::Truncated) => {}
+            other => panic!("unexpected result {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_from_sorted_matches_insert() {
+        let mut rng = SmallRng::from_seed([6; 16]);
+        for &n in &[0, 1, 2, 3, 100, 2000] {
+            let mut items: Vec<(u64, usize)> = (0..n)
+                .map(|i| (rng.gen::<u64>() & u64::used_bits(), i))
+                .collect();
+            // Add some duplicate mortons to make sure the last one wins.
+            if n > 10 {
+                items.push((items[3].0, n));
+                items.push((items[7].0, n + 1));
+            }
+
+            let mut inserted = LinearOctree::new();
+            for &(m, i) in &items {
+                inserted.insert(m, i);
+            }
+
+            items.sort_by_key(|&(m, _)| m);
+            let built = LinearOctree::from_sorted(items);
+            assert!(built.leaves == inserted.leaves);
+            assert!(built.internals == inserted.internals);
+            assert!(built.counts == inserted.counts);
+        }
+    }
+
+    #[test]
+    fn test_from_sorted_dedup_by() {
+        let region = LeveledRegion(0);
+        // All of these points are in the same leaf cell.
+        let points = [0.25, 0.25 + 1e-9, 0.25 + 2e-9, 0.25 + 3e-9];
+        let mut items: Vec<(u64, Vec<usize>)> = points
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| (region.discretize(Vector3::new(p, p, p)).unwrap(), vec![i]))
+            .collect();
+        items.push((
+            region.discretize(Vector3::new(-0.5, 0.5, 0.5)).unwrap(),
+            vec![4],
+        ));
+        items.sort_by_key(|&(m, _)| m);
+        let cell = items.iter().find(|(_, v)| v[0] == 0).unwrap().0;
+
+        let tree = LinearOctree::from_sorted_dedup_by(items, |mut a: Vec<usize>, b: Vec<usize>| {
+            a.extend(b);
+            a
+        });
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.get(cell), Some(&vec![0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn test_merge_matches_single_build() {
+        let mut rng = SmallRng::from_seed([13; 16]);
+        let (whole, mortons) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        // Split the tree into two interleaved halves so that they share most of their regions.
+        let mut left = LinearOctree::new();
+        let mut right = LinearOctree::new();
+        for (i, &m) in mortons.iter().enumerate() {
+            let item = *whole.get(m).unwrap();
+            if i % 2 == 0 {
+                left.insert(m, item);
+            } else {
+                right.insert(m, item);
+            }
+        }
+        let merged = left.merge(right, |_, _| panic!("the halves are disjoint"));
+        assert!(merged.leaves == whole.leaves);
+        assert!(merged.internals == whole.internals);
+
+        let merged = LinearOctree::new().merge(merged, |_, _| unreachable!());
+        assert!(merged.leaves == whole.leaves);
+    }
+
+    #[test]
+    fn test_merge_collisions() {
+        let mut a = LinearOctree::<Vec<u8>, u64>::new();
+        let mut b = LinearOctree::<Vec<u8>, u64>::new();
+        a.insert(5, vec![1]);
+        a.insert(9, vec![2]);
+        b.insert(9, vec![3]);
+        b.insert(12, vec![4]);
+        let merged = a.merge(b, |mut a, b| {
+            a.extend(b);
+            a
+        });
+        let found: Vec<(u64, Vec<u8>)> = merged
+            .iter_explore(|_| true)
+            .map(|(m, item)| (m, item.clone()))
+            .collect();
+        assert_eq!(found, vec![(5, vec![1]), (9, vec![2, 3]), (12, vec![4])]);
+    }
+
+    #[test]
+    fn test_eq_ignores_insertion_order() {
+        let mut rng = SmallRng::from_seed([22; 16]);
+        let (tree, mortons) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        let items: Vec<(u64, usize)> = mortons
+            .iter()
+            .map(|&m| (m, *tree.get(m).unwrap()))
+            .collect();
+
+        let mut reversed = LinearOctree::new();
+        reversed.extend(items.iter().cloned().rev());
+        assert!(tree == reversed);
+        assert!(tree == LinearOctree::from_sorted(items.clone()));
+        assert!(tree.clone().merge(LinearOctree::new(), |a, _| a) == tree);
+
+        reversed.insert(mortons[0], items[0].1 + 1);
+        assert!(tree != reversed);
+        assert!(tree != LinearOctree::from_sorted(items[1..].to_vec()));
+    }
+
+    #[test]
+    fn test_into_iter_z_order() {
+        let mut rng = SmallRng::from_seed([15; 16]);
+        let (tree, mortons) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        let expected: Vec<(u64, usize)> = mortons
+            .iter()
+            .map(|&m| (m, *tree.get(m).unwrap()))
+            .collect();
+
+        let mut it = tree.clone().into_iter();
+        assert_eq!(it.size_hint(), (expected.len(), Some(expected.len())));
+        it.next();
+        assert_eq!(
+            it.size_hint(),
+            (expected.len() - 1, Some(expected.len() - 1))
+        );
+
+        let items: Vec<(u64, usize)> = tree.into_iter().collect();
+        assert_eq!(items, expected);
+        assert_eq!(LinearOctree::<usize, u64>::new().into_iter().len(), 0);
+    }
+
+    #[test]
+    fn test_buckets_group_by_prefix() {
+        let mut rng = SmallRng::from_seed([17; 16]);
+        let (tree, mortons) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        // Every item is unique, so the morton of an item can be found from the item.
+        let morton_of: std::collections::HashMap<usize, u64> =
+            tree.iter_explore(|_| true).map(|(m, &i)| (i, m)).collect();
+        for &level in &[0, 1, 3, 7, u64::dim_bits() as u32] {
+            let cut = 3 * (u64::dim_bits() - level as usize);
+            let mut expected: Vec<(u64, Vec<u64>)> = Vec::new();
+            for &m in &mortons {
+                let cell = (m >> cut) << cut;
+                match expected.last_mut() {
+                    Some((last, group)) if *last == cell => group.push(m),
+                    _ => expected.push((cell, vec![m])),
+                }
+            }
+            let found: Vec<(u64, Vec<u64>)> = tree
+                .buckets(level)
+                .map(|(cell, items)| (cell, items.map(|i| morton_of[i]).collect()))
+                .collect();
+            assert_eq!(found, expected);
+        }
+        assert_eq!(LinearOctree::<usize, u64>::new().buckets(2).count(), 0);
+    }
+
+    #[test]
+    fn test_try_fold_short_circuits() {
+        use std::cell::Cell;
+
+        /// Stops at the first item above the limit and counts the gathers.
+        struct FirstAbove {
+            limit: usize,
+            gathered: Cell<usize>,
+        }
+
+        impl TryFolder<usize, u64> for FirstAbove {
+            type Sum = Option<usize>;
+
+            fn gather(&self, _: u64, &item: &usize) -> ControlFlow<Self::Sum, Self::Sum> {
+                self.gathered.set(self.gathered.get() + 1);
+                if item > self.limit {
+                    ControlFlow::Break(Some(item))
+                } else {
+                    ControlFlow::Continue(None)
+                }
+            }
+
+            fn fold<I>(&self, _: I) -> ControlFlow<Self::Sum, Self::Sum>
+            where
+                I: Iterator<Item = Self::Sum>,
+            {
+                ControlFlow::Continue(None)
+            }
+        }
+
+        let mut rng = SmallRng::from_seed([19; 16]);
+        let (tree, mortons) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        // The first item in z-order above the limit is where the walk must stop.
+        let items: Vec<usize> = mortons.iter().map(|&m| *tree.get(m).unwrap()).collect();
+        let stop = items.iter().position(|&item| item > 900).unwrap();
+
+        let folder = FirstAbove {
+            limit: 900,
+            gathered: Cell::new(0),
+        };
+        assert_eq!(
+            tree.try_fold(&folder),
+            Some(ControlFlow::Break(Some(items[stop])))
+        );
+        assert_eq!(folder.gathered.get(), stop + 1);
+
+        // A plain `Folder` never stops early.
+        assert_eq!(
+            tree.try_fold(&CountFolder),
+            Some(ControlFlow::Continue(mortons.len()))
+        );
+        assert_eq!(
+            LinearOctree::<usize, u64>::new().try_fold(&CountFolder),
+            None
+        );
+    }
+
+    /// Finds the leaves in a box like `query_box`, while keeping track of what it visited.
+    struct BoxVisitor {
+        min: Vector3<f64>,
+        max: Vector3<f64>,
+        entered: usize,
+        found: Vec<u64>,
+    }
+
+    impl Visitor<usize, u64, f64> for BoxVisitor {
+        fn enter_node(
+            &mut self,
+            prefix: u64,
+            level: u32,
+            (min, max): (Vector3<f64>, Vector3<f64>),
+        ) -> Descent {
+            let cut = 3 * (u64::dim_bits() - level as usize);
+            assert_eq!(prefix, (prefix >> cut) << cut);
+            self.entered += 1;
+            if (0..3).all(|i| min[i] <= self.max[i] && self.min[i] <= max[i]) {
+                Descent::Descend
+            } else {
+                Descent::Skip
+            }
+        }
+
+        fn leaf(&mut self, morton: u64, _: &usize) {
+            self.found.push(morton);
+        }
+    }
+
+    #[test]
+    fn test_visit_matches_query_box() {
+        let mut rng = SmallRng::from_seed([25; 16]);
+        let region = LeveledRegion(0);
+        let (tree, mortons) = random_tree(&mut rng, region, 1000);
+
+        let mut all = BoxVisitor {
+            min: Vector3::new(-1.0, -1.0, -1.0),
+            max: Vector3::new(1.0, 1.0, 1.0),
+            entered: 0,
+            found: vec![],
+        };
+        tree.visit(region, &mut all);
+        assert_eq!(all.found, mortons);
+
+        for _ in 0..20 {
+            let a = random_vector(&mut rng, 1.0);
+            let b = random_vector(&mut rng, 1.0);
+            let min = Vector3::from_iterator((0..3).map(|i| a[i].min(b[i])));
+            let max = Vector3::from_iterator((0..3).map(|i| a[i].max(b[i])));
+            let mut visitor = BoxVisitor {
+                min,
+                max,
+                entered: 0,
+                found: vec![],
+            };
+            tree.visit(region, &mut visitor);
+            assert!(visitor.entered <= all.entered);
+            // A leaf stored high in the tree can be outside of the box, which `query_box` filters out.
+            let expected: Vec<u64> = tree.query_box(region, min, max).map(|(m, _)| m).collect();
+            let found: Vec<u64> = visitor
+                .found
+                .into_iter()
+                .filter(|&m| {
+                    let (low, high) = region.cell_bounds::<f64, u64>(m, u64::dim_bits() as u32);
+                    (0..3).all(|i| low[i] <= max[i] && min[i] <= high[i])
+                })
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn test_pairs_within_brute_force() {
+        let mut rng = SmallRng::from_seed([23; 16]);
+        let region = LeveledRegion(0);
+        let (tree, mortons) = random_tree(&mut rng, region, 300);
+        for &radius in &[0.0, 0.05, 0.2, 0.5] {
+            let mut expected = Vec::new();
+            for (i, &a) in mortons.iter().enumerate() {
+                for &b in &mortons[i + 1..] {
+                    let (pa, pb): (Vector3<f64>, Vector3<f64>) =
+                        (region.undiscretize(a), region.undiscretize(b));
+                    if super::super::distance2(pa, pb) <= radius * radius {
+                        expected.push((a, b));
+                    }
+                }
+            }
+            let mut found: Vec<(u64, u64)> = tree.pairs_within(region, radius).collect();
+            found.sort();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn test_subtree_brute_force() {
+        let mut rng = SmallRng::from_seed([21; 16]);
+        let (tree, mortons) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        for level in 0..4 {
+            for _ in 0..20 {
+                let prefix = mortons[rng.gen_range(0, mortons.len())];
+                let cell = MortonRegion {
+                    morton: prefix,
+                    level: level as usize,
+                };
+                let expected: Vec<u64> = mortons
+                    .iter()
+                    .cloned()
+                    .filter(|&m| cell.contains(m))
+                    .collect();
+                let found: Vec<u64> = tree.subtree(prefix, level).map(|(m, _)| m).collect();
+                assert_eq!(found, expected);
+            }
+        }
+        // A leaf stored high in the tree is excluded from cells it isn't in.
+        let mut tree = LinearOctree::<usize, u64>::new();
+        tree.insert(0, 0);
+        assert_eq!(tree.subtree(0, 5).count(), 1);
+        assert_eq!(tree.subtree(1 << 48, 5).count(), 0);
+    }
+
+    #[test]
+    fn test_clear_keeps_capacity() {
+        let mut rng = SmallRng::from_seed([20; 16]);
+        let (mut tree, _) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        let leaves = tree.leaves.capacity();
+        let internals = tree.internals.capacity();
+        tree.clear();
+        assert!(tree.is_empty());
+        assert!(tree.leaves.capacity() >= leaves);
+        assert!(tree.internals.capacity() >= internals);
+        assert!(tree.internals == LinearOctree::<usize, u64>::new().internals);
+
+        // The cleared octree is the same as a new one once it is filled again.
+        let (fresh, mortons) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        tree.extend(mortons.iter().map(|&m| (m, *fresh.get(m).unwrap())));
+        assert!(tree.leaves == fresh.leaves);
+        assert!(tree.internals == fresh.internals);
+    }
+
+    #[test]
+    fn test_intersect_cells_brute_force() {
+        let mut rng = SmallRng::from_seed([14; 16]);
+        let (a, a_mortons) = random_tree(&mut rng, LeveledRegion(0), 300);
+        let (mut b, _) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        // Make sure that some of the cells are shared.
+        for &m in a_mortons.iter().step_by(3) {
+            b.insert(m, 0);
+        }
+        let brute: Vec<u64> = a_mortons
+            .iter()
+            .cloned()
+            .filter(|&m| b.get(m).is_some())
+            .collect();
+        assert!(!brute.is_empty());
+        assert_eq!(a.intersect_cells(&b).collect::<Vec<_>>(), brute);
+        assert_eq!(b.intersect_cells(&a).collect::<Vec<_>>(), brute);
+
+        let empty = LinearOctree::<(), u64>::new();
+        assert_eq!(a.intersect_cells(&empty).count(), 0);
+        assert_eq!(empty.intersect_cells(&a).count(), 0);
+        assert_eq!(empty.intersect_cells(&empty).count(), 0);
+    }
+
+    #[test]
+    fn test_difference_cells_brute_force() {
+        let mut rng = SmallRng::from_seed([27; 16]);
+        let (a, a_mortons) = random_tree(&mut rng, LeveledRegion(0), 300);
+        let (mut b, b_mortons) = random_tree(&mut rng, LeveledRegion(0), 300);
+        assert_eq!(a.difference_cells(&b).collect::<Vec<_>>(), a_mortons);
+        for &m in a_mortons.iter().step_by(3) {
+            b.insert(m, 0);
+        }
+        let brute: Vec<u64> = a_mortons
+            .iter()
+            .cloned()
+            .filter(|&m| b.get(m).is_none())
+            .collect();
+        assert!(!brute.is_empty() && brute.len() < a_mortons.len());
+        assert_eq!(a.difference_cells(&b).collect::<Vec<_>>(), brute);
+
+        // The difference and the intersection split the cells of `self` between them.
+        let mut split: Vec<u64> = a
+            .difference_cells(&b)
+            .chain(a.intersect_cells(&b))
+            .collect();
+        split.sort();
+        assert_eq!(split, a_mortons);
+        let only_b = b.difference_cells(&a).count();
+        assert_eq!(only_b, b_mortons.len());
+
+        let empty = LinearOctree::<(), u64>::new();
+        assert_eq!(a.difference_cells(&empty).collect::<Vec<_>>(), a_mortons);
+        assert_eq!(empty.difference_cells(&a).count(), 0);
+        assert_eq!(a.difference_cells(&a).count(), 0);
+    }
+
+    #[test]
+    fn test_retain_matches_insert() {
+        let mut rng = SmallRng::from_seed([33; 16]);
+        let (mut tree, mortons) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        let kept: Vec<u64> = mortons.iter().cloned().step_by(2).collect();
+        let mut expected = LinearOctree::new();
+        for &m in &kept {
+            expected.insert(m, *tree.get(m).unwrap());
+        }
+
+        tree.retain(|m, _| kept.binary_search(m).is_ok());
+        assert_eq!(tree.len(), kept.len());
+        let found: Vec<u64> = tree.iter_explore(|_| true).map(|(m, _)| m).collect();
+        assert_eq!(found, kept);
+        assert!(tree.leaves == expected.leaves);
+        assert!(tree.internals == expected.internals);
+        assert!(tree.counts == expected.counts);
+
+        tree.retain(|_, _| false);
+        assert!(tree.is_empty());
+        assert!(tree.internals == LinearOctree::<usize, u64>::new().internals);
+        assert!(tree.counts.is_empty());
+    }
+
+    #[test]
+    fn test_empty_neighbors_of_cube() {
+        let mut tree = LinearOctree::<(), u64>::new();
+        let inside = |d: u64| (10..14).contains(&d);
+        for x in 10..14 {
+            for y in 10..14 {
+                for z in 10..14 {
+                    tree.insert(u64::encode(Vector3::new(x, y, z)), ());
+                }
+            }
+        }
+
+        let shell: Vec<u64> = tree.empty_neighbors().collect();
+        // Each of the 6 faces of the cube has 16 cells in front of it.
+        assert_eq!(shell.len(), 96);
+        assert!(shell.windows(2).all(|w| w[0] < w[1]));
+        for &m in &shell {
+            let dims = m.decode();
+            let outside: Vec<usize> = (0..3).filter(|&i| !inside(dims[i])).collect();
+            assert_eq!(outside.len(), 1);
+            assert!(dims[outside[0]] == 9 || dims[outside[0]] == 14);
+        }
+
+        // Cells on the edge of the grid have no neighbor past it.
+        let mut corner = LinearOctree::<(), u64>::new();
+        corner.insert(0, ());
+        assert_eq!(corner.empty_neighbors().count(), 3);
+    }
+
+    #[test]
+    fn test_to_dense_grid() {
+        let mut tree = LinearOctree::<(), u64>::new();
+        // Two items share the cell at `(3, 0, 2)` on level 2 and one is at `(0, 1, 0)`.
+        let shift = u64::dim_bits() - 2;
+        let cells = [(3, 0, 2, 0), (3, 0, 2, 9), (0, 1, 0, 1)];
+        for &(x, y, z, low) in &cells {
+            tree.insert(
+                u64::encode(Vector3::new(x << shift, y << shift, (z << shift) + low)),
+                (),
+            );
+        }
+
+        let (grid, side) = tree.to_dense_grid(2).unwrap();
+        assert_eq!(side, 4);
+        assert_eq!(grid.len(), 64);
+        let occupied: Vec<usize> = (0..64).filter(|&i| grid[i]).collect();
+        assert_eq!(occupied, vec![4, 35]);
+
+        assert_eq!(tree.to_dense_grid(0), Some((vec![true], 1)));
+        assert_eq!(
+            LinearOctree::<(), u64>::new().to_dense_grid(1),
+            Some((vec![false; 8], 2))
+        );
+        assert_eq!(tree.to_dense_grid(11), None);
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let mut rng = SmallRng::from_seed([34; 16]);
+        for &n in &[0, 1, 1000] {
+            let mut tree = LinearOctree::with_capacity(n);
+            assert!(tree.capacity() >= n);
+            assert!(tree == LinearOctree::new());
+            let capacity = tree.capacity();
+            for i in 0..n {
+                tree.insert(rng.gen::<u64>() & u64::used_bits(), i);
+            }
+            assert_eq!(tree.capacity(), capacity);
+            assert!(
+                tree.internals == LinearOctree::from_sorted(tree.clone().into_sorted()).internals
+            );
+        }
+    }
+
+    #[test]
+    fn test_fold_spatial() {
+        /// Counts the items and checks that every node is given its own bounds.
+        struct CheckedCount(LeveledRegion);
+
+        impl SpatialFolder<usize, u64, f64> for CheckedCount {
+            type Sum = usize;
+
+            fn gather(&self, _: u64, _: &usize) -> usize {
+                1
+            }
+
+            fn fold<I>(
+                &self,
+                prefix: u64,
+                level: u32,
+                bounds: (Vector3<f64>, Vector3<f64>),
+                it: I,
+            ) -> usize
+            where
+                I: Iterator<Item = usize>,
+            {
+                let node = MortonRegion {
+                    morton: prefix,
+                    level: level as usize,
+                };
+                assert_eq!(bounds, self.0.region_bounds(node));
+                let cut = 3 * (u64::dim_bits() - level as usize);
+                assert_eq!(prefix, (prefix >> cut) << cut);
+                it.sum()
+            }
+        }
+
+        let mut rng = SmallRng::from_seed([35; 16]);
+        let region = LeveledRegion(2);
+        let (tree, mortons) = random_tree(&mut rng, region, 500);
+        assert_eq!(
+            tree.fold_spatial(region, &CheckedCount(region)),
+            Some(mortons.len())
+        );
+        // Any `Folder` works too.
+        assert_eq!(
+            tree.fold_spatial::<f64, _>(region, &CountFolder),
+            tree.fold(&CountFolder)
+        );
+        assert_eq!(
+            LinearOctree::<usize, u64>::new().fold_spatial(region, &CheckedCount(region)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_map_items() {
+        let mut rng = SmallRng::from_seed([28; 16]);
+        let (tree, mortons) = random_tree(&mut rng, LeveledRegion(0), 1000);
+        let mapped = tree.clone().map_items(|&m, i| (m, i * 2));
+        assert!(mapped.internals == tree.internals);
+        let found: Vec<u64> = mapped.iter_explore(|_| true).map(|(m, _)| m).collect();
+        assert_eq!(found, mortons);
+        for &m in &mortons {
+            assert_eq!(mapped.get(m), Some(&(m, tree.get(m).unwrap() * 2)));
+        }
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut tree = LinearOctree::<(), u64>::new();
+        assert!(tree.is_empty());
+        assert_eq!(tree.max_depth(), 0);
+        // These share the first two levels and split on the third.
+        tree.insert(u64::encode(Vector3::new(0, 0, 0)), ());
+        tree.insert(u64::encode(Vector3::new(1 << 18, 0, 0)), ());
+        assert_eq!(tree.max_depth(), 3);
+        // This splits off at the top level, so it doesn't change the depth.
+        tree.insert(u64::encode(Vector3::new(0, 0, 1 << 20)), ());
+        assert_eq!(tree.max_depth(), 3);
+        // These differ only in the last level.
+        tree.insert(u64::encode(Vector3::new(5, 5, 5)), ());
+        tree.insert(u64::encode(Vector3::new(4, 5, 5)), ());
+        assert_eq!(tree.max_depth(), u64::dim_bits() as u32);
+        assert_eq!(tree.len(), 5);
+
+        // The depth must agree with the levels of the regions that the leaves are actually stored in.
+        let deepest = tree
+            .internals
+            .iter()
+            .filter(|(_, m)| !m.is_null())
+            .map(|(region, _)| region.level as u32)
+            .max();
+        assert_eq!(deepest, Some(tree.max_depth()));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_from_points_parallel_matches_sequential() {
+        let mut rng = SmallRng::from_seed([16; 16]);
+        let region = LeveledRegion(1);
+        // The points are spread past the region so that some of them are dropped, but none share a leaf cell.
+        let items: Vec<(Vector3<f64>, usize)> = (0..5000)
+            .map(|i| (random_vector(&mut rng, 3.0), i))
+            .collect();
+        let sequential =
+            LinearOctree::<usize, u64>::from_sorted(region.discretize_sorted(items.clone()));
+        let parallel = LinearOctree::<usize, u64>::from_points_parallel(region, items);
+        assert!(parallel.len() < 5000);
+        assert!(parallel.leaves == sequential.leaves);
+        assert!(parallel.internals == sequential.internals);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_fold_parallel() {
+        struct Center;
+
+        impl Folder<usize, u64> for Center {
+            type Sum = (u64, Vector3<u64>);
+
+            fn gather(&self, m: u64, _: &usize) -> Self::Sum {
+                (1, m.decode())
+            }
+
+            fn fold<I>(&self, it: I) -> Self::Sum
+            where
+                I: Iterator<Item = Self::Sum>,
+            {
+                it.fold((0, Vector3::zeros()), |total, part| {
+                    (total.0 + part.0, total.1 + part.1)
+                })
+            }
+        }
+
+        let mut rng = SmallRng::from_seed([7; 16]);
+        let (tree, mortons) = random_tree(&mut rng, LeveledRegion(0), 2000);
+        let sequential = tree.fold(&Center).unwrap();
+        assert_eq!(sequential.0, mortons.len() as u64);
+        assert_eq!(tree.fold_parallel(&Center), Some(sequential));
+        assert_eq!(
+            LinearOctree::<usize, u64>::new().fold_parallel(&Center),
+            None
+        );
+    }
+
+    #[test]
+    fn test_locate() {
+        let mut rng = SmallRng::from_seed([29; 16]);
+        let region = LeveledRegion(0);
+        let (tree, mortons) = random_tree(&mut rng, region, 500);
+        for &m in &mortons {
+            let (found, &item) = tree
+                .locate(region, region.undiscretize::<f64, u64>(m))
+                .unwrap();
+            assert_eq!(found, m);
+            assert_eq!(Some(&item), tree.get(m));
+        }
+        for _ in 0..200 {
+            // Some of these points are outside of the region.
+            let point = random_vector(&mut rng, 1.5);
+            let expected = region
+                .discretize::<f64, u64>(point)
+                .and_then(|m| tree.get(m).map(|item| (m, item)));
+            assert_eq!(tree.locate(region, point), expected);
+        }
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<LinearOctree<usize, u64>>();
+        assert_send_sync::<LinearOctree<Vec<f64>, u128>>();
+    }
+
+    #[test]
+    fn test_query_sphere_periodic_brute_force() {
+        let mut rng = SmallRng::from_seed([30; 16]);
+        let region = LeveledRegion(0);
+        let (tree, _) = random_tree(&mut rng, region, 500);
+        let axis = [-2.0, 0.0, 2.0];
+        for _ in 0..50 {
+            let center = random_vector(&mut rng, 1.0);
+            let radius = rng.gen_range(0.0, 0.6);
+            let mut found: Vec<u64> = tree
+                .query_sphere_periodic(region, center, radius)
+                .map(|(m, _)| m)
+                .collect();
+            found.sort();
+            // Every image of the sphere in the neighboring copies of the region.
+            let mut brute: Vec<u64> = Vec::new();
+            for &x in &axis {
+                for &y in &axis {
+                    for &z in &axis {
+                        let image = center + Vector3::new(x, y, z);
+                        brute.extend(tree.query_sphere(region, image, radius).map(|(m, _)| m));
+                    }
+                }
+            }
+            brute.sort();
+            brute.dedup();
+            assert_eq!(found, brute);
+            // Shifting the center by a whole period doesn't change anything.
+            let mut shifted: Vec<u64> = tree
+                .query_sphere_periodic(region, center + Vector3::new(4.0, -2.0, 0.0), radius)
+                .map(|(m, _)| m)
+                .collect();
+            shifted.sort();
+            assert_eq!(shifted, found);
+        }
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut rng = SmallRng::from_seed([31; 16]);
+        let (tree, _) = random_tree(&mut rng, LeveledRegion(0), 1000);
+
+        let bytes = bincode::serialize(&tree).unwrap();
+        let decoded: LinearOctree<usize, u64> = bincode::deserialize(&bytes).unwrap();
+        assert!(decoded.leaves == tree.leaves);
+        assert!(decoded.internals == tree.internals);
+
+        let json = serde_json::to_string(&tree).unwrap();
+        let decoded: LinearOctree<usize, u64> = serde_json::from_str(&json).unwrap();
+        assert!(decoded == tree);
+
+        assert!(serde_json::from_str::<LinearOctree<usize, u64>>("[[2,0],[1,1]]").is_err());
+        assert!(serde_json::from_str::<LinearOctree<usize, u64>>("[[1,0],[1,1]]").is_err());
+        let unused = format!("[[{},0]]", 1u64 << 63);
+        assert!(serde_json::from_str::<LinearOctree<usize, u64>>(&unused).is_err());
+    }
+
+    #[test]
+    fn test_nearest_empty() {
+        let tree = LinearOctree::<usize, u64>::new();
+        assert!(tree
+            .nearest(LeveledRegion(0), Vector3::new(0.0, 0.0, 0.0))
+            .is_none());
+    }
+}
@@ -18,7 +18,9 @@ use std::ops::{Deref, DerefMut};
 
 use log::*;
 
-#[derive(Copy, Clone, Debug, Default)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Oct<T> {
     pub children: [T; 8],
 }
@@ -30,9 +32,28 @@ impl<T> Oct<T> {
 }
 
 /// An octree that uses pointers for internal nodes.
+///
+/// Every internal node is one level below its parent, so two leaves that share a long morton prefix are separated by
+/// a chain of nodes with only one occupied child. `compress` collapses each such chain into one node that stores the
+/// skipped morton bits. Inserting or removing expands the compressed nodes on the way to the morton again, so call
+/// `compress` after the tree has been built. For sparse trees, `LinearOctree` also finds any region with a single hash
+/// lookup instead.
+///
+/// This can be serialized with `serde` when `T` and `M` can. The nodes are written as they are, so the same tree
+/// structure comes back when it is deserialized. The changes tracked for `fold_cached` are not written.
+#[derive(Serialize, Deserialize)]
 pub struct PointerOctree<T, M> {
     tree: Internal<T, M>,
     count: usize,
+    /// Tells a `FoldCache` which octree its sums came from.
+    #[serde(skip, default = "next_octree_id")]
+    id: usize,
+    /// The mortons changed most recently, whose regions are stale in any `FoldCache` that hasn't seen them yet.
+    #[serde(skip)]
+    changes: Vec<M>,
+    /// The number of changes dropped from the front of `changes`.
+    #[serde(skip)]
+    changes_base: usize,
 }
 
 /// A pointer octree with the capability of resizing.
@@ -45,6 +66,94 @@ where
 
     /// CenteredLeveledRegion for the ResizingPointerOctree
     pub region: CenteredLeveledRegion<S>,
+
+    /// Items inserted with `insert_bounds`, stored at the deepest region that contains their whole box.
+    ///
+    /// Only add to this with `insert_bounds`, since `query_bounds` relies on an index of its regions.
+    pub bounded: MortonRegionMap<Vec<T>, M>,
+
+    /// Every region above a region in `bounded`, which `query_bounds` descends through.
+    bounded_parents: MortonRegionSet<M>,
+}
+
+/// What happened when an item was inserted with `ResizingPointerOctree::insert_reporting`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InsertOutcome<T> {
+    /// The item was put in an empty cell and the region stayed the same.
+    Inserted,
+    /// The region expanded by this many levels to fit the item, which was put in an empty cell.
+    GrewBy(u32),
+    /// The item replaced this one in its cell and the region stayed the same.
+    Replaced(T),
+}
+
+/// A cell of a `ResizingPointerOctree` that may or may not hold an item, given back by
+/// `ResizingPointerOctree::entry`. The region has already been expanded to contain the cell.
+pub struct Entry<'a, T, M> {
+    morton: M,
+    /// The leaf at `morton` if the cell is occupied, or else the empty node or other leaf that it would go in.
+    slot: &'a mut Internal<T, M>,
+    /// The depth of `slot` in the tree.
+    level: usize,
+    count: &'a mut usize,
+    changes: &'a mut Vec<M>,
+    changes_base: &'a mut usize,
+}
+
+/// The region sums kept between calls to `PointerOctree::fold_cached`.
+///
+/// Use one cache per folder. The cache only knows the octree it was last used with, so using it with another octree
+/// starts over.
+pub struct FoldCache<S, M> {
+    sums: MortonRegionMap<S, M>,
+    /// The id of the octree that the sums came from.
+    octree: Option<usize>,
+    /// The number of changes to that octree that the sums account for.
+    seen: usize,
+}
+
+impl<S, M> Default for FoldCache<S, M> {
+    fn default() -> Self {
+        FoldCache {
+            sums: MortonRegionMap::default(),
+            octree: None,
+            seen: 0,
+        }
+    }
+}
+
+impl<S, M> FoldCache<S, M>
+where
+    M: Morton,
+{
+    /// Create an empty cache. Calls Default impl.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops every sum so that the next `fold_cached` starts over.
+    pub fn clear(&mut self) {
+        self.sums.clear();
+        self.octree = None;
+    }
+}
+
+/// Gives every `PointerOctree` its own id for `FoldCache`.
+fn next_octree_id() -> usize {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Records that `morton` changed in an octree with `count` items, given the `changes` and `changes_base` fields of
+/// the octree. This is `PointerOctree::mark_dirty` for when its fields are borrowed apart.
+fn push_change<M>(changes: &mut Vec<M>, changes_base: &mut usize, count: usize, morton: M) {
+    if changes.len() >= count.max(64) {
+        // Replaying more changes than there are items is no faster than starting over.
+        *changes_base += changes.len();
+        changes.clear();
+    }
+    changes.push(morton);
 }
 
 impl<T, M> Default for PointerOctree<T, M> {
@@ -58,6 +167,9 @@ impl<T, M> Default for PointerOctree<T, M> {
         PointerOctree {
             tree: Internal::default(),
             count: 0,
+            id: next_octree_id(),
+            changes: vec![],
+            changes_base: 0,
         }
     }
 }
@@ -98,6 +210,16 @@ where
                         let subindex = morton.get_level(i);
                         Continue((&children[subindex], i))
                     }
+                    // The path is only followed while the morton stays in its one occupied octant.
+                    Internal::Path(prefix, level, box ref child) => {
+                        if morton.get_level(i) != prefix.get_level(i) {
+                            Done((node, old_ix))
+                        } else if i + 1 == *level {
+                            Continue((child, i))
+                        } else {
+                            Continue((node, i))
+                        }
+                    }
                     Internal::Leaf(_, _) => Done((node, old_ix)),
                     Internal::None => Done((node, old_ix)),
                 }
@@ -114,10 +236,10 @@ where
                 }
                 // Otherwise we must split them, which we must do outside of this scope due to the borrow.
             }
-            Internal::None => None,
+            Internal::None | Internal::Path(..) => None,
             _ => {
                 unreachable!(
-                    "space::PointerOctree::get(): can only get None or Leaf in this code area"
+                    "space::PointerOctree::get(): can only get None, Path or Leaf in this code area"
                 );
             }
         }
@@ -130,43 +252,23 @@ where
     /// use nalgebra::Vector3;
     ///
     /// let mut tree = PointerOctree::<String, u64>::new();
+    /// let m = Morton::encode(Vector3::<u64>::new(1, 2, 3));
     ///
-    /// let fetched_value = tree.get(Morton::encode(Vector3::<u64>::new(1, 2, 3)));
-    /// assert!(fetched_value.is_none());
+    /// assert!(tree.get_mut(m).is_none());
+    /// tree.insert(m, "a".to_owned());
+    /// tree.get_mut(m).unwrap().push('b');
+    /// assert_eq!(tree.get(m), Some(&"ab".to_owned()));
     /// ```
-    pub fn get_mut(&mut self, morton: M) -> Option<&T> {
-        // Traverse the tree down to the node we need to operate on.
-        let (tree_part, _) = (0..M::dim_bits())
-            .fold_while((&mut self.tree, 0), |(node, old_ix), i| {
-                use itertools::FoldWhile::{Continue, Done};
-                match node {
-                    Internal::Node(box Oct { ref mut children }) => {
-                        // The index into the array to access the next octree node
-                        let subindex = morton.get_level(i);
-                        Continue((&mut children[subindex], i))
-                    }
-                    Internal::Leaf(_, _) => Done((node, old_ix)),
-                    Internal::None => Done((node, old_ix)),
-                }
-            })
-            .into_inner();
-
-        match tree_part {
-            Internal::Leaf(ref mut leaf_item, dest_morton) => {
-                // If they have the same code then replace it.
-                if morton == *dest_morton {
-                    Some(leaf_item)
-                } else {
-                    None
-                }
-                // Otherwise we must split them, which we must do outside of this scope due to the borrow.
-            }
-            Internal::None => None,
-            _ => {
-                unreachable!(
-                    "space::PointerOctree::get_mut(): can only get None or Leaf in this code area"
-                );
+    pub fn get_mut(&mut self, morton: M) -> Option<&mut T> {
+        // The item can be changed through the reference, so its regions in the fold cache are stale.
+        self.mark_dirty(morton);
+        let (slot, _) = self.tree.slot_mut(morton);
+        match *slot {
+            // If they have the same code then give back the item that is there.
+            Internal::Leaf(ref mut leaf_item, dest_morton) if morton == dest_morton => {
+                Some(leaf_item)
             }
+            _ => None,
         }
     }
 
@@ -181,71 +283,44 @@ where
     ///
     /// ```
     pub fn insert(&mut self, morton: M, item: T) {
-        // Traverse the tree down to the node we need to operate on.
-        let (tree_part, level) = (0..M::dim_bits())
-            .fold_while((&mut self.tree, 0), |(node, old_ix), i| {
-                use itertools::FoldWhile::{Continue, Done};
-                match node {
-                    Internal::Node(box Oct { ref mut children }) => {
-                        // The index into the array to access the next octree node
-                        let subindex = morton.get_level(i);
-                        Continue((&mut children[subindex], i))
-                    }
-                    Internal::Leaf(_, _) => Done((node, old_ix)),
-                    Internal::None => Done((node, old_ix)),
-                }
-            })
-            .into_inner();
-
-        match tree_part {
-            Internal::Leaf(ref mut leaf_item, dest_morton) => {
-                // If they have the same code then replace it.
-                if morton == *dest_morton {
-                    *leaf_item = item;
-                    // Don't increase the count here because we replaced only.
-                    return;
-                }
-                // Otherwise we must split them, which we must do outside of this scope due to the borrow.
-            }
-            Internal::None => {
-                // Simply add a new leaf.
-                *tree_part = Internal::Leaf(item, morton);
-                self.count += 1;
-                return;
-            }
-            _ => {
-                unreachable!(
-                    "space::PointerOctree::insert(): can only get None or Leaf in this code area"
-                );
-            }
+        let mut item = Some(item);
+        let slot = self.get_or_insert_with(morton, || item.take().unwrap());
+        // The item is still here if the morton was already in the tree, so it replaces the old one.
+        if let Some(item) = item {
+            *slot = item;
         }
+    }
 
-        let mut dest_old = Internal::empty_node();
-        std::mem::swap(&mut dest_old, tree_part);
+    /// Gives back a mutable reference to the item at `morton`, inserting the item made by `default` first if there is
+    /// none. This only traverses the tree once.
+    fn get_or_insert_with<F>(&mut self, morton: M, default: F) -> &mut T
+    where
+        F: FnOnce() -> T,
+    {
+        self.entry_at(morton).or_insert_with(default)
+    }
 
-        if let Internal::Leaf(dest_item, dest_morton) = dest_old {
-            // Set our initial reference to the default node in the dest.
-            let mut building_node = tree_part;
-            // Create deeper nodes till they differ at some level.
-            for i in level + 1..M::dim_bits() {
-                // We know for sure that the dest is a node.
-                if let Internal::Node(box Oct { ref mut children }) = building_node {
-                    if morton.get_level(i) == dest_morton.get_level(i) {
-                        children[morton.get_level(i)] = Internal::empty_node();
-                        building_node = &mut children[morton.get_level(i)];
-                    } else {
-                        // We reached the end where they differ, so put them both into the node.
-                        children[morton.get_level(i)] = Internal::Leaf(item, morton);
-                        children[dest_morton.get_level(i)] = Internal::Leaf(dest_item, dest_morton);
-                        self.count += 1;
-                        return;
-                    }
-                } else {
-                    unreachable!("space::Octree::insert(): cant get a non-node in this section");
-                }
-            }
-        } else {
-            unreachable!("space::Octree::insert(): cant get a non-leaf in this code area")
+    /// Finds the cell at `morton` for an `Entry`, traversing the tree once.
+    fn entry_at(&mut self, morton: M) -> Entry<'_, T, M> {
+        let PointerOctree {
+            tree,
+            count,
+            changes,
+            changes_base,
+            ..
+        } = self;
+        let (slot, level) = tree.slot_mut(morton);
+        if slot.is_leaf_at(morton) {
+            // Every way of using the entry hands out the item, so its regions in the fold cache are stale.
+            push_change(changes, changes_base, *count, morton);
+        }
+        Entry {
+            morton,
+            slot,
+            level,
+            count,
+            changes,
+            changes_base,
         }
     }
 
@@ -275,7 +350,13 @@ where
     /// assert!(fetched_value == Some("hello".to_owned()));
     /// ```
     pub fn remove(&mut self, morton: M) -> Option<T> {
-        Self::remove_helper(&mut self.tree, morton, 0)
+        self.tree.expand_path(morton, 0);
+        let res = Self::remove_helper(&mut self.tree, morton, 0);
+        if res.is_some() {
+            self.count -= 1;
+            self.mark_dirty(morton);
+        }
+        res
     }
 
     fn remove_helper(tree: &mut Internal<T, M>, morton: M, level: usize) -> Option<T> {
@@ -283,20 +364,30 @@ where
             Internal::Node(box Oct { ref mut children }) => {
                 let subindex = morton.get_level(level);
                 let res = Self::remove_helper(&mut children[subindex], morton, level + 1);
-                if children
-                    .iter()
-                    .filter(|c| match c {
+                if res.is_some() {
+                    // Collapse this node if it is empty or if it only has one leaf left in it.
+                    let mut occupied = children.iter().enumerate().filter(|(_, c)| match c {
                         Internal::None => false,
                         _ => true,
-                    })
-                    .count()
-                    == 0
-                {
-                    std::mem::swap(&mut Internal::None, tree);
+                    });
+                    match (occupied.next(), occupied.next()) {
+                        (None, _) => {
+                            std::mem::swap(&mut Internal::None, tree);
+                        }
+                        (Some((ix, Internal::Leaf(_, _))), None) => {
+                            let mut leaf = Internal::None;
+                            std::mem::swap(&mut leaf, &mut children[ix]);
+                            std::mem::swap(&mut leaf, tree);
+                        }
+                        _ => {}
+                    }
                 }
                 res
             }
-            Internal::Leaf(_, _) => {
+            Internal::Leaf(_, leaf_morton) => {
+                if *leaf_morton != morton {
+                    return None;
+                }
                 let mut leaf = Internal::None;
                 std::mem::swap(&mut leaf, tree);
                 if let Internal::Leaf(leaf_item, _) = leaf {
@@ -307,6 +398,9 @@ where
                     )
                 }
             }
+            Internal::Path(..) => {
+                unreachable!("space::PointerOctree::remove(): the path to the morton was expanded")
+            }
             Internal::None => None,
         }
     }
@@ -338,11 +432,103 @@ where
     /// assert!(i == 2);
     /// assert!(mortons.contains(&Morton::encode(Vector3::<u64>::new(1, 2, 3))));
     /// assert!(mortons.contains(&Morton::encode(Vector3::<u64>::new(4, 5, 6))));
+    /// assert_eq!(mortons, vec![m1, m2]);
     /// ```
     pub fn iter(&self) -> impl Iterator<Item = (M, &T)> {
         self.tree.iter()
     }
 
+    /// Iterate over all leaves depth-first in z-order, which means the mortons are given back in ascending order.
+    ///
+    /// This uses an explicit stack rather than recursion and doesn't collect the leaves up front.
+    ///
+    /// ```
+    /// use space::{PointerOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = PointerOctree::<&str, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), "b");
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a");
+    ///
+    /// let items: Vec<_> = tree.leaves().map(|(_, item)| *item).collect();
+    /// assert_eq!(items, vec!["a", "b"]);
+    /// ```
+    pub fn leaves(&self) -> impl Iterator<Item = (M, &T)> {
+        self.tree.iter()
+    }
+
+    /// Iterate over every non-empty node (internal and leaf) in breadth-first order, giving back the morton
+    /// prefix of the node and its level. The root comes first with level `0` and nodes that share a parent
+    /// are given back contiguously in z-order. A chain of nodes collapsed by `compress` is given back once, at the
+    /// region where it starts.
+    ///
+    /// ```
+    /// use space::{PointerOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = PointerOctree::<&str, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a");
+    /// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), "b");
+    ///
+    /// let levels: Vec<u32> = tree.nodes_bfs().map(|(_, level)| level).collect();
+    /// assert_eq!(levels[0], 0);
+    /// assert!(levels.windows(2).all(|w| w[0] <= w[1]));
+    /// ```
+    pub fn nodes_bfs(&self) -> impl Iterator<Item = (M, u32)> + '_ {
+        InternalBfsIter::new(&self.tree)
+    }
+
+    /// Gives back the morton prefix and level of every non-empty node on the way from the root down to `morton`,
+    /// in the same form as `nodes_bfs`. The path ends at the leaf node that `morton` falls in, or at the deepest
+    /// internal node if the octant `morton` is in is empty. The leaf node might hold a different morton, which
+    /// `get` will tell. An empty octree gives back no nodes.
+    ///
+    /// ```
+    /// use space::{PointerOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = PointerOctree::<&str, u64>::new();
+    /// let a = Morton::encode(Vector3::new(1 << 20, 0, 0));
+    /// tree.insert(a, "a");
+    /// tree.insert(Morton::encode(Vector3::new(0, 1 << 20, 0)), "b");
+    ///
+    /// // The root splits the two leaves in its first octants.
+    /// assert_eq!(tree.path_to(a), vec![(0, 0), (1 << 60, 1)]);
+    /// assert!(PointerOctree::<&str, u64>::new().path_to(a).is_empty());
+    /// ```
+    pub fn path_to(&self, morton: M) -> Vec<(M, u32)> {
+        let mut path = vec![];
+        let mut node = &self.tree;
+        let mut region = MortonRegion::base();
+        loop {
+            match node {
+                Internal::Node(box Oct { ref children }) => {
+                    path.push((region.morton, region.level as u32));
+                    let octant = morton.get_level(region.level);
+                    node = &children[octant];
+                    region = region.enter(octant);
+                }
+                Internal::Path(prefix, level, box ref child) => {
+                    // Report every node of the compressed chain so the path doesn't depend on compression.
+                    while region.level < *level {
+                        path.push((region.morton, region.level as u32));
+                        let octant = morton.get_level(region.level);
+                        if octant != prefix.get_level(region.level) {
+                            return path;
+                        }
+                        region = region.enter(octant);
+                    }
+                    node = child;
+                }
+                Internal::Leaf(_, _) => {
+                    path.push((region.morton, region.level as u32));
+                    return path;
+                }
+                Internal::None => return path,
+            }
+        }
+    }
+
     // pub fn iter_mut(&mut self) -> impl Iterator<Item = (M, T)> {
     //     self.tree.iter_mut();
     // }
@@ -448,15 +634,163 @@ where
         map
     }
 
+    /// Walks the octree for a Barnes-Hut approximation of the forces on `target`, using the opening criterion
+    /// `width / distance < theta`. Any region whose cell `width` in `region` is small enough compared to the
+    /// `distance` from `target` to its centroid is given to `visit` as one mass at its centroid, and every other
+    /// region is descended into. Leaves are always given to `visit` exactly. A `theta` of `0` visits every leaf.
+    ///
+    /// `sums` must hold the sum of every region, which `collect_fold` with a `WeightedCentroidFolder` gives back.
+    /// Regions with a total weight of `0` are skipped. If `target` is the position of a leaf, that leaf is also
+    /// given to `visit`, so it is up to `visit` to skip the zero distance.
+    ///
+    /// ```
+    /// use space::{LeveledRegion, MortonRegionMap, PointerOctree, WeightedCentroidFolder};
+    /// use nalgebra::Vector3;
+    ///
+    /// let region = LeveledRegion(0);
+    /// let mut tree = PointerOctree::<Vector3<f64>, u64>::new();
+    /// for &p in &[Vector3::new(0.5, 0.5, 0.5), Vector3::new(0.6, 0.5, 0.5), Vector3::new(-0.5, 0.0, 0.0)] {
+    ///     tree.insert(region.discretize(p).unwrap(), p);
+    /// }
+    /// let sums: MortonRegionMap<_, u64> = tree.collect_fold(&WeightedCentroidFolder::new(|_: &Vector3<f64>| 1.0));
+    ///
+    /// let mut masses = vec![];
+    /// tree.traverse_theta(region, &sums, Vector3::new(-0.9, 0.0, 0.0), 1.0, |_, mass| masses.push(mass));
+    /// // The two nearby points on the far side are treated as one mass.
+    /// assert_eq!(masses.iter().sum::<f64>(), 3.0);
+    /// assert!(masses.len() < 3);
+    /// ```
+    pub fn traverse_theta<S, V>(
+        &self,
+        region: LeveledRegion,
+        sums: &MortonRegionMap<WeightedCentroidSum<S>, M>,
+        target: Vector3<S>,
+        theta: S,
+        mut visit: V,
+    ) where
+        S: Float + std::fmt::Debug + 'static,
+        V: FnMut(Vector3<S>, S),
+    {
+        let width = (S::one() + S::one()).powi(region.0 + 1);
+        self.tree
+            .traverse_theta(MortonRegion::base(), width, sums, target, theta, &mut visit);
+    }
+
+    /// Folds the whole octree down to the sum at the root, the same as the root of `collect_fold`, but the sum of
+    /// every region is kept in `cache` so that the next call only recomputes the regions that were changed by
+    /// `insert` or `remove` in the meantime. Gives back `None` if the octree is empty.
+    ///
+    /// The cache must only ever be used with the same folder, since the sums in it are taken as they are.
+    ///
+    /// ```
+    /// use space::{CountFolder, FoldCache, PointerOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = PointerOctree::<(), u64>::new();
+    /// let mut cache = FoldCache::new();
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), ());
+    /// assert_eq!(tree.fold_cached(&mut cache, &CountFolder), Some(&1));
+    /// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), ());
+    /// assert_eq!(tree.fold_cached(&mut cache, &CountFolder), Some(&2));
+    /// ```
+    pub fn fold_cached<'a, F>(
+        &self,
+        cache: &'a mut FoldCache<F::Sum, M>,
+        folder: &F,
+    ) -> Option<&'a F::Sum>
+    where
+        F: Folder<T, M>,
+        F::Sum: Clone,
+    {
+        let changes: &[M] = if cache.octree == Some(self.id) && cache.seen >= self.changes_base {
+            &self.changes[cache.seen - self.changes_base..]
+        } else {
+            cache.sums.clear();
+            &[]
+        };
+        // Only the regions containing a changed morton can have a different sum.
+        for &morton in changes {
+            for region in morton_levels(morton) {
+                cache.sums.remove(&region);
+            }
+        }
+        cache.octree = Some(self.id);
+        cache.seen = self.changes_base + self.changes.len();
+        self.tree
+            .fold_cached(MortonRegion::base(), folder, &mut cache.sums);
+        cache.sums.get(&MortonRegion::base())
+    }
+
+    /// Records that `morton` changed so that `fold_cached` recomputes the regions containing it.
+    fn mark_dirty(&mut self, morton: M) {
+        push_change(
+            &mut self.changes,
+            &mut self.changes_base,
+            self.count,
+            morton,
+        );
+    }
+
     /// Returns the number of leaves in the tree.
     pub fn len(&self) -> usize {
         self.count
     }
 
+    /// Removes every item from the octree. Any `FoldCache` used with it starts over.
+    pub fn clear(&mut self) {
+        self.tree = Internal::default();
+        self.count = 0;
+        self.id = next_octree_id();
+        self.changes.clear();
+        self.changes_base = 0;
+    }
+
     /// Checks if the octree is empty.
     pub fn is_empty(&self) -> bool {
         self.count == 0
     }
+
+    /// Collapses every chain of internal nodes with only one occupied child into a single node that stores the
+    /// morton bits it skips, which takes out the indirections of sparse trees. Lookups, iteration and folds give back
+    /// the same results afterwards.
+    ///
+    /// ```
+    /// use space::PointerOctree;
+    ///
+    /// let mut tree = PointerOctree::<&str, u64>::new();
+    /// tree.insert(0, "a");
+    /// tree.insert(1, "b");
+    ///
+    /// assert_eq!(tree.nodes_bfs().count(), 23);
+    /// tree.compress();
+    /// assert_eq!(tree.nodes_bfs().count(), 4);
+    /// assert_eq!(tree.get(1), Some(&"b"));
+    /// ```
+    pub fn compress(&mut self) {
+        self.tree.compress(MortonRegion::base());
+    }
+
+    /// Moves every item into a `LinearOctree` with the same mortons. The leaves are taken out in one traversal,
+    /// which already gives them in z-order, so they are not sorted again.
+    ///
+    /// ```
+    /// use space::{PointerOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = PointerOctree::<&str, u64>::new();
+    /// tree.insert(Morton::encode(Vector3::new(4, 5, 6)), "b");
+    /// tree.insert(Morton::encode(Vector3::new(1, 2, 3)), "a");
+    ///
+    /// let linear = tree.into_linear();
+    /// assert_eq!(linear.len(), 2);
+    /// assert_eq!(linear.get(Morton::encode(Vector3::new(1, 2, 3))), Some(&"a"));
+    /// ```
+    pub fn into_linear(self) -> LinearOctree<T, M> {
+        let mut items: Vec<(M, T)> = self.tree.into_iter().collect();
+        // The children are pushed onto the stack in order, so they come back out in reverse z-order.
+        items.reverse();
+        LinearOctree::from_sorted(items)
+    }
 }
 
 impl<T, M> IntoIterator for PointerOctree<T, M>
@@ -485,6 +819,94 @@ where
     }
 }
 
+impl<T, M> PartialEq for PointerOctree<T, M>
+where
+    T: PartialEq,
+    M: Morton,
+{
+    /// Two octrees are equal if they have the same items at the same mortons, regardless of the order they were
+    /// inserted in. The changes tracked for `fold_cached` are not compared.
+    ///
+    /// ```
+    /// use space::{PointerOctree, Morton};
+    /// use nalgebra::Vector3;
+    ///
+    /// let a: u64 = Morton::encode(Vector3::new(1, 2, 3));
+    /// let b: u64 = Morton::encode(Vector3::new(4, 5, 6));
+    /// let mut first = PointerOctree::<&str, u64>::new();
+    /// first.insert(a, "a");
+    /// first.insert(b, "b");
+    /// let mut second = PointerOctree::<&str, u64>::new();
+    /// second.insert(b, "b");
+    /// second.insert(a, "a");
+    /// assert!(first == second);
+    /// second.insert(a, "c");
+    /// assert!(first != second);
+    /// ```
+    fn eq(&self, other: &Self) -> bool {
+        // Both trees give back their leaves in z-order, so they can be compared in step.
+        self.count == other.count && self.iter().eq(other.iter())
+    }
+}
+
+impl<T, M> Eq for PointerOctree<T, M>
+where
+    T: Eq,
+    M: Morton,
+{
+}
+
+impl<'a, T, M> Entry<'a, T, M>
+where
+    M: Morton,
+{
+    /// Gets the morton of the cell.
+    pub fn morton(&self) -> M {
+        self.morton
+    }
+
+    /// Inserts `default` if the cell is empty and gives back a mutable reference to the item in the cell.
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the item made by `default` if the cell is empty and gives back a mutable reference to the item in
+    /// the cell.
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut T
+    where
+        F: FnOnce() -> T,
+    {
+        let Entry {
+            morton,
+            slot,
+            level,
+            count,
+            changes,
+            changes_base,
+        } = self;
+        if slot.is_leaf_at(morton) {
+            // The change was already recorded when the entry was made.
+            return slot.leaf_mut();
+        }
+        push_change(changes, changes_base, *count, morton);
+        *count += 1;
+        slot.insert_at(morton, level, default)
+    }
+
+    /// Calls `f` with the item in the cell if there is one.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut T),
+    {
+        if let Internal::Leaf(ref mut item, leaf_morton) = *self.slot {
+            if leaf_morton == self.morton {
+                f(item);
+            }
+        }
+        self
+    }
+}
+
 #[allow(dead_code)]
 /// Associates an octree with a LeveledRegion in order to enable resizing.
 impl<T, M, S> ResizingPointerOctree<T, M, S>
@@ -502,9 +924,74 @@ where
                 leveled_region: LeveledRegion(n),
                 center,
             },
+            bounded: MortonRegionMap::default(),
+            bounded_parents: MortonRegionSet::default(),
         }
     }
 
+    /// Removes every item from the octree, including the items from `insert_bounds`, and resets the region to
+    /// level `n` centered at `center`. The memory allocated for `bounded` is kept so that it can be filled again
+    /// without reallocating.
+    ///
+    /// ```
+    /// use space::ResizingPointerOctree;
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = ResizingPointerOctree::<&str, u64, f64>::new(0, Vector3::new(0.0, 0.0, 0.0));
+    /// tree.insert_vector(Vector3::new(5.0, 5.0, 5.0), "a");
+    /// tree.clear(0, Vector3::new(1.0, 1.0, 1.0));
+    /// assert!(tree.is_empty());
+    /// assert_eq!(tree.region.leveled_region.0, 0);
+    /// assert_eq!(tree.region.center, Vector3::new(1.0, 1.0, 1.0));
+    /// ```
+    pub fn clear(&mut self, n: i32, center: Vector3<S>) {
+        self.octree.clear();
+        self.bounded.clear();
+        self.bounded_parents.clear();
+        self.region = CenteredLeveledRegion {
+            leveled_region: LeveledRegion(n),
+            center,
+        };
+    }
+
+    /// Removes the item at `morton` if there is one. Any internal nodes left empty or holding a single leaf
+    /// are collapsed so the tree stays minimal.
+    ///
+    /// ```
+    /// use space::ResizingPointerOctree;
+    /// use space::Morton;
+    /// use nalgebra::Vector3;
+    /// let mut tree = ResizingPointerOctree::<String, u64, f64>::new(0, Vector3::new(0.0, 0.0, 0.0));
+    /// let m = Morton::encode(Vector3::new(0, 0, 0));
+    /// assert!(tree.remove(m).is_none());
+    ///
+    /// tree.insert(m, String::from("test1"));
+    /// assert_eq!(tree.remove(m), Some(String::from("test1")));
+    /// assert!(tree.is_empty());
+    /// ```
+    pub fn remove(&mut self, morton: M) -> Option<T> {
+        self.octree.remove(morton)
+    }
+
+    /// Moves every item inserted by point into a `LinearOctree` with the same mortons, like
+    /// `PointerOctree::into_linear`. The mortons are relative to `region`, so copy it first to keep using it for the
+    /// queries of the `LinearOctree`. Items inserted with `insert_bounds` have no leaf cell and are dropped.
+    ///
+    /// ```
+    /// use space::ResizingPointerOctree;
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = ResizingPointerOctree::<&str, u64, f64>::new(0, Vector3::zeros());
+    /// tree.insert_vector(Vector3::new(10.0, 0.0, 0.0), "a");
+    /// let region = tree.region;
+    ///
+    /// let linear = tree.into_linear();
+    /// assert_eq!(linear.get(region.discretize(Vector3::new(10.0, 0.0, 0.0)).unwrap()), Some(&"a"));
+    /// ```
+    pub fn into_linear(self) -> LinearOctree<T, M> {
+        self.octree.into_linear()
+    }
+
     /// Computes the octant the octree should expand in to add the given point.
     /// Note: performs only one iteration (does not recursively expand) and
     /// does not modify the actual ResizingPointerOctree in any way.
@@ -515,7 +1002,7 @@ where
     ///
     /// ```
     /// use space::ResizingPointerOctree;
-    /// use space::Morton;
+    /// use space::{Morton, Octant};
     /// use nalgebra::Vector3;
     /// let mut tree = ResizingPointerOctree::<String, u64, f64>::new(0, Vector3::new(0.0, 0.0, 0.0));
     /// tree.insert(Morton::encode(Vector3::new(0, 0, 0)), String::from("test1"));
@@ -524,9 +1011,9 @@ where
     /// // This is outside all the bounds of the current octree, so the
     /// // point itself dictates the direction of expansion (i.e. 0b00000111)
     /// // Since the new point will be in octant 0b111, the returned octant will be 0b000.
-    /// assert!(expand_loc == Some(0));
+    /// assert!(expand_loc == Some(Octant::from(0)));
     /// ```
-    pub fn expand_loc(&self, point: Vector3<S>) -> Option<u8> {
+    pub fn expand_loc(&self, point: Vector3<S>) -> Option<Octant> {
         self.region.expand_loc(point)
     }
 
@@ -546,21 +1033,40 @@ where
     ///
     /// assert!(tree.region.leveled_region.0 == 1);
     /// ```
+    ///
+    /// Panics if a coordinate of `point` isn't finite, since no region could ever contain it.
     pub fn resize(&mut self, point: Vector3<S>)
     where S: std::ops::AddAssign,
     {
+        assert!(
+            (0..3).all(|i| point[i].is_finite()),
+            "space::ResizingPointerOctree::resize(): the point must be finite"
+        );
         if let Some(octant) = self.expand_loc(point) {
             let old_octree = std::mem::replace(&mut self.octree, PointerOctree::<T, M>::new());
 
             self.octree
                 .extend(old_octree.into_iter().map(|(morton, item)| {
                     // Add modified morton to new octree
-                    let octant: M = M::from_u8(octant).unwrap();
+                    let octant: M = M::from_u8(octant.into()).unwrap();
                     let new_morton: M = (morton >> 3) | (octant << (3 * M::dim_bits() - 3));
                     (new_morton, item)
                 }));
 
-            self.region.expand(octant);
+            // The regions of bounded items move one level down into the same octant. A region at the deepest level
+            // can't move down, so its items move to the parent cell instead.
+            let high: M = M::from_u8(octant.into()).unwrap() << (3 * M::dim_bits() - 3);
+            let bounded = std::mem::replace(&mut self.bounded, MortonRegionMap::default());
+            self.bounded_parents.clear();
+            for (region, items) in bounded {
+                let region = MortonRegion {
+                    morton: (region.morton >> 3) | high,
+                    level: (region.level + 1).min(M::dim_bits()),
+                };
+                self.bounded_at(region).extend(items);
+            }
+
+            self.region.expand(octant.into());
             self.resize(point);
         }
     }
@@ -577,11 +1083,17 @@ where
     /// // This should execute without error, with exactly one expansion needed.
     /// tree.insert_vector(Vector3::new(1.5f64, 1.5f64, 1.5f64), String::from("test2"));
     /// assert!(tree.region.leveled_region.0 == 1);
+    /// ```
     ///
+    /// Panics if a coordinate of `point` isn't finite, since no region could ever contain it.
     pub fn insert_vector(&mut self, point: Vector3<S>, item: T)
     where
         S: nalgebra::base::Scalar + alga::general::ClosedSub + std::ops::AddAssign,
     {
+        assert!(
+            (0..3).all(|i| point[i].is_finite()),
+            "space::ResizingPointerOctree::insert_vector(): the point must be finite"
+        );
         match self.expand_loc(point) {
             None => self
                 .octree
@@ -592,6 +1104,317 @@ where
             }
         }
     }
+
+    /// Inserts a vector like `insert_vector`, but reports if the region grew or if an item was replaced.
+    ///
+    /// Every morton in the tree changes when the region grows, so this is useful to know when anything computed
+    /// from them, like the sums in a `FoldCache`, has to be thrown away.
+    ///
+    /// ```
+    /// use space::{InsertOutcome, ResizingPointerOctree};
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = ResizingPointerOctree::<&str, u64, f64>::new(0, Vector3::new(0.0, 0.0, 0.0));
+    /// let point = Vector3::new(0.5, 0.5, 0.5);
+    /// assert_eq!(tree.insert_reporting(point, "a"), InsertOutcome::Inserted);
+    /// assert_eq!(tree.insert_reporting(point, "b"), InsertOutcome::Replaced("a"));
+    /// assert_eq!(
+    ///     tree.insert_reporting(Vector3::new(5.0, 5.0, 5.0), "c"),
+    ///     InsertOutcome::GrewBy(2)
+    /// );
+    /// ```
+    ///
+    /// Panics if a coordinate of `point` isn't finite, like `insert_vector`.
+    pub fn insert_reporting(&mut self, point: Vector3<S>, item: T) -> InsertOutcome<T>
+    where
+        S: nalgebra::base::Scalar + alga::general::ClosedSub + std::ops::AddAssign,
+    {
+        let level = self.region.leveled_region.0;
+        self.resize(point);
+        let levels = (self.region.leveled_region.0 - level) as u32;
+        let morton = self.region.discretize(point).unwrap();
+        let replaced = self.octree.remove(morton);
+        self.octree.insert(morton, item);
+        match replaced {
+            // The old region is one of the cells of the grown region, so a point outside of it is alone.
+            _ if levels != 0 => InsertOutcome::GrewBy(levels),
+            Some(old) => InsertOutcome::Replaced(old),
+            None => InsertOutcome::Inserted,
+        }
+    }
+
+    /// Gets the cell that `point` is in to insert into or modify its item in place, resizing as many times as
+    /// necessary first, like with `insert_vector`.
+    ///
+    /// ```
+    /// use space::ResizingPointerOctree;
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = ResizingPointerOctree::<usize, u64, f64>::new(0, Vector3::new(0.0, 0.0, 0.0));
+    /// for point in &[Vector3::new(0.5, 0.5, 0.5), Vector3::new(0.5, 0.5, 0.5), Vector3::new(3.0, 0.5, 0.5)] {
+    ///     *tree.entry(*point).or_insert(0) += 1;
+    /// }
+    /// assert_eq!(tree.region.leveled_region.0, 2);
+    /// assert_eq!(tree.get(tree.region.discretize(Vector3::new(0.5, 0.5, 0.5)).unwrap()), Some(&2));
+    /// ```
+    ///
+    /// Panics if a coordinate of `point` isn't finite, like `insert_vector`.
+    pub fn entry(&mut self, point: Vector3<S>) -> Entry<'_, T, M>
+    where
+        S: nalgebra::base::Scalar + alga::general::ClosedSub + std::ops::AddAssign,
+    {
+        self.resize(point);
+        let morton = self.region.discretize(point).unwrap();
+        self.octree.entry_at(morton)
+    }
+
+    /// Inserts an item that spans the closed box `[min, max]`, such as a triangle or a sphere, resizing as many
+    /// times as necessary. The item is stored in `bounded` at the deepest region that contains the whole box, like
+    /// in a loose octree, and is found again with `query_bounds`. These items are kept apart from the point items,
+    /// so they are not counted by `len`.
+    ///
+    /// Panics if the box has a coordinate that isn't finite or if `min > max` on any axis.
+    ///
+    /// ```
+    /// use space::ResizingPointerOctree;
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = ResizingPointerOctree::<&str, u64, f64>::new(0, Vector3::new(0.0, 0.0, 0.0));
+    /// tree.insert_bounds(Vector3::new(0.1, 0.1, 0.1), Vector3::new(0.2, 0.2, 0.2), "small");
+    /// // This box is outside of the region, so the region is expanded for it.
+    /// tree.insert_bounds(Vector3::new(5.0, 5.0, 5.0), Vector3::new(5.5, 5.5, 5.5), "far");
+    /// assert_eq!(tree.region.leveled_region.0, 2);
+    ///
+    /// let found: Vec<_> = tree
+    ///     .query_bounds(Vector3::new(0.15, 0.15, 0.15), Vector3::new(0.15, 0.15, 0.15))
+    ///     .collect();
+    /// assert_eq!(found, vec![&"small"]);
+    /// ```
+    pub fn insert_bounds(&mut self, min: Vector3<S>, max: Vector3<S>, item: T)
+    where
+        S: nalgebra::base::Scalar + alga::general::ClosedSub + std::ops::AddAssign,
+    {
+        assert!(
+            (0..3).all(|i| min[i].is_finite() && max[i].is_finite() && min[i] <= max[i]),
+            "space::ResizingPointerOctree::insert_bounds(): the box must be finite and not inverted"
+        );
+        self.resize(min);
+        self.resize(max);
+        let [(lx, hx), (ly, hy), (lz, hz)] = self
+            .region
+            .leveled_region
+            .grid_range::<S, M>(min - self.region.center, max - self.region.center)
+            .expect(
+                "space::ResizingPointerOctree::insert_bounds(): the box must be in the region",
+            );
+        let (low, high) = (Vector3::new(lx, ly, lz), Vector3::new(hx, hy, hz));
+        // The cells at a level are the same for both corners until the first bit where a coordinate differs.
+        let differ = (0..3).fold(M::zero(), |differ, i| differ | (low[i] ^ high[i]));
+        let level = M::dim_bits() - (M::BITS - differ.leading_zeros() as usize);
+        self.bounded_at(MortonRegion {
+            morton: M::encode(low),
+            level,
+        })
+        .push(item);
+    }
+
+    /// Gets the items in `bounded` at `region`, adding its parents to the index used by `query_bounds`.
+    fn bounded_at(&mut self, region: MortonRegion<M>) -> &mut Vec<T> {
+        let mut parent = region;
+        while parent.level > 0 {
+            parent.exit();
+            if !self.bounded_parents.insert(parent) {
+                // The regions above this one were added along with it.
+                break;
+            }
+        }
+        self.bounded.entry(region).or_insert_with(Vec::new)
+    }
+
+    /// Iterates over every item inserted with `insert_bounds` whose region touches the closed box `[min, max]`.
+    /// Because items are stored at the region that contains their box, this can give back items whose own box
+    /// doesn't touch `[min, max]`, which can be checked exactly afterwards. The items are in no particular order.
+    ///
+    /// Only the regions that touch the box and have items at or below them are visited.
+    pub fn query_bounds(&self, min: Vector3<S>, max: Vector3<S>) -> impl Iterator<Item = &T>
+    where
+        S: nalgebra::base::Scalar + alga::general::ClosedSub,
+    {
+        let leveled_region = self.region.leveled_region;
+        let (min, max) = (min - self.region.center, max - self.region.center);
+        let touches = |region: MortonRegion<M>| {
+            let (low, high) = leveled_region.region_bounds::<S, M>(region);
+            (0..3).all(|i| low[i] <= max[i] && min[i] <= high[i])
+        };
+        let mut found = vec![];
+        let mut regions = vec![MortonRegion::base()];
+        while let Some(region) = regions.pop() {
+            if !touches(region) {
+                continue;
+            }
+            if let Some(items) = self.bounded.get(&region) {
+                found.push(items);
+            }
+            if self.bounded_parents.contains(&region) {
+                regions.extend((0..8).map(|octant| region.enter(octant)));
+            }
+        }
+        found.into_iter().flat_map(|items| items.iter())
+    }
+
+    /// Replaces the region with the smallest one from `CenteredLeveledRegion::fit` that still contains every item,
+    /// which keeps the mortons dense after many items have been removed. Nothing is changed if the tree is empty or
+    /// the region can't get any smaller.
+    ///
+    /// The point items are moved to the cells containing the centers of their old leaf cells. The items from
+    /// `insert_bounds` are inserted again with the bounds of the regions they were stored at, since their exact
+    /// boxes aren't kept.
+    ///
+    /// ```
+    /// use space::ResizingPointerOctree;
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = ResizingPointerOctree::<&str, u64, f64>::new(0, Vector3::new(0.0, 0.0, 0.0));
+    /// tree.insert_vector(Vector3::new(0.5, 0.5, 0.5), "near");
+    /// tree.insert_vector(Vector3::new(100.0, 100.0, 100.0), "far");
+    /// let far = tree.region.discretize(Vector3::new(100.0, 100.0, 100.0)).unwrap();
+    /// tree.remove(far);
+    ///
+    /// tree.shrink_to_fit();
+    /// assert_eq!(tree.region.leveled_region.0, 0);
+    /// assert_eq!(tree.len(), 1);
+    /// ```
+    pub fn shrink_to_fit(&mut self)
+    where
+        S: nalgebra::base::Scalar
+            + alga::general::ClosedAdd
+            + alga::general::ClosedSub
+            + std::ops::AddAssign,
+    {
+        let old = self.region;
+        let mut points: Vec<Vector3<S>> = self
+            .octree
+            .iter()
+            .map(|(morton, _)| old.undiscretize(morton))
+            .collect();
+        for &region in self.bounded.keys() {
+            let (min, max) = old.leveled_region.region_bounds::<S, M>(region);
+            points.push(min + old.center);
+            points.push(max + old.center);
+        }
+
+        let region = match CenteredLeveledRegion::fit(&points) {
+            Some(region) if region.leveled_region.0 < old.leveled_region.0 => region,
+            _ => return,
+        };
+        let octree = std::mem::replace(&mut self.octree, PointerOctree::new());
+        let bounded = std::mem::replace(&mut self.bounded, MortonRegionMap::default());
+        self.bounded_parents.clear();
+        self.region = region;
+        for (morton, item) in octree {
+            self.insert_vector(old.undiscretize(morton), item);
+        }
+        for (old_region, items) in bounded {
+            let (min, max) = old.leveled_region.region_bounds::<S, M>(old_region);
+            let (min, max) = (min + old.center, max + old.center);
+            for item in items {
+                self.insert_bounds(min, max, item);
+            }
+        }
+    }
+}
+
+impl<T, M, S> std::iter::FromIterator<(Vector3<S>, T)> for ResizingPointerOctree<T, M, S>
+where
+    M: Morton,
+    S: Float
+        + ToPrimitive
+        + FromPrimitive
+        + PartialOrd
+        + std::fmt::Debug
+        + nalgebra::base::Scalar
+        + alga::general::ClosedSub
+        + std::ops::AddAssign
+        + 'static,
+{
+    /// Builds a resizing octree by inserting every point with `insert_vector`, so the region expands as needed.
+    /// The region starts at level `0` centered on the first point.
+    ///
+    /// Points with a NaN or infinite coordinate can't be in any region, so they are skipped.
+    ///
+    /// ```
+    /// use space::ResizingPointerOctree;
+    /// use nalgebra::Vector3;
+    ///
+    /// let points = vec![
+    ///     (Vector3::new(0.0, 0.0, 0.0), "a"),
+    ///     (Vector3::new(100.0, -50.0, 3.0), "b"),
+    ///     (Vector3::new(std::f64::NAN, 0.0, 0.0), "skipped"),
+    /// ];
+    /// let tree: ResizingPointerOctree<_, u64, f64> = points.into_iter().collect();
+    /// assert_eq!(tree.len(), 2);
+    /// let b = tree.region.discretize(Vector3::new(100.0, -50.0, 3.0)).unwrap();
+    /// assert_eq!(tree.get(b), Some(&"b"));
+    /// ```
+    fn from_iter<I>(it: I) -> Self
+    where
+        I: IntoIterator<Item = (Vector3<S>, T)>,
+    {
+        let mut points = it
+            .into_iter()
+            .filter(|&(point, _)| (0..3).all(|i| point[i].is_finite()));
+        let mut tree = match points.next() {
+            Some((point, item)) => {
+                let mut tree = Self::new(0, point);
+                tree.insert_vector(point, item);
+                tree
+            }
+            None => Self::new(0, Vector3::new(S::zero(), S::zero(), S::zero())),
+        };
+        tree.extend(points);
+        tree
+    }
+}
+
+impl<T, M, S> Extend<(Vector3<S>, T)> for ResizingPointerOctree<T, M, S>
+where
+    M: Morton,
+    S: Float
+        + ToPrimitive
+        + FromPrimitive
+        + PartialOrd
+        + std::fmt::Debug
+        + nalgebra::base::Scalar
+        + alga::general::ClosedSub
+        + std::ops::AddAssign
+        + 'static,
+{
+    /// Inserts every point with `insert_vector`, so the region expands as needed to fit points outside of it.
+    ///
+    /// Points with a NaN or infinite coordinate can't be in any region, so they are skipped.
+    ///
+    /// ```
+    /// use space::ResizingPointerOctree;
+    /// use nalgebra::Vector3;
+    ///
+    /// let mut tree = ResizingPointerOctree::<&str, u64, f64>::new(0, Vector3::new(0.0, 0.0, 0.0));
+    /// tree.extend(vec![
+    ///     (Vector3::new(0.5, 0.5, 0.5), "a"),
+    ///     (Vector3::new(100.0, -50.0, 3.0), "b"),
+    /// ]);
+    /// assert_eq!(tree.len(), 2);
+    /// assert!(tree.region.leveled_region.0 > 0);
+    /// ```
+    fn extend<I>(&mut self, it: I)
+    where
+        I: IntoIterator<Item = (Vector3<S>, T)>,
+    {
+        for (point, item) in it.into_iter() {
+            if (0..3).all(|i| point[i].is_finite()) {
+                self.insert_vector(point, item);
+            }
+        }
+    }
 }
 
 impl<T, M, S> Deref for ResizingPointerOctree<T, M, S>
@@ -610,43 +1433,224 @@ where
     fn deref(&self) -> &Self::Target {
         &self.octree
     }
-}
+}
+
+impl<T, M, S> DerefMut for ResizingPointerOctree<T, M, S>
+where
+    M: Morton,
+    S: Float
+        + ToPrimitive
+        + FromPrimitive
+        + PartialOrd
+        + std::fmt::Debug
+        + Sub
+        + 'static,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.octree
+    }
+}
+
+/// Internal node of a pointer octree.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Internal<T, M> {
+    Node(Box<Oct<Internal<T, M>>>),
+    /// A chain of nodes that each had only one occupied child, collapsed by `PointerOctree::compress`. This holds the
+    /// morton prefix and level of the region of the node at the end of the chain, which is always a `Node`.
+    Path(M, usize, Box<Internal<T, M>>),
+    Leaf(T, M),
+    None,
+}
+
+impl<T, M> Internal<T, M>
+where
+    M: Morton,
+{
+    /// Gives back the children of this node one level below `region` along with their regions.
+    ///
+    /// A `Path` has one child, which is either the node at the end of the chain or the rest of the `Path` itself.
+    fn children<'a>(
+        &'a self,
+        region: MortonRegion<M>,
+    ) -> impl Iterator<Item = (&'a Self, MortonRegion<M>)> + 'a {
+        use either::Either::*;
+        match self {
+            Internal::Node(box Oct { ref children }) => Left(
+                children
+                    .iter()
+                    .enumerate()
+                    .map(move |(ix, child)| (child, region.enter(ix))),
+            ),
+            Internal::Path(prefix, level, box ref child) => {
+                let next = region.enter(prefix.get_level(region.level));
+                Right(Some(if next.level == *level { (child, next) } else { (self, next) }).into_iter())
+            }
+            _ => Right(None.into_iter()),
+        }
+    }
+
+    /// Expands the path to `morton` and gives back the leaf or empty node that the traversal to it ends at, along with
+    /// its depth.
+    fn slot_mut(&mut self, morton: M) -> (&mut Self, usize) {
+        self.expand_path(morton, 0);
+        // Traverse the tree down to the node we need to operate on.
+        (0..M::dim_bits())
+            .fold_while((self, 0), |(node, depth), i| {
+                use itertools::FoldWhile::{Continue, Done};
+                match node {
+                    Internal::Node(box Oct { ref mut children }) => {
+                        // The index into the array to access the next octree node
+                        let subindex = morton.get_level(i);
+                        Continue((&mut children[subindex], i + 1))
+                    }
+                    Internal::Path(..) => unreachable!(
+                        "space::PointerOctree::slot_mut(): the path to the morton was expanded"
+                    ),
+                    Internal::Leaf(_, _) => Done((node, depth)),
+                    Internal::None => Done((node, depth)),
+                }
+            })
+            .into_inner()
+    }
+
+    /// Checks if this is the leaf at `morton`.
+    fn is_leaf_at(&self, morton: M) -> bool {
+        match self {
+            Internal::Leaf(_, leaf_morton) => *leaf_morton == morton,
+            _ => false,
+        }
+    }
+
+    /// Puts the item made by `default` at `morton` in place of this node, which is at depth `level` and is either
+    /// empty or a leaf with another morton, and gives back a mutable reference to the item.
+    fn insert_at<F>(&mut self, morton: M, level: usize, default: F) -> &mut T
+    where
+        F: FnOnce() -> T,
+    {
+        if let Internal::None = self {
+            // Simply add a new leaf.
+            *self = Internal::Leaf(default(), morton);
+            return self.leaf_mut();
+        }
+
+        let dest_old = std::mem::replace(self, Internal::empty_node());
+        if let Internal::Leaf(dest_item, dest_morton) = dest_old {
+            // Set our initial reference to the default node in the dest.
+            let mut building_node = self;
+            // Create deeper nodes till they differ at some level, starting at the level of the split leaf.
+            for i in level..M::dim_bits() {
+                // We know for sure that the dest is a node.
+                if let Internal::Node(box Oct { ref mut children }) = building_node {
+                    if morton.get_level(i) == dest_morton.get_level(i) {
+                        children[morton.get_level(i)] = Internal::empty_node();
+                        building_node = &mut children[morton.get_level(i)];
+                    } else {
+                        // We reached the end where they differ, so put them both into the node.
+                        children[dest_morton.get_level(i)] = Internal::Leaf(dest_item, dest_morton);
+                        let slot = &mut children[morton.get_level(i)];
+                        *slot = Internal::Leaf(default(), morton);
+                        return slot.leaf_mut();
+                    }
+                } else {
+                    unreachable!("space::Octree::insert(): cant get a non-node in this section");
+                }
+            }
+            unreachable!("space::Octree::insert(): two different mortons must differ at some level")
+        } else {
+            unreachable!("space::Octree::insert(): cant get a non-leaf in this code area")
+        }
+    }
+
+    /// Gives back the item of a `Leaf`.
+    fn leaf_mut(&mut self) -> &mut T {
+        match self {
+            Internal::Leaf(ref mut item, _) => item,
+            _ => unreachable!("space::PointerOctree: expected a leaf in this code area"),
+        }
+    }
+
+    /// Skips over a `Path` to the node at the end of it.
+    fn unpath(&self) -> &Self {
+        match self {
+            Internal::Path(_, _, box ref child) => child,
+            _ => self,
+        }
+    }
+
+    /// Expands every `Path` on the way to `morton` back into nodes with one occupied child, where `level` is the
+    /// level of this node.
+    fn expand_path(&mut self, morton: M, level: usize) {
+        if let Internal::Path(..) = self {
+            if let Internal::Path(prefix, end, child) =
+                std::mem::replace(self, Internal::empty_node())
+            {
+                let next = if level + 1 == end {
+                    *child
+                } else {
+                    Internal::Path(prefix, end, child)
+                };
+                if let Internal::Node(box Oct { ref mut children }) = self {
+                    children[prefix.get_level(level)] = next;
+                }
+            }
+        }
+        if let Internal::Node(box Oct { ref mut children }) = self {
+            children[morton.get_level(level)].expand_path(morton, level + 1);
+        }
+    }
 
-impl<T, M, S> DerefMut for ResizingPointerOctree<T, M, S>
-where
-    M: Morton,
-    S: Float
-        + ToPrimitive
-        + FromPrimitive
-        + PartialOrd
-        + std::fmt::Debug
-        + Sub
-        + 'static,
-{
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.octree
+    /// Collapses every node below `region` (including this one) with only one occupied child into a `Path`.
+    fn compress(&mut self, region: MortonRegion<M>) {
+        match self {
+            Internal::Node(box Oct { ref mut children }) => {
+                for (ix, child) in children.iter_mut().enumerate() {
+                    child.compress(region.enter(ix));
+                }
+            }
+            Internal::Path(prefix, level, box ref mut child) => child.compress(MortonRegion {
+                morton: *prefix,
+                level: *level,
+            }),
+            _ => return,
+        }
+        let replacement = match self {
+            Internal::Node(box Oct { ref mut children }) => {
+                let occupied = {
+                    let mut occupied = (0..8).filter(|&ix: &usize| match children[ix] {
+                        Internal::None => false,
+                        _ => true,
+                    });
+                    match (occupied.next(), occupied.next()) {
+                        (Some(ix), None) => ix,
+                        _ => return,
+                    }
+                };
+                match std::mem::replace(&mut children[occupied], Internal::None) {
+                    node @ Internal::Node(_) => {
+                        Internal::Path(region.enter(occupied).morton, region.level + 1, box node)
+                    }
+                    // A leaf or the rest of a chain takes the place of the node.
+                    other => other,
+                }
+            }
+            // If the node at the end of the chain was collapsed as well, it takes the place of the chain.
+            Internal::Path(_, _, box ref mut child) => match child {
+                Internal::Node(_) => return,
+                _ => std::mem::replace(child, Internal::None),
+            },
+            _ => return,
+        };
+        *self = replacement;
     }
-}
-
-/// Internal node of a pointer octree.
-#[derive(Clone, Debug)]
-enum Internal<T, M> {
-    Node(Box<Oct<Internal<T, M>>>),
-    Leaf(T, M),
-    None,
-}
 
-impl<T, M> Internal<T, M>
-where
-    M: Morton,
-{
     /// Iterate over all octree nodes and their morton codes.
     fn iter(&self) -> impl Iterator<Item = (M, &T)> {
         use either::Either::*;
-        match self {
+        match self.unpath() {
             Internal::Node(box ref n) => Left(InternalIter::new(vec![(&n.children, 0)])),
             Internal::Leaf(ref item, morton) => Right(std::iter::once((*morton, item))),
             Internal::None => Left(InternalIter::new(vec![])),
+            Internal::Path(..) => unreachable!("a path always ends at a node"),
         }
     }
 
@@ -662,7 +1666,7 @@ where
         rng: &'a mut R,
     ) -> impl Iterator<Item = (M, &T)> + 'a {
         use either::Either::*;
-        match self {
+        match self.unpath() {
             Internal::Node(box Oct { ref children }) => {
                 if depth == 0 {
                     let mut choice = rng.gen_range(0, 8);
@@ -680,6 +1684,7 @@ where
             }
             Internal::Leaf(ref item, morton) => Right(std::iter::once((*morton, item))),
             Internal::None => Left(InternalRandIter::new(vec![], depth, rng)),
+            Internal::Path(..) => unreachable!("a path always ends at a node"),
         }
     }
 
@@ -697,6 +1702,7 @@ where
                 }
                 children[choice].sample(morton << 3)
             }
+            Internal::Path(_, _, box ref child) => child.sample(morton),
             Internal::Leaf(ref item, morton) => (*morton, item),
             Internal::None => unreachable!("can't sample a none node"),
         }
@@ -739,12 +1745,12 @@ where
         E: Extend<(MortonRegion<M>, F::Sum)> + Default,
     {
         match self {
-            Internal::Node(box Oct { ref children }) => {
+            Internal::Node(_) | Internal::Path(..) => {
                 if region.level < M::dim_bits() {
-                    let sum = folder
-                        .fold((0..8).filter_map(|i| {
-                            children[i].collect_fold(region.enter(i), folder, map)
-                        }));
+                    let sum = folder.fold(
+                        self.children(region)
+                            .filter_map(|(child, region)| child.collect_fold(region, folder, map)),
+                    );
                     map.extend(std::iter::once((region, sum.clone())));
                     Some(sum)
                 } else {
@@ -760,6 +1766,70 @@ where
         }
     }
 
+    /// Visits the regions of the Barnes-Hut approximation for `target`, where `width` is the width of `region`.
+    fn traverse_theta<S, V>(
+        &self,
+        region: MortonRegion<M>,
+        width: S,
+        sums: &MortonRegionMap<WeightedCentroidSum<S>, M>,
+        target: Vector3<S>,
+        theta: S,
+        visit: &mut V,
+    ) where
+        S: Float + std::fmt::Debug + 'static,
+        V: FnMut(Vector3<S>, S),
+    {
+        if let Internal::None = self {
+            return;
+        }
+        let sum = sums.get(&region).expect(
+            "space::PointerOctree::traverse_theta(): sums must have every region of the octree",
+        );
+        let centroid = match sum.centroid() {
+            Some(centroid) => centroid,
+            None => return,
+        };
+        match self {
+            // This is the opposite of `width / distance < theta` on squared distances, which also opens every node if
+            // `theta` is `0`.
+            Internal::Node(_) | Internal::Path(..)
+                if width * width >= theta * theta * super::distance2(centroid, target) =>
+            {
+                let half = width / (S::one() + S::one());
+                for (child, region) in self.children(region) {
+                    child.traverse_theta(region, half, sums, target, theta, visit);
+                }
+            }
+            _ => visit(centroid, sum.1),
+        }
+    }
+
+    /// Same as `collect_fold`, but any region already in `sums` is taken from there instead of being recomputed.
+    fn fold_cached<F>(
+        &self,
+        region: MortonRegion<M>,
+        folder: &F,
+        sums: &mut MortonRegionMap<F::Sum, M>,
+    ) -> Option<F::Sum>
+    where
+        F: Folder<T, M>,
+        F::Sum: Clone,
+    {
+        if let Some(sum) = sums.get(&region) {
+            return Some(sum.clone());
+        }
+        let sum = match self {
+            Internal::Node(_) | Internal::Path(..) => folder.fold(
+                self.children(region)
+                    .filter_map(|(child, region)| child.fold_cached(region, folder, sums)),
+            ),
+            Internal::Leaf(ref item, morton) => folder.gather(*morton, item),
+            Internal::None => return None,
+        };
+        sums.insert(region, sum.clone());
+        Some(sum)
+    }
+
     fn fold_rand<F, R>(
         &self,
         region: MortonRegion<M>,
@@ -775,41 +1845,20 @@ where
         Standard: Distribution<M>,
     {
         match self {
-            Internal::Node(box Oct { ref children }) => {
+            Internal::Node(_) | Internal::Path(..) => {
                 if let Some(sum) = cache.get_mut(&region).cloned() {
                     return Some(sum);
                 }
                 if depth == 0 {
-                    let morton = rng.gen();
-                    match self {
-                        Internal::Node(box Oct { ref children }) => {
-                            let mut choice = morton.get_level(0);
-                            // Iterate until we find the first non-empty spot.
-                            // This technically results in not completely random behavior
-                            // since an octant that comes after more empty octants is more likely to be chosen.
-                            while let Internal::None = children[choice] {
-                                choice += 1;
-                                choice %= 8;
-                            }
-                            let (morton, item) = children[choice].sample(morton << 3);
-                            let sum = folder.gather(morton, item);
-                            cache.insert(region, sum.clone());
-                            Some(sum)
-                        }
-                        Internal::Leaf(ref item, morton) => {
-                            let sum = folder.gather(*morton, item);
-                            cache.insert(region, sum.clone());
-                            Some(sum)
-                        }
-                        Internal::None => None,
-                    }
+                    let (morton, item) = self.sample(rng.gen());
+                    let sum = folder.gather(morton, item);
+                    cache.insert(region, sum.clone());
+                    Some(sum)
                 } else {
                     let sum = folder.fold(
-                        children
-                            .iter()
-                            .enumerate()
-                            .map(|(ix, child)| {
-                                child.fold_rand(region.enter(ix), depth - 1, folder, cache, rng)
+                        self.children(region)
+                            .map(|(child, region)| {
+                                child.fold_rand(region, depth - 1, folder, cache, rng)
                             })
                             .filter_map(|c| c),
                     );
@@ -882,10 +1931,10 @@ where
             if ix != 7 {
                 self.nodes.push((node, ix + 1));
             }
-            match node[ix] {
+            match node[ix].unpath() {
                 Internal::Node(box Oct { ref children }) => self.nodes.push((children, 0)),
                 Internal::Leaf(ref item, morton) => {
-                    return Some((morton, item));
+                    return Some((*morton, item));
                 }
                 _ => {}
             }
@@ -929,6 +1978,7 @@ where
                     self.nodes.push(c6);
                     self.nodes.push(c7);
                 }
+                Internal::Path(_, _, box child) => self.nodes.push(child),
                 Internal::Leaf(item, morton) => {
                     return Some((morton, item));
                 }
@@ -939,6 +1989,59 @@ where
     }
 }
 
+struct InternalBfsIter<'a, T, M> {
+    /// The nodes left to visit. The flag is `false` for a `Path` that was already given back at an earlier level.
+    nodes: std::collections::VecDeque<(&'a Internal<T, M>, MortonRegion<M>, bool)>,
+}
+
+impl<'a, T, M> InternalBfsIter<'a, T, M>
+where
+    M: Morton,
+{
+    fn new(node: &'a Internal<T, M>) -> Self {
+        InternalBfsIter {
+            nodes: std::iter::once((node, MortonRegion::base(), true)).collect(),
+        }
+    }
+}
+
+impl<'a, T, M> Iterator for InternalBfsIter<'a, T, M>
+where
+    M: Morton,
+{
+    type Item = (M, u32);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, region, first)) = self.nodes.pop_front() {
+            match node {
+                Internal::Node(box Oct { ref children }) => {
+                    for (ix, child) in children.iter().enumerate() {
+                        self.nodes.push_back((child, region.enter(ix), true));
+                    }
+                }
+                Internal::Path(..) => {
+                    // The chain is walked one level at a time so the node at its end still comes in level order.
+                    for (child, region) in node.children(region) {
+                        let first = match child {
+                            Internal::Path(..) => false,
+                            _ => true,
+                        };
+                        self.nodes.push_back((child, region, first));
+                    }
+                    if !first {
+                        continue;
+                    }
+                }
+                Internal::Leaf(_, _) => {}
+                Internal::None => continue,
+            }
+            return Some((region.morton, region.level as u32));
+        }
+        None
+    }
+}
+
 type NodeIndexLevel<'a, T, M> = (&'a [Internal<T, M>; 8], usize, usize);
 
 struct InternalRandIter<'a, T, M, R> {
@@ -970,7 +2073,7 @@ where
             if level <= self.depth && ix != 7 {
                 self.nodes.push((node, ix + 1, level));
             }
-            match node[ix] {
+            match node[ix].unpath() {
                 Internal::Node(box Oct { ref children }) => self.nodes.push((
                     children,
                     if level >= self.depth {
@@ -989,7 +2092,7 @@ where
                     level + 1,
                 )),
                 Internal::Leaf(ref item, morton) => {
-                    return Some((morton, item));
+                    return Some((*morton, item));
                 }
                 _ => {}
             }
@@ -1077,12 +2180,10 @@ where
                 }
             } else {
                 match node {
-                    Internal::Node(box Oct { ref children }) => {
+                    Internal::Node(_) | Internal::Path(..) => {
                         trace!("traversing deeper due to node at level {}", region.level);
                         // Traverse deeper (we already checked if we didn't need to go further).
-                        for (ix, child) in children.iter().enumerate() {
-                            self.nodes.push((child, region.enter(ix)));
-                        }
+                        self.nodes.extend(node.children(region));
                     }
                     Internal::Leaf(ref item, morton) => {
                         trace!("stopping due to leaf at level {}", region.level);
@@ -1144,7 +2245,7 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         while let Some((node, region)) = self.nodes.pop() {
             match node {
-                Internal::Node(box Oct { ref children }) => {
+                Internal::Node(_) | Internal::Path(..) => {
                     // If we shouldn't go further into the region, then take the first thing from the iterator.
                     if !(self.explore)(region) {
                         trace!("chose not to go further");
@@ -1157,9 +2258,7 @@ where
                     } else {
                         trace!("traversing deeper due to node at level {}", region.level);
                         // Traverse deeper (we already checked if we didn't need to go further).
-                        for (ix, child) in children.iter().enumerate() {
-                            self.nodes.push((child, region.enter(ix)));
-                        }
+                        self.nodes.extend(node.children(region));
                     }
                 }
                 Internal::Leaf(ref item, morton) => {
@@ -1203,4 +2302,745 @@ mod tests {
 
         assert_eq!(octree.iter().count(), 5000);
     }
+
+    #[test]
+    fn test_insert_splits_root_leaf() {
+        // The first item is stored at the root, so the second one splits it starting from the top level.
+        for &(a, b) in &[(0u64, 1 << 60), (0, 1), (5 << 57, 3 << 60)] {
+            let mut octree = PointerOctree::<_, u64>::new();
+            octree.insert(a, 0);
+            octree.insert(b, 1);
+            assert_eq!(octree.len(), 2);
+            assert_eq!(octree.get(a), Some(&0));
+            assert_eq!(octree.get(b), Some(&1));
+            assert_eq!(octree.iter().count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_leaves_ascending() {
+        let mut rng = SmallRng::from_seed([2; 16]);
+        let mut octree = PointerOctree::<_, u64>::new();
+        octree.extend((0..5000).map(|i| (rng.gen::<u64>() & u64::used_bits(), i)));
+
+        let mortons: Vec<u64> = octree.leaves().map(|(m, _)| m).collect();
+        assert_eq!(mortons.len(), octree.len());
+        assert!(mortons.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_nodes_bfs() {
+        let mut rng = SmallRng::from_seed([3; 16]);
+        let mut octree = PointerOctree::<_, u64>::new();
+        octree.extend((0..1000).map(|i| (rng.gen::<u64>() & u64::used_bits(), i)));
+
+        let nodes: Vec<(u64, u32)> = octree.nodes_bfs().collect();
+        assert_eq!(nodes[0], (0, 0));
+        assert!(nodes.windows(2).all(|w| w[0].1 <= w[1].1));
+        // Every leaf is a node too.
+        let leaves = octree.leaves().count();
+        assert!(nodes.len() > leaves);
+    }
+
+    #[test]
+    fn test_path_to() {
+        let mut tree = PointerOctree::<usize, u64>::new();
+        let a = u64::encode(Vector3::new(0, 0, 0));
+        let b = u64::encode(Vector3::new(1, 0, 0));
+        let c = u64::encode(Vector3::new(1 << 20, 0, 0));
+        tree.insert(a, 0);
+        // A single leaf is stored at the root.
+        assert_eq!(tree.path_to(a), vec![(0, 0)]);
+        assert_eq!(tree.path_to(c), vec![(0, 0)]);
+
+        tree.insert(b, 1);
+        tree.insert(c, 2);
+        // `a` and `b` share every octant but the last, so they are split at the deepest level.
+        let path = tree.path_to(b);
+        assert_eq!(path.len(), u64::dim_bits() + 1);
+        for (level, &(prefix, l)) in path.iter().enumerate() {
+            assert_eq!(l, level as u32);
+            let cut = 3 * (u64::dim_bits() - level);
+            assert_eq!(prefix, (b >> cut) << cut);
+        }
+        assert_eq!(path.last(), Some(&(b, u64::dim_bits() as u32)));
+        assert_eq!(tree.path_to(c), vec![(0, 0), (1 << 60, 1)]);
+        // The octant of this morton is empty, so the path stops at the root.
+        assert_eq!(
+            tree.path_to(u64::encode(Vector3::new(0, 1 << 20, 0))),
+            vec![(0, 0)]
+        );
+        // Every node on a path is a node of the tree.
+        let nodes: Vec<(u64, u32)> = tree.nodes_bfs().collect();
+        assert!(path.iter().all(|node| nodes.contains(node)));
+    }
+
+    #[test]
+    fn test_fold_cached() {
+        use std::cell::Cell;
+
+        /// Counts the items while also counting how many times `gather` is called.
+        struct Gathers<'a>(&'a Cell<usize>);
+
+        impl<'a> Folder<usize, u64> for Gathers<'a> {
+            type Sum = usize;
+
+            fn gather(&self, _: u64, _: &usize) -> Self::Sum {
+                self.0.set(self.0.get() + 1);
+                1
+            }
+
+            fn fold<I>(&self, it: I) -> Self::Sum
+            where
+                I: Iterator<Item = Self::Sum>,
+            {
+                it.sum()
+            }
+        }
+
+        /// Counts every item twice, which has the same sum type as `Gathers`.
+        struct Doubles;
+
+        impl Folder<usize, u64> for Doubles {
+            type Sum = usize;
+
+            fn gather(&self, _: u64, _: &usize) -> Self::Sum {
+                2
+            }
+
+            fn fold<I>(&self, it: I) -> Self::Sum
+            where
+                I: Iterator<Item = Self::Sum>,
+            {
+                it.sum()
+            }
+        }
+
+        let mut rng = SmallRng::from_seed([3; 16]);
+        let mut tree = PointerOctree::<usize, u64>::new();
+        let mut cache = FoldCache::new();
+        assert_eq!(tree.fold_cached(&mut cache, &CountFolder), None);
+        let mortons: Vec<u64> = (0..1000)
+            .map(|_| rng.gen::<u64>() & u64::used_bits())
+            .collect();
+        tree.extend(mortons.iter().map(|&m| (m, 0)));
+
+        let gathers = Cell::new(0);
+        let mut cache = FoldCache::new();
+        assert_eq!(
+            tree.fold_cached(&mut cache, &Gathers(&gathers)),
+            Some(&tree.len())
+        );
+        assert_eq!(gathers.get(), tree.len());
+
+        // Nothing is recomputed when nothing changed.
+        gathers.set(0);
+        assert_eq!(
+            tree.fold_cached(&mut cache, &Gathers(&gathers)),
+            Some(&tree.len())
+        );
+        assert_eq!(gathers.get(), 0);
+
+        // Only the changed leaf and the leaf split from it are gathered again.
+        gathers.set(0);
+        tree.insert(rng.gen::<u64>() & u64::used_bits(), 0);
+        assert_eq!(
+            tree.fold_cached(&mut cache, &Gathers(&gathers)),
+            Some(&tree.len())
+        );
+        assert!(gathers.get() <= 2);
+
+        // Another folder with the same sum type keeps its own cache, which stays current through the same changes.
+        let mut doubled = FoldCache::new();
+        assert_eq!(
+            tree.fold_cached(&mut doubled, &Doubles),
+            Some(&(2 * tree.len()))
+        );
+        for &m in mortons.iter().step_by(2) {
+            tree.remove(m);
+        }
+        let expected = tree.len();
+        assert_eq!(
+            tree.fold_cached(&mut cache, &Gathers(&gathers)),
+            Some(&expected)
+        );
+        assert_eq!(
+            tree.fold_cached(&mut doubled, &Doubles),
+            Some(&(2 * expected))
+        );
+
+        // A cache used with another octree starts over.
+        let mut other = PointerOctree::<usize, u64>::new();
+        other.insert(0, 0);
+        assert_eq!(other.fold_cached(&mut cache, &Gathers(&gathers)), Some(&1));
+        assert_eq!(
+            tree.fold_cached(&mut cache, &Gathers(&gathers)),
+            Some(&expected)
+        );
+        cache.clear();
+        assert_eq!(
+            tree.fold_cached(&mut cache, &Gathers(&gathers)),
+            Some(&expected)
+        );
+    }
+
+    #[test]
+    fn test_remove_half() {
+        let mut rng = SmallRng::from_seed([4; 16]);
+        let mut tree = ResizingPointerOctree::<_, u64, f64>::new(0, Vector3::zeros());
+        let mut mortons: Vec<u64> = (0..2000)
+            .map(|_| rng.gen::<u64>() & u64::used_bits())
+            .collect();
+        mortons.sort();
+        mortons.dedup();
+        tree.octree.extend(mortons.iter().map(|&m| (m, m)));
+
+        let (removed, kept): (Vec<_>, Vec<_>) =
+            mortons.iter().enumerate().partition(|&(ix, _)| ix % 2 == 0);
+        for (_, &m) in removed {
+            assert_eq!(tree.remove(m), Some(m));
+            assert_eq!(tree.remove(m), None);
+        }
+
+        let kept: Vec<u64> = kept.into_iter().map(|(_, &m)| m).collect();
+        let leaves: Vec<u64> = tree.leaves().map(|(_, &m)| m).collect();
+        assert_eq!(leaves, kept);
+        assert_eq!(tree.len(), kept.len());
+
+        for &m in &kept {
+            assert_eq!(tree.remove(m), Some(m));
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.nodes_bfs().count(), 0);
+    }
+
+    #[test]
+    fn test_remove_plain() {
+        let mut rng = SmallRng::from_seed([5; 16]);
+        let mut octree = PointerOctree::<_, u64>::new();
+        let mut mortons: Vec<u64> = (0..1000)
+            .map(|_| rng.gen::<u64>() & u64::used_bits())
+            .collect();
+        mortons.sort();
+        mortons.dedup();
+        octree.extend(mortons.iter().map(|&m| (m, m)));
+
+        // Removing a morton that isn't in the tree leaves everything in place.
+        let missing = (0..)
+            .map(|_| rng.gen::<u64>() & u64::used_bits())
+            .find(|m| mortons.binary_search(m).is_err())
+            .unwrap();
+        assert_eq!(octree.remove(missing), None);
+        assert_eq!(octree.len(), mortons.len());
+
+        for (ix, &m) in mortons.iter().enumerate() {
+            assert_eq!(octree.remove(m), Some(m));
+            assert_eq!(octree.get(m), None);
+            assert_eq!(octree.len(), mortons.len() - ix - 1);
+            // The rest are still reachable after the path to `m` was collapsed.
+            for &other in &mortons[ix + 1..] {
+                assert_eq!(octree.get(other), Some(&other));
+            }
+        }
+        assert!(octree.is_empty());
+        assert_eq!(octree.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_compress() {
+        // Both leaves are in the deepest node, so every node above it has only one occupied child.
+        let mut octree = PointerOctree::<_, u64>::new();
+        octree.insert(0, 0);
+        octree.insert(1, 1);
+        let path = octree.path_to(1);
+        assert_eq!(octree.nodes_bfs().count(), 23);
+        octree.compress();
+        assert_eq!(octree.nodes_bfs().count(), 4);
+        assert_eq!(octree.get(0), Some(&0));
+        assert_eq!(octree.get(1), Some(&1));
+        assert_eq!(octree.get(2), None);
+        assert_eq!(octree.get(1 << 60), None);
+        assert_eq!(octree.path_to(1), path);
+
+        // A cluster below a long shared prefix iterates and folds the same once it is compressed.
+        let mut rng = SmallRng::from_seed([13; 16]);
+        let mut octree = PointerOctree::<_, u64>::new();
+        octree.extend((0..200).map(|i| ((5 << 57) | (rng.gen::<u64>() & 0xfff), i)));
+        let items: Vec<(u64, usize)> = octree.iter().map(|(m, &i)| (m, i)).collect();
+        let sums: MortonRegionMap<usize, u64> = octree.collect_fold(&CountFolder);
+        let nodes = octree.nodes_bfs().count();
+
+        octree.compress();
+        assert!(octree.nodes_bfs().count() < nodes);
+        let compressed_items: Vec<(u64, usize)> = octree.iter().map(|(m, &i)| (m, i)).collect();
+        assert_eq!(compressed_items, items);
+        assert!(items.iter().all(|&(m, i)| octree.get(m) == Some(&i)));
+        let compressed_sums: MortonRegionMap<usize, u64> = octree.collect_fold(&CountFolder);
+        assert_eq!(compressed_sums, sums);
+        let mut cache = FoldCache::new();
+        assert_eq!(
+            octree.fold_cached(&mut cache, &CountFolder),
+            Some(&items.len())
+        );
+
+        // Inserting and removing expand the compressed nodes on the way to the morton.
+        octree.insert(1 << 60, 200);
+        assert_eq!(octree.remove(items[0].0), Some(items[0].1));
+        assert_eq!(octree.get(1 << 60), Some(&200));
+        assert_eq!(octree.len(), items.len());
+        assert_eq!(octree.iter().count(), items.len());
+        assert_eq!(
+            octree.fold_cached(&mut cache, &CountFolder),
+            Some(&items.len())
+        );
+    }
+
+    #[test]
+    fn test_resizing_from_iter() {
+        let mut rng = SmallRng::from_seed([6; 16]);
+        let mut points: Vec<Vector3<f64>> = (0..500)
+            .map(|_| {
+                Vector3::new(
+                    rng.gen_range(-1000.0, 1000.0),
+                    rng.gen_range(-1000.0, 1000.0),
+                    rng.gen_range(-1000.0, 1000.0),
+                )
+            })
+            .collect();
+        points.insert(250, Vector3::new(0.0, std::f64::NAN, 0.0));
+        points.push(Vector3::new(std::f64::INFINITY, 0.0, 0.0));
+
+        let tree: ResizingPointerOctree<usize, u64, f64> = points
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(i, p)| (p, i))
+            .collect();
+        assert_eq!(tree.len(), 500);
+        for (i, &point) in points.iter().enumerate() {
+            if (0..3).all(|axis| point[axis].is_finite()) {
+                let morton = tree.region.discretize(point).unwrap();
+                assert_eq!(tree.get(morton), Some(&i));
+            }
+        }
+
+        let empty: ResizingPointerOctree<usize, u64, f64> = std::iter::empty().collect();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_traverse_theta_clusters() {
+        let mut rng = SmallRng::from_seed([7; 16]);
+        let region = LeveledRegion(0);
+        let mut tree = PointerOctree::<Vector3<f64>, u64>::new();
+        let mut bodies = vec![];
+        for &center in &[Vector3::new(-0.5, -0.5, -0.5), Vector3::new(0.5, 0.5, 0.5)] {
+            for _ in 0..200 {
+                let offset = Vector3::new(
+                    rng.gen_range(-0.05, 0.05),
+                    rng.gen_range(-0.05, 0.05),
+                    rng.gen_range(-0.05, 0.05),
+                );
+                let body = center + offset;
+                tree.insert(region.discretize(body).unwrap(), body);
+                bodies.push(body);
+            }
+        }
+        let sums: MortonRegionMap<_, u64> =
+            tree.collect_fold(&WeightedCentroidFolder::new(|_: &Vector3<f64>| 1.0));
+
+        // The force on a unit mass at `target` from a unit mass at every body.
+        let force = |p: Vector3<f64>, mass: f64, target: Vector3<f64>| {
+            let delta = p - target;
+            delta * (mass / delta.norm().powi(3))
+        };
+        let target = Vector3::new(-0.6, -0.5, -0.5);
+        let exact = bodies
+            .iter()
+            .fold(Vector3::zeros(), |total, &p| total + force(p, 1.0, target));
+
+        let mut visits = 0;
+        let mut approx = Vector3::zeros();
+        tree.traverse_theta(region, &sums, target, 0.3, |p, mass| {
+            visits += 1;
+            approx += force(p, mass, target);
+        });
+        assert!(visits < bodies.len());
+        assert!((approx - exact).norm() < 0.05 * exact.norm());
+
+        let mut visits = 0;
+        let mut total = Vector3::zeros();
+        tree.traverse_theta(region, &sums, target, 0.0, |p, mass| {
+            visits += 1;
+            total += force(p, mass, target);
+        });
+        assert_eq!(visits, bodies.len());
+        assert!((total - exact).norm() < 1e-9 * exact.norm());
+    }
+
+    #[test]
+    fn test_eq_ignores_insertion_order() {
+        let mut rng = SmallRng::from_seed([9; 16]);
+        let items: Vec<(u64, usize)> = (0..1000)
+            .map(|i| (rng.gen::<u64>() & u64::used_bits(), i))
+            .collect();
+        let mut forward = PointerOctree::new();
+        forward.extend(items.iter().cloned());
+        let mut reversed = PointerOctree::new();
+        reversed.extend(items.iter().cloned().rev());
+        assert!(forward == reversed);
+
+        reversed.insert(items[0].0, 1000);
+        assert!(forward != reversed);
+        reversed.insert(items[0].0, 0);
+        reversed.remove(items[1].0);
+        assert!(forward != reversed);
+        reversed.insert(items[1].0, 1);
+        assert!(forward == reversed);
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut rng = SmallRng::from_seed([10; 16]);
+        let mut tree = PointerOctree::new();
+        tree.extend((0..1000).map(|i| (rng.gen::<u64>() & u64::used_bits(), i)));
+        // Removing items can leave nodes behind, which must survive the round trip too.
+        let removed: Vec<u64> = tree.iter().map(|(m, _)| m).step_by(3).collect();
+        for m in removed {
+            tree.remove(m);
+        }
+
+        let bytes = bincode::serialize(&tree).unwrap();
+        let decoded: PointerOctree<usize, u64> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.len(), tree.len());
+        assert!(decoded == tree);
+        assert_eq!(
+            decoded.nodes_bfs().collect::<Vec<_>>(),
+            tree.nodes_bfs().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_entry_counts_per_cell() {
+        let mut rng = SmallRng::from_seed([11; 16]);
+        let mut tree = ResizingPointerOctree::<usize, u64, f64>::new(-3, Vector3::zeros());
+        let cells: Vec<Vector3<f64>> = (0..20)
+            .map(|_| {
+                Vector3::new(
+                    rng.gen_range(-4.0, 4.0),
+                    rng.gen_range(-4.0, 4.0),
+                    rng.gen_range(-4.0, 4.0),
+                )
+            })
+            .collect();
+        for i in 0..200 {
+            *tree.entry(cells[i % cells.len()]).or_insert(0) += 1;
+        }
+        assert_eq!(tree.len(), cells.len());
+        for point in &cells {
+            let morton = tree.region.discretize(*point).unwrap();
+            assert_eq!(tree.get(morton), Some(&10));
+        }
+
+        let point = cells[0];
+        assert_eq!(*tree.entry(point).and_modify(|n| *n *= 3).or_insert(0), 30);
+        let empty = Vector3::new(0.5, 0.5, 0.5);
+        assert_eq!(
+            *tree
+                .entry(empty)
+                .and_modify(|n| *n *= 3)
+                .or_insert_with(|| 7),
+            7
+        );
+        assert_eq!(tree.len(), cells.len() + 1);
+
+        // Looking into an empty cell without inserting doesn't record a change for `fold_cached`.
+        let empty = Vector3::new(-0.25, 0.75, 0.25);
+        assert!(tree.get(tree.region.discretize(empty).unwrap()).is_none());
+        let changes = tree.octree.changes.len();
+        tree.entry(empty).and_modify(|n| *n += 1);
+        assert_eq!(tree.octree.changes.len(), changes);
+        assert_eq!(tree.len(), cells.len() + 1);
+    }
+
+    #[test]
+    fn test_resizing_extend() {
+        let mut tree = ResizingPointerOctree::<usize, u64, f64>::new(0, Vector3::zeros());
+        tree.extend(vec![(Vector3::new(0.5, 0.5, 0.5), 0)]);
+        assert_eq!(tree.region.leveled_region.0, 0);
+
+        // Every batch lands further out than the last, so the region has to expand again each time.
+        let mut points = vec![Vector3::new(0.5, 0.5, 0.5)];
+        for batch in 1..6 {
+            let scale = 10f64.powi(batch);
+            let level = tree.region.leveled_region.0;
+            let new: Vec<Vector3<f64>> = (0..10)
+                .map(|i| Vector3::new(scale + i as f64, -scale, scale / 2.0))
+                .collect();
+            let start = points.len();
+            tree.extend(new.iter().cloned().zip(start..));
+            points.extend(new);
+            assert!(tree.region.leveled_region.0 > level);
+        }
+
+        assert_eq!(tree.len(), points.len());
+        for (i, &point) in points.iter().enumerate() {
+            let morton = tree.region.discretize(point).unwrap();
+            assert_eq!(tree.get(morton), Some(&i));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the point must be finite")]
+    fn test_insert_vector_infinite() {
+        let mut tree = ResizingPointerOctree::<usize, u64, f64>::new(0, Vector3::zeros());
+        tree.insert_vector(Vector3::new(std::f64::INFINITY, 0.0, 0.0), 0);
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut rng = SmallRng::from_seed([10; 16]);
+        let mut tree = ResizingPointerOctree::<usize, u64, f64>::new(0, Vector3::zeros());
+        let mut points = vec![];
+        for i in 0..200 {
+            let point = if i < 10 {
+                Vector3::new(
+                    rng.gen_range(3.0, 3.5),
+                    rng.gen_range(-2.0, -1.5),
+                    rng.gen_range(0.0, 0.5),
+                )
+            } else {
+                Vector3::new(
+                    rng.gen_range(-500.0, 500.0),
+                    rng.gen_range(-500.0, 500.0),
+                    rng.gen_range(-500.0, 500.0),
+                )
+            };
+            tree.insert_vector(point, i);
+            points.push(point);
+        }
+        tree.insert_bounds(
+            Vector3::new(3.0, -2.0, 0.0),
+            Vector3::new(3.1, -1.9, 0.1),
+            200,
+        );
+        for &point in &points[10..] {
+            tree.remove(tree.region.discretize(point).unwrap());
+        }
+        let level = tree.region.leveled_region.0;
+        let (_, old_max) = tree.region.bounds();
+        let old_width = (old_max[0] - tree.region.center[0]) * 2.0 / (1u64 << 21) as f64;
+
+        tree.shrink_to_fit();
+        assert!(tree.region.leveled_region.0 < level);
+        assert_eq!(tree.len(), 10);
+        // Every item stays within a leaf cell of where it was inserted.
+        for (morton, &i) in tree.iter() {
+            let center = tree.region.undiscretize(morton);
+            assert!((0..3).all(|axis| (center[axis] - points[i][axis]).abs() <= old_width));
+        }
+        let found: Vec<usize> = tree
+            .query_bounds(
+                Vector3::new(3.05, -1.95, 0.05),
+                Vector3::new(3.05, -1.95, 0.05),
+            )
+            .cloned()
+            .collect();
+        assert_eq!(found, vec![200]);
+
+        // A tree that is already as small as it can be is left alone.
+        let region = tree.region;
+        tree.shrink_to_fit();
+        assert_eq!(tree.region.leveled_region.0, region.leveled_region.0);
+        assert_eq!(tree.region.center, region.center);
+    }
+
+    #[test]
+    fn test_insert_reporting() {
+        let mut tree = ResizingPointerOctree::<usize, u64, f64>::new(0, Vector3::zeros());
+        let mut levels = 0;
+        for i in 0..200 {
+            let point = Vector3::new(i as f64, -(i as f64) / 2.0, 0.0);
+            match tree.insert_reporting(point, i) {
+                InsertOutcome::GrewBy(n) => levels += n,
+                InsertOutcome::Inserted | InsertOutcome::Replaced(_) => {}
+            }
+            assert_eq!(levels as i32, tree.region.leveled_region.0);
+            let morton = tree.region.discretize(point).unwrap();
+            assert_eq!(tree.get(morton), Some(&i));
+        }
+        assert!(levels > 1);
+        assert_eq!(
+            tree.insert_reporting(Vector3::new(0.0, 0.0, 0.0), 200),
+            InsertOutcome::Replaced(0)
+        );
+    }
+
+    #[test]
+    fn test_insert_bounds() {
+        let mut rng = SmallRng::from_seed([8; 16]);
+        let mut tree = ResizingPointerOctree::<usize, u64, f64>::new(0, Vector3::zeros());
+        let mut boxes = vec![];
+        for i in 0..300 {
+            let min = Vector3::new(
+                rng.gen_range(-20.0, 20.0),
+                rng.gen_range(-20.0, 20.0),
+                rng.gen_range(-20.0, 20.0),
+            );
+            let size = rng.gen_range(0.0, 4.0);
+            let max = min.map(|n| n + size);
+            tree.insert_bounds(min, max, i);
+            boxes.push((min, max));
+        }
+
+        // Every stored region contains the whole box of its items, and none of its children do.
+        let leveled_region = tree.region.leveled_region;
+        let center = tree.region.center;
+        for (&region, items) in &tree.bounded {
+            let (low, high) = leveled_region.region_bounds::<f64, u64>(region);
+            for &i in items {
+                let (min, max) = (boxes[i].0 - center, boxes[i].1 - center);
+                assert!((0..3).all(|axis| low[axis] <= min[axis] && max[axis] < high[axis]));
+                if region.level < u64::dim_bits() {
+                    let inside_child = (0..8).any(|octant| {
+                        let (low, high) =
+                            leveled_region.region_bounds::<f64, u64>(region.enter(octant));
+                        (0..3).all(|axis| low[axis] <= min[axis] && max[axis] < high[axis])
+                    });
+                    assert!(!inside_child);
+                }
+            }
+        }
+
+        // Every item whose box touches the query box is found.
+        for _ in 0..50 {
+            let min = Vector3::new(
+                rng.gen_range(-25.0, 25.0),
+                rng.gen_range(-25.0, 25.0),
+                rng.gen_range(-25.0, 25.0),
+            );
+            let max = min.map(|n| n + 2.0);
+            let mut found: Vec<usize> = tree.query_bounds(min, max).cloned().collect();
+            for (i, &(bmin, bmax)) in boxes.iter().enumerate() {
+                if (0..3).all(|axis| bmin[axis] <= max[axis] && min[axis] <= bmax[axis]) {
+                    assert!(found.contains(&i));
+                }
+            }
+            // Only skipping the regions that don't touch the box finds the same items as checking every region.
+            let (qmin, qmax) = (min - center, max - center);
+            let mut brute: Vec<usize> = tree
+                .bounded
+                .iter()
+                .filter(|&(&region, _)| {
+                    let (low, high) = leveled_region.region_bounds::<f64, u64>(region);
+                    (0..3).all(|axis| low[axis] <= qmax[axis] && qmin[axis] <= high[axis])
+                })
+                .flat_map(|(_, items)| items.iter().cloned())
+                .collect();
+            found.sort();
+            brute.sort();
+            assert_eq!(found, brute);
+        }
+        assert_eq!(tree.bounded.values().map(Vec::len).sum::<usize>(), 300);
+    }
+
+    #[test]
+    fn test_insert_bounds_deepest_level() {
+        let mut tree = ResizingPointerOctree::<usize, u64, f64>::new(0, Vector3::zeros());
+        let point = Vector3::new(0.1, 0.1, 0.1);
+        tree.insert_bounds(point, point, 0);
+        assert_eq!(
+            tree.bounded.keys().next().map(|region| region.level),
+            Some(u64::dim_bits())
+        );
+
+        // Every expansion moves the item up to the parent cell, since it can't go any deeper.
+        tree.insert_vector(Vector3::new(100.0, 100.0, 100.0), 1);
+        assert!(tree.region.leveled_region.0 > 1);
+        assert!(tree
+            .bounded
+            .keys()
+            .all(|region| region.level <= u64::dim_bits()));
+        assert_eq!(
+            tree.query_bounds(point, point).cloned().collect::<Vec<_>>(),
+            vec![0]
+        );
+        assert_eq!(
+            tree.query_bounds(
+                Vector3::new(50.0, 50.0, 50.0),
+                Vector3::new(60.0, 60.0, 60.0)
+            )
+            .count(),
+            0
+        );
+
+        let far = tree
+            .region
+            .discretize(Vector3::new(100.0, 100.0, 100.0))
+            .unwrap();
+        tree.remove(far);
+        tree.shrink_to_fit();
+        assert_eq!(
+            tree.query_bounds(point, point).cloned().collect::<Vec<_>>(),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn test_resizing_clear() {
+        let mut tree = ResizingPointerOctree::<usize, u64, f64>::new(0, Vector3::zeros());
+        for i in 0..100 {
+            let corner = Vector3::new(i as f64, 0.0, 0.0);
+            tree.insert_bounds(corner, corner.map(|n| n + 0.5), i);
+            tree.insert_vector(Vector3::new(0.0, i as f64, 0.0), i);
+        }
+        let mut cache = FoldCache::new();
+        tree.fold_cached(&mut cache, &CountFolder);
+        let capacity = tree.bounded.capacity();
+
+        tree.clear(1, Vector3::new(0.5, 0.5, 0.5));
+        assert!(tree.is_empty());
+        assert!(tree.bounded.is_empty());
+        assert!(tree.bounded.capacity() >= capacity);
+        assert_eq!(tree.region.leveled_region.0, 1);
+        assert_eq!(tree.fold_cached(&mut cache, &CountFolder), None);
+
+        tree.insert_vector(Vector3::new(0.0, 0.0, 0.0), 7);
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.fold_cached(&mut cache, &CountFolder), Some(&1));
+    }
+
+    #[test]
+    fn test_linear_round_trip() {
+        let mut rng = SmallRng::from_seed([12; 16]);
+        let mut tree = ResizingPointerOctree::<usize, u64, f64>::new(0, Vector3::zeros());
+        for i in 0..1000 {
+            let point = Vector3::new(
+                rng.gen_range(-100.0, 100.0),
+                rng.gen_range(-100.0, 100.0),
+                rng.gen_range(-100.0, 100.0),
+            );
+            tree.insert_vector(point, i);
+        }
+        let region = tree.region;
+        let items: Vec<(u64, usize)> = tree.leaves().map(|(m, &i)| (m, i)).collect();
+
+        let linear = tree.into_linear();
+        assert_eq!(linear.len(), items.len());
+        let sorted: Vec<(u64, usize)> = linear
+            .iter_explore(|_| true)
+            .map(|(m, &i)| (m, i))
+            .collect();
+        assert_eq!(sorted, items);
+        let point: Vector3<f64> = region.undiscretize(items[0].0);
+        assert_eq!(
+            linear.locate(region.leveled_region, point - region.center),
+            Some((items[0].0, &items[0].1))
+        );
+
+        let pointer = linear.into_pointer();
+        let back: Vec<(u64, usize)> = pointer.leaves().map(|(m, &i)| (m, i)).collect();
+        assert_eq!(back, items);
+        assert!(PointerOctree::<usize, u64>::new().into_linear().is_empty());
+    }
 }